@@ -21,7 +21,7 @@ use notecalc_lib::helper::*;
 use notecalc_lib::units::units::Units;
 use notecalc_lib::{
     Layer, NoteCalcApp, OutputMessage, OutputMessageCommandId, RenderAsciiTextMsg, RenderBuckets,
-    RenderStringMsg, RenderUtf8TextMsg, Variable, MAX_LINE_COUNT,
+    RenderStringMsg, RenderUtf8TextMsg, Variable, MAX_LINE_COUNT, VARIABLE_COUNT,
 };
 
 mod utils;
@@ -101,13 +101,13 @@ impl AppPointers {
     fn mut_vars<'a>(ptr: u32) -> &'a mut [Option<Variable>] {
         let ptr_holder = unsafe { &*(ptr as *const AppPointers) };
         unsafe {
-            &mut (&mut *(ptr_holder.vars_ptr as *mut [Option<Variable>; MAX_LINE_COUNT + 1]))[..]
+            &mut (&mut *(ptr_holder.vars_ptr as *mut [Option<Variable>; VARIABLE_COUNT]))[..]
         }
     }
 
     fn vars<'a>(ptr: u32) -> &'a [Option<Variable>] {
         let ptr_holder = unsafe { &*(ptr as *const AppPointers) };
-        unsafe { &(&*(ptr_holder.vars_ptr as *const [Option<Variable>; MAX_LINE_COUNT + 1]))[..] }
+        unsafe { &(&*(ptr_holder.vars_ptr as *const [Option<Variable>; VARIABLE_COUNT]))[..] }
     }
 
     fn allocator<'a>(ptr: u32) -> &'a Bump {
@@ -1,13 +1,19 @@
+use std::cmp::Ordering;
 use std::ops::BitXor;
 use std::ops::Neg;
 use std::ops::Not;
 
+use crate::functions::FnRegistry;
 use crate::matrix::MatrixData;
-use crate::token_parser::{OperatorTokenType, Token, TokenType};
-use crate::units::consts::EMPTY_UNIT_DIMENSIONS;
+use crate::token_parser::{
+    has_radix_prefix, ConversionKeyword, NumberInputMode, OperatorTokenType, ParseOptions, Token,
+    TokenType,
+};
+use crate::units::consts::{UnitDimensionExponent, EMPTY_UNIT_DIMENSIONS};
 use crate::units::units::UnitOutput;
 use crate::Variables;
 use rust_decimal::prelude::*;
+use rust_decimal::RoundingStrategy;
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct CalcResult {
@@ -72,6 +78,134 @@ impl CalcResult {
     pub fn zero() -> CalcResult {
         CalcResult::new(CalcResultType::Number(Decimal::zero()), 0)
     }
+
+    /// Returns the numeric value as a float and whether precision was lost by the conversion.
+    /// For quantities, the magnitude is expressed in the result's own unit and the unit is dropped
+    /// (e.g. 2 km is 2.0).
+    /// Non numeric results (units, matrices) return None.
+    pub fn to_f64(&self) -> Option<(f64, bool)> {
+        let num = match &self.typ {
+            CalcResultType::Number(num) | CalcResultType::Percentage(num) => num.clone(),
+            CalcResultType::Quantity(num, unit) => unit.from_base_to_this_unit(num)?,
+            CalcResultType::Unit(..) | CalcResultType::Matrix(..) => return None,
+        };
+        let f = num.to_f64()?;
+        let lossy = Decimal::from_f64(f).map(|it| it != num).unwrap_or(true);
+        Some((f, lossy))
+    }
+}
+
+/// How hexadecimal literals are interpreted during evaluation.
+/// The token parser always stores their raw (unsigned) magnitude.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HexSignedness {
+    Unsigned,
+    /// two's complement at the given bit width, e.g. 0xFFFFFFFF is -1 at 32 bits
+    Signed {
+        bit_width: u32,
+    },
+}
+
+impl Default for HexSignedness {
+    fn default() -> Self {
+        HexSignedness::Unsigned
+    }
+}
+
+/// How the arguments of the trigonometric functions are interpreted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AngleMode {
+    Rad,
+    Deg,
+    Grad,
+}
+
+impl Default for AngleMode {
+    fn default() -> Self {
+        AngleMode::Rad
+    }
+}
+
+impl AngleMode {
+    pub fn to_radians(&self, angle: f64) -> f64 {
+        match self {
+            AngleMode::Rad => angle,
+            AngleMode::Deg => angle.to_radians(),
+            AngleMode::Grad => angle * std::f64::consts::PI / 200.0,
+        }
+    }
+}
+
+/// How ties are broken when a result is rounded to a given number of decimal places,
+/// e.g. by `round` or when it is displayed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoundingMode {
+    /// banker's rounding, the default of `rust_decimal`, e.g. round(2.5) = 2
+    HalfEven,
+    /// ties are rounded away from zero, e.g. round(2.5) = 3
+    HalfUp,
+}
+
+impl Default for RoundingMode {
+    fn default() -> Self {
+        RoundingMode::HalfEven
+    }
+}
+
+impl RoundingMode {
+    pub fn round_dp(&self, num: &Decimal, decimal_places: u32) -> Decimal {
+        let strategy = match self {
+            RoundingMode::HalfEven => RoundingStrategy::BankersRounding,
+            RoundingMode::HalfUp => RoundingStrategy::RoundHalfUp,
+        };
+        num.round_dp_with_strategy(decimal_places, strategy)
+    }
+}
+
+/// Settings and host provided extensions used during the evaluation.
+#[derive(Default, Clone)]
+pub struct EvalContext {
+    pub hex_signedness: HexSignedness,
+    pub angle_mode: AngleMode,
+    pub rounding_mode: RoundingMode,
+    pub conversion_keyword: ConversionKeyword,
+    pub number_input_mode: NumberInputMode,
+    pub fns: FnRegistry,
+    /// When set, `sum` and line ranges (`&[1:3]`) ignore the erroneous lines (and `sum` the lines
+    /// which can't be added to it), otherwise such a line turns them into an error.
+    pub skip_errors: bool,
+    /// When set, the lines which define a variable (e.g. `x = 5`) are not added to `sum`
+    /// (nor counted by `avg` and `count`), so helper definitions don't inflate the totals.
+    pub skip_assignments_in_sum: bool,
+}
+
+impl EvalContext {
+    pub fn parse_options(&self) -> ParseOptions {
+        ParseOptions {
+            conversion_keyword: self.conversion_keyword,
+            number_input_mode: self.number_input_mode,
+        }
+    }
+}
+
+impl HexSignedness {
+    fn reinterpret(&self, num: &Decimal) -> Option<Decimal> {
+        match self {
+            HexSignedness::Unsigned => None,
+            HexSignedness::Signed { bit_width } => {
+                if *bit_width == 0 || *bit_width > 64 {
+                    return None;
+                }
+                let range = pow(dec(2), *bit_width as i64)?;
+                let sign_bit = pow(dec(2), *bit_width as i64 - 1)?;
+                if *num >= sign_bit && *num < range {
+                    num.checked_sub(&range)
+                } else {
+                    None
+                }
+            }
+        }
+    }
 }
 
 pub struct EvaluationResult {
@@ -100,6 +234,7 @@ pub fn evaluate_tokens<'text_ptr>(
     tokens: &mut [Token<'text_ptr>],
     shunting_tokens: &mut Vec<ShuntingYardResult>,
     variables: &Variables,
+    ctx: &EvalContext,
 ) -> Result<Option<EvaluationResult>, ()> {
     let mut stack: Vec<CalcResult> = vec![];
     let mut there_was_unit_conversion = false;
@@ -108,10 +243,23 @@ pub fn evaluate_tokens<'text_ptr>(
 
     for token in shunting_tokens.iter_mut() {
         match &token.typ {
-            TokenType::NumberLiteral(num) => stack.push(CalcResult::new(
-                CalcResultType::Number(num.clone()),
-                token.index_into_tokens,
-            )),
+            TokenType::NumberLiteral(num) => {
+                let is_hex = tokens
+                    .get(token.index_into_tokens)
+                    .map(|it| has_radix_prefix(it.ptr, 'x'))
+                    .unwrap_or(false);
+                let num = if is_hex {
+                    ctx.hex_signedness
+                        .reinterpret(num)
+                        .unwrap_or_else(|| num.clone())
+                } else {
+                    num.clone()
+                };
+                stack.push(CalcResult::new(
+                    CalcResultType::Number(num),
+                    token.index_into_tokens,
+                ))
+            }
             TokenType::NumberErr => {
                 return Err(());
             }
@@ -127,7 +275,7 @@ pub fn evaluate_tokens<'text_ptr>(
                     assignment = true;
                     continue;
                 }
-                if apply_operation(tokens, &mut stack, &typ, token.index_into_tokens) == true {
+                if apply_operation(tokens, &mut stack, &typ, token.index_into_tokens, ctx) == true {
                     if matches!(typ, OperatorTokenType::UnitConverter) {
                         there_was_unit_conversion = true;
                     }
@@ -138,6 +286,28 @@ pub fn evaluate_tokens<'text_ptr>(
                     return Err(());
                 }
             }
+            TokenType::LineRange {
+                from_var_index,
+                to_var_index,
+            } => {
+                let mut cells = Vec::with_capacity(to_var_index - from_var_index + 1);
+                // the lines without results (e.g. texts, blank lines or comments) are skipped
+                for var in variables[*from_var_index..=*to_var_index].iter().flatten() {
+                    match &var.value {
+                        Ok(value) => cells.push(value.clone()),
+                        Err(_) if var.placeholder || ctx.skip_errors => {}
+                        Err(_) => return Err(()),
+                    }
+                }
+                let row_count = cells.len();
+                if row_count == 0 {
+                    return Err(());
+                }
+                stack.push(CalcResult::new(
+                    CalcResultType::Matrix(MatrixData::new(cells, row_count, 1)),
+                    token.index_into_tokens,
+                ));
+            }
             TokenType::StringLiteral | TokenType::Header => panic!(),
             TokenType::Variable { var_index } | TokenType::LineReference { var_index } => {
                 // TODO clone :(
@@ -181,6 +351,7 @@ fn apply_operation<'text_ptr>(
     stack: &mut Vec<CalcResult>,
     op: &OperatorTokenType,
     op_token_index: usize,
+    ctx: &EvalContext,
 ) -> bool {
     let succeed = match &op {
         OperatorTokenType::Mult
@@ -193,6 +364,17 @@ fn apply_operation<'text_ptr>(
         | OperatorTokenType::Pow
         | OperatorTokenType::ShiftLeft
         | OperatorTokenType::ShiftRight
+        | OperatorTokenType::PercentOf
+        | OperatorTokenType::Modulo
+        | OperatorTokenType::Mod
+        | OperatorTokenType::IncreasedBy
+        | OperatorTokenType::DecreasedBy
+        | OperatorTokenType::And
+        | OperatorTokenType::Or
+        | OperatorTokenType::Greater
+        | OperatorTokenType::Less
+        | OperatorTokenType::GreaterOrEq
+        | OperatorTokenType::LessOrEq
         | OperatorTokenType::UnitConverter => {
             if stack.len() > 1 {
                 let (lhs, rhs) = (&stack[stack.len() - 2], &stack[stack.len() - 1]);
@@ -213,7 +395,9 @@ fn apply_operation<'text_ptr>(
         OperatorTokenType::UnaryMinus
         | OperatorTokenType::UnaryPlus
         | OperatorTokenType::Perc
-        | OperatorTokenType::BinNot => {
+        | OperatorTokenType::BinNot
+        | OperatorTokenType::Not
+        | OperatorTokenType::Sqrt => {
             let maybe_top = stack.last();
             if let Some(result) =
                 maybe_top.and_then(|top| unary_operation(&op, top, op_token_index))
@@ -242,7 +426,7 @@ fn apply_operation<'text_ptr>(
             }
         }
         OperatorTokenType::Fn { arg_count, typ } => {
-            typ.execute(*arg_count, stack, op_token_index, tokens)
+            typ.execute(*arg_count, stack, op_token_index, tokens, ctx)
         }
         OperatorTokenType::Semicolon | OperatorTokenType::Comma => {
             // ignore
@@ -252,7 +436,8 @@ fn apply_operation<'text_ptr>(
         OperatorTokenType::ParenOpen
         | OperatorTokenType::ParenClose
         | OperatorTokenType::BracketOpen
-        | OperatorTokenType::BracketClose => {
+        | OperatorTokenType::BracketClose
+        | OperatorTokenType::AbsBar => {
             // this branch was executed during fuzz testing, don't panic here
             // check test_panic_fuzz_3
             return false;
@@ -279,6 +464,10 @@ fn apply_operation<'text_ptr>(
 
 fn unit_conversion(top: &CalcResult, target_unit: &UnitOutput) -> Option<CalcResult> {
     match &top.typ {
+        CalcResultType::Matrix(mat) => {
+            // [1, 2] kg
+            mat.map_cells(|cell| unit_conversion(cell, target_unit))
+        }
         CalcResultType::Number(num) => {
             let norm = target_unit.normalize(num);
             if target_unit.dimensions == EMPTY_UNIT_DIMENSIONS {
@@ -304,6 +493,11 @@ fn unary_operation(
         OperatorTokenType::UnaryMinus => unary_minus_op(top),
         OperatorTokenType::Perc => percentage_operator(top, op_token_index),
         OperatorTokenType::BinNot => binary_complement(top),
+        OperatorTokenType::Sqrt => sqrt_op(top),
+        OperatorTokenType::Not => {
+            let value = as_bool(top)?;
+            Some(CalcResult::new(bool_result(!value), top.index_into_tokens))
+        }
         _ => None,
     };
 }
@@ -316,21 +510,62 @@ fn binary_operation(
     let result = match &op {
         OperatorTokenType::Mult => multiply_op(lhs, rhs),
         OperatorTokenType::Div => divide_op(lhs, rhs),
-        OperatorTokenType::Add => add_op(lhs, rhs),
-        OperatorTokenType::Sub => sub_op(lhs, rhs),
+        OperatorTokenType::Add | OperatorTokenType::IncreasedBy => add_op(lhs, rhs),
+        OperatorTokenType::Sub | OperatorTokenType::DecreasedBy => sub_op(lhs, rhs),
         OperatorTokenType::BinAnd => binary_and_op(lhs, rhs),
         OperatorTokenType::BinOr => binary_or_op(lhs, rhs),
         OperatorTokenType::BinXor => binary_xor_op(lhs, rhs),
+        OperatorTokenType::And => Some(CalcResult::new(
+            bool_result(as_bool(lhs)? && as_bool(rhs)?),
+            lhs.index_into_tokens,
+        )),
+        OperatorTokenType::Or => Some(CalcResult::new(
+            bool_result(as_bool(lhs)? || as_bool(rhs)?),
+            lhs.index_into_tokens,
+        )),
         OperatorTokenType::Pow => pow_op(lhs, rhs),
         OperatorTokenType::ShiftLeft => binary_shift_left(lhs, rhs),
         OperatorTokenType::ShiftRight => binary_shift_right(lhs, rhs),
+        OperatorTokenType::PercentOf => percent_of_op(lhs, rhs),
+        OperatorTokenType::Modulo => modulo_op(lhs, rhs, false),
+        OperatorTokenType::Mod => modulo_op(lhs, rhs, true),
+        OperatorTokenType::Greater => compare_op(lhs, rhs, |it| it == Ordering::Greater),
+        OperatorTokenType::Less => compare_op(lhs, rhs, |it| it == Ordering::Less),
+        OperatorTokenType::GreaterOrEq => compare_op(lhs, rhs, |it| it != Ordering::Less),
+        OperatorTokenType::LessOrEq => compare_op(lhs, rhs, |it| it != Ordering::Greater),
         OperatorTokenType::UnitConverter => {
             return match (&lhs.typ, &rhs.typ) {
+                // "in number", the unit is stripped if the value is dimensionless
+                (CalcResultType::Number(num), CalcResultType::Unit(target_unit))
+                    if target_unit.units.is_empty() =>
+                {
+                    Some(CalcResult::new(CalcResultType::Number(num.clone()), 0))
+                }
+                (CalcResultType::Quantity(num, source_unit), CalcResultType::Unit(target_unit))
+                    if target_unit.units.is_empty() =>
+                {
+                    // ratios (e.g. ppm) are stored in their base value
+                    if source_unit.is_unitless() {
+                        Some(CalcResult::new(CalcResultType::Number(num.clone()), 0))
+                    } else {
+                        None
+                    }
+                }
+                (CalcResultType::Percentage(num), CalcResultType::Unit(target_unit))
+                    if target_unit.units.is_empty() =>
+                {
+                    Some(CalcResult::new(
+                        CalcResultType::Number(num.checked_div(&DECIMAL_100)?),
+                        0,
+                    ))
+                }
                 (
                     CalcResultType::Quantity(lhs_num, source_unit),
                     CalcResultType::Unit(target_unit),
                 ) => {
                     if source_unit == target_unit {
+                        // e.g. "1e25 km in nm" does not fit into a Decimal
+                        target_unit.from_base_to_this_unit(lhs_num)?;
                         Some(CalcResult::new(
                             CalcResultType::Quantity(lhs_num.clone(), target_unit.clone()),
                             0,
@@ -339,6 +574,43 @@ fn binary_operation(
                         None
                     }
                 }
+                (CalcResultType::Number(num), CalcResultType::Unit(target_unit))
+                    if target_unit.is_unitless() =>
+                {
+                    // ratios, e.g. "5000 ppm in %", the lhs was already normalized into a number
+                    Some(CalcResult::new(
+                        CalcResultType::Quantity(num.clone(), target_unit.clone()),
+                        0,
+                    ))
+                }
+                (CalcResultType::Percentage(num), CalcResultType::Unit(target_unit))
+                    if target_unit.is_unitless() =>
+                {
+                    // "1 % in ppm"
+                    let ratio = num.checked_div(&DECIMAL_100)?;
+                    Some(CalcResult::new(
+                        CalcResultType::Quantity(ratio, target_unit.clone()),
+                        0,
+                    ))
+                }
+                (lhs_typ, CalcResultType::Quantity(target_num, target_unit))
+                    if matches!(
+                        lhs_typ,
+                        CalcResultType::Quantity(..) | CalcResultType::Matrix(..)
+                    ) =>
+                {
+                    // the target is a variable holding a unit (e.g. "1 m"),
+                    // only its unit is used, so its magnitude must be 1
+                    if target_unit.from_base_to_this_unit(target_num) != Some(Decimal::one()) {
+                        None
+                    } else {
+                        binary_operation(
+                            op,
+                            lhs,
+                            &CalcResult::new(CalcResultType::Unit(target_unit.clone()), 0),
+                        )
+                    }
+                }
                 (CalcResultType::Matrix(mat), CalcResultType::Unit(..)) => {
                     let cells: Option<Vec<CalcResult>> = mat
                         .cells
@@ -366,6 +638,104 @@ fn binary_operation(
     result
 }
 
+/// Compares numbers, percentages or quantities of the same dimension
+/// (quantities are stored in their base units, so 1 m > 50 cm).
+pub fn compare(lhs: &CalcResult, rhs: &CalcResult) -> Option<Ordering> {
+    match (&lhs.typ, &rhs.typ) {
+        (CalcResultType::Number(lhs_num), CalcResultType::Number(rhs_num))
+        | (CalcResultType::Percentage(lhs_num), CalcResultType::Percentage(rhs_num)) => {
+            Some(lhs_num.cmp(rhs_num))
+        }
+        (
+            CalcResultType::Quantity(lhs_num, lhs_unit),
+            CalcResultType::Quantity(rhs_num, rhs_unit),
+        ) => {
+            if lhs_unit == rhs_unit {
+                Some(lhs_num.cmp(rhs_num))
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// the result is 1 if the comparison holds, 0 otherwise
+fn compare_op(
+    lhs: &CalcResult,
+    rhs: &CalcResult,
+    holds: impl Fn(Ordering) -> bool,
+) -> Option<CalcResult> {
+    let ordering = compare(lhs, rhs)?;
+    let result = if holds(ordering) {
+        Decimal::one()
+    } else {
+        Decimal::zero()
+    };
+    Some(CalcResult::new(
+        CalcResultType::Number(result),
+        lhs.index_into_tokens,
+    ))
+}
+
+/// If `floored` is true, the sign of the result follows the divisor (`mod`),
+/// otherwise the dividend (`rem` and `%`).
+fn modulo_op(lhs: &CalcResult, rhs: &CalcResult, floored: bool) -> Option<CalcResult> {
+    fn modulo(lhs: &Decimal, rhs: &Decimal, floored: bool) -> Option<Decimal> {
+        if rhs.is_zero() {
+            return None;
+        }
+        let mut result = lhs % rhs;
+        if floored && !result.is_zero() && result.is_sign_negative() != rhs.is_sign_negative() {
+            result = result.checked_add(rhs)?;
+        }
+        Some(result)
+    }
+    match (&lhs.typ, &rhs.typ) {
+        (CalcResultType::Number(lhs_num), CalcResultType::Number(rhs_num)) => {
+            Some(CalcResult::new(
+                CalcResultType::Number(modulo(lhs_num, rhs_num, floored)?),
+                lhs.index_into_tokens,
+            ))
+        }
+        (
+            CalcResultType::Quantity(lhs_num, lhs_unit),
+            CalcResultType::Quantity(rhs_num, rhs_unit),
+        ) => {
+            // e.g. 90 min mod 1 h, both are stored in the base unit, the result has the left's unit
+            if lhs_unit != rhs_unit {
+                None
+            } else {
+                Some(CalcResult::new(
+                    CalcResultType::Quantity(modulo(lhs_num, rhs_num, floored)?, lhs_unit.clone()),
+                    lhs.index_into_tokens,
+                ))
+            }
+        }
+        _ => None,
+    }
+}
+
+fn percent_of_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
+    match (&lhs.typ, &rhs.typ) {
+        (CalcResultType::Percentage(lhs), CalcResultType::Number(rhs)) => {
+            // 10% of 50
+            Some(CalcResult::new(
+                CalcResultType::Number(percentage_of(lhs, rhs)?),
+                0,
+            ))
+        }
+        (CalcResultType::Percentage(lhs), CalcResultType::Quantity(rhs, rhs_unit)) => {
+            // 10% of 50 kg
+            Some(CalcResult::new(
+                CalcResultType::Quantity(percentage_of(lhs, rhs)?, rhs_unit.clone()),
+                0,
+            ))
+        }
+        _ => None,
+    }
+}
+
 fn percentage_operator(lhs: &CalcResult, op_token_index: usize) -> Option<CalcResult> {
     match &lhs.typ {
         CalcResultType::Number(lhs_num) => {
@@ -380,6 +750,36 @@ fn percentage_operator(lhs: &CalcResult, op_token_index: usize) -> Option<CalcRe
     }
 }
 
+pub fn sqrt_op(lhs: &CalcResult) -> Option<CalcResult> {
+    match &lhs.typ {
+        CalcResultType::Number(lhs_num) => Some(CalcResult::new(
+            CalcResultType::Number(sqrt(lhs_num)?),
+            lhs.index_into_tokens,
+        )),
+        CalcResultType::Quantity(num, unit) => Some(CalcResult::new(
+            CalcResultType::Quantity(sqrt(num)?, unit.root(2)?),
+            lhs.index_into_tokens,
+        )),
+        _ => None,
+    }
+}
+
+/// Only numbers have a truth value, any non-zero number is true
+fn as_bool(value: &CalcResult) -> Option<bool> {
+    match &value.typ {
+        CalcResultType::Number(num) => Some(!num.is_zero()),
+        _ => None,
+    }
+}
+
+pub fn bool_result(value: bool) -> CalcResultType {
+    CalcResultType::Number(if value {
+        Decimal::one()
+    } else {
+        Decimal::zero()
+    })
+}
+
 fn binary_complement(lhs: &CalcResult) -> Option<CalcResult> {
     match &lhs.typ {
         CalcResultType::Number(lhs_num) => {
@@ -497,12 +897,16 @@ fn unary_minus_op(lhs: &CalcResult) -> Option<CalcResult> {
     }
 }
 
-fn pow_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
+/// The largest denominator of a fractional exponent applied to a unit, e.g. (8 m^3)^(1/3).
+/// The root of the resulting unit still has to be a divisor of a power of 10 (see `UnitOutput::root`)
+const MAX_UNIT_ROOT: i64 = 9;
+
+pub fn pow_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
     match (&lhs.typ, &rhs.typ) {
         //////////////
         // 1^x
         //////////////
-        (CalcResultType::Number(lhs), CalcResultType::Number(rhs)) => {
+        (CalcResultType::Number(lhs), CalcResultType::Number(rhs)) if rhs.trunc() == *rhs => {
             // 2^3
             rhs.to_i64()
                 .and_then(|rhs| {
@@ -511,7 +915,27 @@ fn pow_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
                 })
                 .map(|pow| CalcResult::new(CalcResultType::Number(pow), 0))
         }
-        (CalcResultType::Quantity(lhs, lhs_unit), CalcResultType::Number(rhs)) => {
+        (CalcResultType::Number(lhs), CalcResultType::Number(rhs)) => {
+            // 4^0.5, it is not exact so only positive bases are allowed
+            if lhs.is_sign_negative() {
+                return None;
+            }
+            let result = lhs.to_f64()?.powf(rhs.to_f64()?);
+            if !result.is_finite() {
+                return None;
+            }
+            let mut result = Decimal::from_f64(result)?;
+            // get rid of the floating point noise
+            result.rescale(16);
+            Some(CalcResult::new(
+                CalcResultType::Number(result.normalize()),
+                0,
+            ))
+        }
+        (CalcResultType::Quantity(lhs, lhs_unit), CalcResultType::Number(rhs))
+            if rhs.trunc() == *rhs =>
+        {
+            // (2 m)^3
             let p = rhs.to_i64()?;
             let num_powered = pow(lhs.clone(), p)?;
             let unit_powered = lhs_unit.pow(p);
@@ -520,6 +944,41 @@ fn pow_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
                 0,
             ))
         }
+        (CalcResultType::Quantity(lhs_num, lhs_unit), CalcResultType::Number(rhs)) => {
+            // (4 m^2)^0.5, the exponent of the unit must be a fraction with a small denominator
+            // (e.g. 1/2, 2/3), which is represented by the root of the unit
+            let (p, q) = (2..=MAX_UNIT_ROOT).find_map(|q| {
+                let p = rhs.checked_mul(&dec(q))?;
+                let rounded = p.round();
+                if (p - rounded).abs() < Decimal::new(1, 20) {
+                    Some((rounded.to_i64()?, q))
+                } else {
+                    None
+                }
+            })?;
+            let unit = lhs_unit.pow(p)?.root(q as UnitDimensionExponent)?;
+            let num = pow_op(
+                &CalcResult::new(CalcResultType::Number(lhs_num.clone()), 0),
+                rhs,
+            )?;
+            match num.typ {
+                CalcResultType::Number(num) => {
+                    Some(CalcResult::new(CalcResultType::Quantity(num, unit), 0))
+                }
+                _ => None,
+            }
+        }
+        (CalcResultType::Matrix(mat), CalcResultType::Number(..)) => {
+            // elementwise
+            let cells: Option<Vec<CalcResult>> =
+                mat.cells.iter().map(|cell| pow_op(cell, rhs)).collect();
+            cells.map(|it| {
+                CalcResult::new(
+                    CalcResultType::Matrix(MatrixData::new(it, mat.row_count, mat.col_count)),
+                    0,
+                )
+            })
+        }
         _ => None,
     }
 }
@@ -570,7 +1029,7 @@ pub fn multiply_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
             CalcResultType::Quantity(rhs_num, rhs_unit),
         ) => {
             // 2s * 3s
-            let new_unit = lhs_unit * rhs_unit;
+            let new_unit = (lhs_unit * rhs_unit)?;
             if new_unit.is_unitless() {
                 if let Some(lhs_num) = lhs_unit.from_base_to_this_unit(lhs_num) {
                     if let Some(rhs_num) = rhs_unit.from_base_to_this_unit(rhs_num) {
@@ -615,13 +1074,9 @@ pub fn multiply_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
             ))
         }
         (CalcResultType::Percentage(lhs), CalcResultType::Percentage(rhs)) => {
-            // 50% * 50%
-
+            // 50% * 50% = 25%, it compounds
             Some(CalcResult::new(
-                CalcResultType::Percentage(
-                    (lhs.checked_div(&DECIMAL_100)?)
-                        .checked_mul(&rhs.checked_div(&DECIMAL_100)?)?,
-                ),
+                CalcResultType::Percentage(lhs.checked_mul(&rhs.checked_div(&DECIMAL_100)?)?),
                 0,
             ))
         }
@@ -709,7 +1164,10 @@ pub fn add_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
                 0,
             ))
         }
-        (CalcResultType::Number(_lhs), CalcResultType::Matrix(..)) => None,
+        (CalcResultType::Number(..), CalcResultType::Matrix(mat)) => {
+            // 10 + [1, 2], elementwise
+            mat.map_cells(|cell| add_op(lhs, cell))
+        }
         //////////////
         // 12km + x
         //////////////
@@ -734,11 +1192,16 @@ pub fn add_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
                 .checked_div(&DECIMAL_100)
                 .and_then(|it| it.checked_mul(rhs))?;
             Some(CalcResult::new(
-                CalcResultType::Quantity(lhs + x_percent_of_left_hand_side, lhs_unit.clone()),
+                CalcResultType::Quantity(
+                    lhs.checked_add(&x_percent_of_left_hand_side)?,
+                    lhs_unit.clone(),
+                ),
                 0,
             ))
         }
-        (CalcResultType::Quantity(..), CalcResultType::Matrix(..)) => None,
+        (CalcResultType::Quantity(..), CalcResultType::Matrix(mat)) => {
+            mat.map_cells(|cell| add_op(lhs, cell))
+        }
         //////////////
         // 12% + x
         //////////////
@@ -751,16 +1214,22 @@ pub fn add_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
             None
         }
         (CalcResultType::Percentage(lhs), CalcResultType::Percentage(rhs)) => {
-            // 50% + 50%
-            Some(CalcResult::new(CalcResultType::Percentage(lhs + rhs), 0))
+            // 50% + 50%, in percentage points
+            Some(CalcResult::new(
+                CalcResultType::Percentage(lhs.checked_add(&rhs)?),
+                0,
+            ))
         }
         (CalcResultType::Percentage(..), CalcResultType::Matrix(..)) => None,
         ///////////
         // Matrix
         //////////
-        (CalcResultType::Matrix(..), CalcResultType::Number(..)) => None,
-        (CalcResultType::Matrix(..), CalcResultType::Quantity(..)) => None,
-        (CalcResultType::Matrix(..), CalcResultType::Percentage(..)) => None,
+        (CalcResultType::Matrix(mat), CalcResultType::Number(..))
+        | (CalcResultType::Matrix(mat), CalcResultType::Quantity(..))
+        | (CalcResultType::Matrix(mat), CalcResultType::Percentage(..)) => {
+            // [1, 2] + 10, elementwise
+            mat.map_cells(|cell| add_op(cell, rhs))
+        }
         (CalcResultType::Matrix(lhs), CalcResultType::Matrix(rhs)) => {
             if lhs.row_count != rhs.row_count || lhs.col_count != rhs.col_count {
                 return None;
@@ -781,7 +1250,7 @@ pub fn add_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
     }
 }
 
-fn sub_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
+pub fn sub_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
     match (&lhs.typ, &rhs.typ) {
         (CalcResultType::Unit(..), CalcResultType::Unit(..))
         | (CalcResultType::Unit(..), CalcResultType::Number(..))
@@ -816,7 +1285,10 @@ fn sub_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
                 0,
             ))
         }
-        (CalcResultType::Number(..), CalcResultType::Matrix(..)) => None,
+        (CalcResultType::Number(..), CalcResultType::Matrix(mat)) => {
+            // 10 - [1, 2], elementwise
+            mat.map_cells(|cell| sub_op(lhs, cell))
+        }
         //////////////
         // 12km - x
         //////////////
@@ -848,7 +1320,9 @@ fn sub_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
                 0,
             ))
         }
-        (CalcResultType::Quantity(..), CalcResultType::Matrix(..)) => None,
+        (CalcResultType::Quantity(..), CalcResultType::Matrix(mat)) => {
+            mat.map_cells(|cell| sub_op(lhs, cell))
+        }
         //////////////
         // 12% - x
         //////////////
@@ -861,7 +1335,7 @@ fn sub_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
             None
         }
         (CalcResultType::Percentage(lhs), CalcResultType::Percentage(rhs)) => {
-            // 50% - 50%
+            // 50% - 50%, in percentage points
             Some(CalcResult::new(
                 CalcResultType::Percentage(lhs.checked_sub(&rhs)?),
                 0,
@@ -871,9 +1345,12 @@ fn sub_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
         ///////////
         // Matrix
         //////////
-        (CalcResultType::Matrix(..), CalcResultType::Number(..)) => None,
-        (CalcResultType::Matrix(..), CalcResultType::Quantity(..)) => None,
-        (CalcResultType::Matrix(..), CalcResultType::Percentage(..)) => None,
+        (CalcResultType::Matrix(mat), CalcResultType::Number(..))
+        | (CalcResultType::Matrix(mat), CalcResultType::Quantity(..))
+        | (CalcResultType::Matrix(mat), CalcResultType::Percentage(..)) => {
+            // [1, 2] - 10, elementwise
+            mat.map_cells(|cell| sub_op(cell, rhs))
+        }
         (CalcResultType::Matrix(lhs), CalcResultType::Matrix(rhs)) => {
             if lhs.row_count != rhs.row_count || lhs.col_count != rhs.col_count {
                 return None;
@@ -906,7 +1383,7 @@ pub fn divide_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
         // 12 / year
         //////////////
         (CalcResultType::Quantity(lhs_num, lhs_unit), CalcResultType::Unit(rhs_unit)) => {
-            let new_unit = lhs_unit / rhs_unit;
+            let new_unit = (lhs_unit / rhs_unit)?;
             if new_unit.is_unitless() {
                 if let Some(lhs_num) = lhs_unit.from_base_to_this_unit(lhs_num) {
                     Some(CalcResult::new(CalcResultType::Number(lhs_num), 0))
@@ -993,7 +1470,7 @@ pub fn divide_op(lhs: &CalcResult, rhs: &CalcResult) -> Option<CalcResult> {
                 return None;
             }
             Some(CalcResult::new(
-                CalcResultType::Quantity(lhs / rhs, lhs_unit / rhs_unit),
+                CalcResultType::Quantity(lhs / rhs, (lhs_unit / rhs_unit)?),
                 0,
             ))
         }
@@ -1065,6 +1542,31 @@ pub fn pow(this: Decimal, mut exp: i64) -> Option<Decimal> {
     })
 }
 
+pub fn sqrt(num: &Decimal) -> Option<Decimal> {
+    if num.is_sign_negative() && !num.is_zero() {
+        return None;
+    } else if num.is_zero() {
+        return Some(Decimal::zero());
+    }
+    let two = dec(2);
+    // Newton's method
+    let mut result = if *num > Decimal::one() {
+        num.checked_div(&two)?
+    } else {
+        Decimal::one()
+    };
+    for _ in 0..100 {
+        let next = result
+            .checked_add(&num.checked_div(&result)?)?
+            .checked_div(&two)?;
+        if next == result {
+            break;
+        }
+        result = next;
+    }
+    Some(result)
+}
+
 pub fn dec(num: i64) -> Decimal {
     Decimal::from_i64(num).unwrap()
 }
@@ -1084,10 +1586,14 @@ mod tests {
     use crate::{ResultFormat, Variable, Variables};
     use std::str::FromStr;
 
-    use crate::calc::{CalcResult, CalcResultType, EvaluationResult};
-    use crate::functions::FnType;
+    use crate::calc::{
+        dec, evaluate_tokens, AngleMode, CalcResult, CalcResultType, EvalContext, EvaluationResult,
+        HexSignedness, RoundingMode,
+    };
+    use crate::functions::{FnRegistry, FnType};
     use crate::helper::create_vars;
-    use crate::renderer::render_result;
+    use crate::matrix::MatrixData;
+    use crate::renderer::{format_result, render_result, RenderConfig, UnitSystem};
     use crate::token_parser::{OperatorTokenType, Token};
     use bumpalo::Bump;
     use rust_decimal::prelude::*;
@@ -1109,12 +1615,27 @@ mod tests {
             &vars,
             &arena,
         );
-        let _result_stack = crate::calc::evaluate_tokens(&mut tokens, &mut shunting_output, &vars);
+        let _result_stack = crate::calc::evaluate_tokens(
+            &mut tokens,
+            &mut shunting_output,
+            &vars,
+            &EvalContext::default(),
+        );
 
         crate::shunting_yard::tests::compare_tokens(expected_tokens, &tokens);
     }
 
     fn test_vars(vars: &Variables, text: &str, expected: &str, dec_count: usize) {
+        test_vars_with_ctx(vars, text, expected, dec_count, &EvalContext::default());
+    }
+
+    fn test_vars_with_ctx(
+        vars: &Variables,
+        text: &str,
+        expected: &str,
+        dec_count: usize,
+        ctx: &EvalContext,
+    ) {
         dbg!("===========================================================");
         dbg!(text);
         let temp = text.chars().collect::<Vec<char>>();
@@ -1123,10 +1644,16 @@ mod tests {
 
         let mut tokens = vec![];
         let arena = Bump::new();
-        let mut shunting_output =
-            crate::shunting_yard::tests::do_shunting_yard(&temp, &units, &mut tokens, vars, &arena);
+        let mut shunting_output = crate::shunting_yard::tests::do_shunting_yard_with_fns(
+            &temp,
+            &units,
+            &mut tokens,
+            vars,
+            &ctx.fns,
+            &arena,
+        );
 
-        let result = crate::calc::evaluate_tokens(&mut tokens, &mut shunting_output, vars);
+        let result = crate::calc::evaluate_tokens(&mut tokens, &mut shunting_output, vars, ctx);
 
         if let Err(..) = &result {
             assert_eq!("Err", expected);
@@ -1148,6 +1675,7 @@ mod tests {
                     &ResultFormat::Dec,
                     *there_was_unit_conversion,
                     Some(dec_count),
+                    ctx.rounding_mode,
                     false,
                 ),
                 expected
@@ -1162,6 +1690,7 @@ mod tests {
                         &ResultFormat::Dec,
                         false,
                         Some(dec_count),
+                        ctx.rounding_mode,
                         false
                     ))
                     .unwrap_or(" ".to_string()),
@@ -1178,6 +1707,31 @@ mod tests {
         test_vars(&create_vars(), text, expected, dec_count);
     }
 
+    /// the result of a line which must not fail, e.g. to render it with different settings
+    fn eval_to_result(text: &str) -> CalcResult {
+        let temp = text.chars().collect::<Vec<char>>();
+        let units = Units::new();
+        let vars = create_vars();
+        let mut tokens = vec![];
+        let arena = Bump::new();
+        let mut shunting_output = crate::shunting_yard::tests::do_shunting_yard(
+            &temp,
+            &units,
+            &mut tokens,
+            &vars,
+            &arena,
+        );
+        evaluate_tokens(
+            &mut tokens,
+            &mut shunting_output,
+            &vars,
+            &EvalContext::default(),
+        )
+        .unwrap()
+        .unwrap()
+        .result
+    }
+
     #[test]
     fn calc_tests() {
         test("2^-2", "0.25");
@@ -1269,6 +1823,35 @@ mod tests {
         test("2.3e4e5 + 0", "23000");
     }
 
+    #[test]
+    fn test_percentage_with_percentage() {
+        test("20% + 5%", "25 %");
+        test("20% - 5%", "15 %");
+        test("20% * 50%", "10 %");
+        test("50% * 50%", "25 %");
+    }
+
+    #[test]
+    fn test_percentage_inside_parenthesis() {
+        test("1 + 5%", "1.05");
+        test("(1 + 5%) * 100", "105");
+        test("(1 - 10%) * 200", "180");
+        test("100 * (1 + 5%)", "105");
+    }
+
+    #[test]
+    fn test_ratio_units() {
+        test("5000 ppm in %", "0.5 %");
+        test("1 % in ppm", "10000 ppm");
+        test("1 ‰ in ppm", "1000 ppm");
+        test("2 ppb in ppm", "0.002 ppm");
+        test("5000 ppm", "0.005");
+        test("5000 ppm * 100", "0.5");
+        // % remains the percentage operator
+        test("200 + 10%", "220");
+        test("5000 ppm in m", "Err");
+    }
+
     #[test]
     fn test_percentages() {
         test("200 km/h * 10%", "20 km / h");
@@ -1347,6 +1930,11 @@ mod tests {
         test("3M + k", "3000000");
 
         test("2kalap * 1", "2");
+
+        test("1.5M * 1", "1500000");
+        test("1e3k * 1", "1000");
+        test("3e-3M * 1", "0.003");
+        test("2ke3 * 1", "2000");
     }
 
     #[test]
@@ -1447,8 +2035,8 @@ mod tests {
         );
         test("1 + [2,]", "1");
 
-        // multiply operator must be explicit, "5" is ignored here
-        test("5(1+2)", "3");
+        // implicit multiplication
+        test("5(1+2)", "15");
 
         // invalid
         test("[[2 * 1]]", "[2]");
@@ -1540,6 +2128,16 @@ mod tests {
         test("[2 km] - [3]", "Err");
     }
 
+    #[test]
+    fn test_negative_matrix_elements() {
+        test("[-1,-2;-3,-4]", "[-1, -2; -3, -4]");
+        test("[-1, -2; -3, -4]", "[-1, -2; -3, -4]");
+        test("[1, -2; -3, 4]", "[1, -2; -3, 4]");
+        test("[-1.5; -2]", "[-1.5; -2]");
+        test("[-1,-2;-3,-4] * [-1; 1]", "[-1; -1]");
+        test("nth([-5, -6], 1)", "-6");
+    }
+
     #[test]
     fn test_matrix_scalar_mult() {
         test("3 * [2]", "[6]");
@@ -1593,6 +2191,8 @@ mod tests {
         test("[3m] * [2cm]", "[0.06 m^2]");
 
         test("[2,3] * [4]", "Err");
+        test("[1,000, 2,000] * 2", "[2000, 4000]");
+        test("1,000 + 2,000", "3000");
     }
 
     #[test]
@@ -1678,6 +2278,7 @@ mod tests {
                 CalcResultType::Number(Decimal::from_str("12").unwrap()),
                 0,
             )),
+            placeholder: false,
         });
         test_vars(&vars, "var * 2", "24", 0);
         test_vars(&vars, "var - var", "0", 0);
@@ -1767,6 +2368,229 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_func_row_and_col() {
+        test("row([1, 2; 3, 4], 0)", "[1, 2]");
+        test("row([1, 2; 3, 4], 1)", "[3, 4]");
+        test("col([1, 2; 3, 4], 1)", "[2; 4]");
+        test("col([1, 2, 3], 2)", "[3]");
+    }
+
+    #[test]
+    fn test_out_of_index_row_and_col() {
+        test("row([1, 2; 3, 4], 2)", "Err");
+        test("col([1, 2; 3, 4], 2)", "Err");
+        test("row(3, 0)", "Err");
+    }
+
+    #[test]
+    fn test_engineering_notation() {
+        let units = Units::new();
+        let render = |num: &str| {
+            render_result(
+                &units,
+                &CalcResult::new(CalcResultType::Number(Decimal::from_str(num).unwrap()), 0),
+                &ResultFormat::Eng,
+                false,
+                Some(4),
+                RoundingMode::default(),
+                false,
+            )
+        };
+        assert_eq!(render("12300"), "12.3e3");
+        assert_eq!(render("0.0012"), "1.2e-3");
+        assert_eq!(render("1000000"), "1e6");
+        assert_eq!(render("-12300"), "-12.3e3");
+        assert_eq!(render("999.99999"), "1e3");
+        assert_eq!(render("12"), "12");
+        assert_eq!(render("0"), "0");
+    }
+
+    #[test]
+    fn test_format_result() {
+        let units = Units::new();
+        let mut cfg = RenderConfig::new(&units);
+        assert_eq!(format_result(&eval_to_result("1/3"), &cfg), "0.3333");
+        assert_eq!(format_result(&eval_to_result("5kg*m / 1s^2"), &cfg), "5 N");
+        assert_eq!(
+            format_result(&eval_to_result("[1, 2; 3, 4] * 2"), &cfg),
+            "[2, 4; 6, 8]"
+        );
+        // comparisons result in 1 (true) or 0 (false)
+        assert_eq!(format_result(&eval_to_result("3 > 2"), &cfg), "1");
+        assert_eq!(format_result(&eval_to_result("3 < 2"), &cfg), "0");
+
+        cfg.decimal_count = Some(2);
+        cfg.use_grouping = true;
+        assert_eq!(
+            format_result(&eval_to_result("1234567 / 100"), &cfg),
+            "12 345.67"
+        );
+        cfg.format = ResultFormat::Hex;
+        assert_eq!(format_result(&eval_to_result("255"), &cfg), "FF");
+    }
+
+    #[test]
+    fn test_format_percentage_with_its_decimal_form() {
+        let units = Units::new();
+        let perc =
+            |n: &str| CalcResult::new(CalcResultType::Percentage(Decimal::from_str(n).unwrap()), 0);
+        let mut cfg = RenderConfig::new(&units);
+        // off by default
+        assert_eq!(format_result(&perc("25"), &cfg), "25 %");
+
+        cfg.show_percent_decimal = true;
+        assert_eq!(format_result(&perc("25"), &cfg), "25 % (0.25)");
+        assert_eq!(format_result(&perc("150"), &cfg), "150 % (1.5)");
+        assert_eq!(format_result(&perc("12.5"), &cfg), "12.5 % (0.125)");
+        // only percentages
+        let num = CalcResult::new(CalcResultType::Number(dec(25)), 0);
+        assert_eq!(format_result(&num, &cfg), "25");
+    }
+
+    #[test]
+    fn test_integer_results_are_rendered_without_decimal_point() {
+        test("10 / 2", "5");
+        test("10 / 4", "2.5");
+        test("2.50 * 2", "5");
+        test("ceil(17 / 5)", "4");
+        test("10.0 kg / 2", "5 kg");
+
+        let units = Units::new();
+        let mut cfg = RenderConfig::new(&units);
+        cfg.decimal_count = None;
+        let num =
+            |n: &str| CalcResult::new(CalcResultType::Number(Decimal::from_str(n).unwrap()), 0);
+        assert_eq!(format_result(&num("5.00"), &cfg), "5");
+        assert_eq!(format_result(&num("2.50"), &cfg), "2.5");
+        assert_eq!(format_result(&num("-3.0"), &cfg), "-3");
+    }
+
+    #[test]
+    fn test_displaying_results_with_auto_prefix() {
+        let units = Units::new();
+        let mut cfg = RenderConfig::new(&units);
+        // off by default
+        assert_eq!(format_result(&eval_to_result("1500 m"), &cfg), "1500 m");
+
+        cfg.auto_prefix = true;
+        assert_eq!(format_result(&eval_to_result("1500 m"), &cfg), "1.5 km");
+        assert_eq!(format_result(&eval_to_result("1000 m"), &cfg), "1 km");
+        assert_eq!(format_result(&eval_to_result("999 m"), &cfg), "999 m");
+        assert_eq!(format_result(&eval_to_result("0.5 km"), &cfg), "500 m");
+        assert_eq!(format_result(&eval_to_result("0.02 m"), &cfg), "20 mm");
+        assert_eq!(format_result(&eval_to_result("-1500 m"), &cfg), "-1.5 km");
+        assert_eq!(format_result(&eval_to_result("0.0005 s"), &cfg), "500 µs");
+        assert_eq!(format_result(&eval_to_result("2 µs + 3 us"), &cfg), "5 µs");
+        assert_eq!(format_result(&eval_to_result("2500 g"), &cfg), "2.5 kg");
+        assert_eq!(format_result(&eval_to_result("3500000 W"), &cfg), "3.5 MW");
+        assert_eq!(format_result(&eval_to_result("0 m"), &cfg), "0 m");
+        // units without prefixes and compound units are left as they are
+        assert_eq!(format_result(&eval_to_result("3000 ft"), &cfg), "3000 ft");
+        assert_eq!(
+            format_result(&eval_to_result("5000 km/h"), &cfg),
+            "5000 km / h"
+        );
+        assert_eq!(format_result(&eval_to_result("1500"), &cfg), "1500");
+    }
+
+    #[test]
+    fn test_displaying_results_in_unit_systems() {
+        let units = Units::new();
+        let mut cfg = RenderConfig::new(&units);
+        assert_eq!(format_result(&eval_to_result("100 cm"), &cfg), "100 cm");
+
+        cfg.unit_system = Some(UnitSystem::Metric);
+        assert_eq!(format_result(&eval_to_result("100 cm"), &cfg), "1 m");
+        assert_eq!(format_result(&eval_to_result("1000 g"), &cfg), "1 kg");
+        assert_eq!(format_result(&eval_to_result("1000 l"), &cfg), "1 m^3");
+        // only lengths, masses and volumes
+        assert_eq!(format_result(&eval_to_result("90 min"), &cfg), "90 min");
+
+        cfg.unit_system = Some(UnitSystem::Imperial);
+        assert_eq!(format_result(&eval_to_result("100 cm"), &cfg), "3.2808 ft");
+        assert_eq!(
+            format_result(&eval_to_result("4.54609 l"), &cfg),
+            "1 impgal"
+        );
+
+        cfg.unit_system = Some(UnitSystem::UsCustomary);
+        assert_eq!(format_result(&eval_to_result("3.785412 l"), &cfg), "1 gal");
+
+        // the stored value does not change
+        let result = eval_to_result("100 cm");
+        format_result(&result, &cfg);
+        assert_eq!(
+            render_result(
+                &units,
+                &result,
+                &ResultFormat::Dec,
+                false,
+                Some(4),
+                RoundingMode::default(),
+                false
+            ),
+            "100 cm"
+        );
+    }
+
+    #[test]
+    fn test_sqrt_operator() {
+        test("√9", "3");
+        test("√(4+5)", "3");
+        test("√2", "1.4142");
+        test("1 + √16 * 2", "9");
+        test("√-1", "Err");
+    }
+
+    #[test]
+    fn test_implicit_multiplication() {
+        test("2(3+4)", "14");
+        test("(1+2)(3+4)", "21");
+        test("(1+2)(3)", "9");
+        test("2(3+4)(1+1)", "28");
+        test("1 + 2(3)", "7");
+        test("2^2(3)", "12");
+        test("ceil(0.5)", "1");
+        test("nth([5, 6, 7], 1)(2)", "12");
+
+        test_tokens(
+            "sin(0)",
+            &[
+                op(OperatorTokenType::Fn {
+                    arg_count: 0,
+                    typ: FnType::Sin,
+                }),
+                op(OperatorTokenType::ParenOpen),
+                num(0),
+                op(OperatorTokenType::ParenClose),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_unit_after_parenthesis() {
+        test("(3+4) kg", "7 kg");
+        test("(3+4)kg", "7 kg");
+        test("(1+1) m in cm", "200 cm");
+        test("2 * (3+4) kg", "14 kg");
+    }
+
+    #[test]
+    fn test_unit_display_order_does_not_depend_on_input_order() {
+        test("2 m*kg", "2 kg m");
+        test("2 kg*m", "2 kg m");
+    }
+
+    #[test]
+    fn test_percent_of() {
+        test("10% of 50", "5");
+        test("10% of 50 kg", "5 kg");
+        test("25% of 200 m", "50 m");
+        test("10 kg of 50", "Err");
+        test("10 of 50", "Err");
+    }
+
     #[test]
     fn test_func_sum() {
         test("sum([5, 6, 7])", "18");
@@ -1778,6 +2602,195 @@ mod tests {
         test("13 AND NOT(4 - 1)", "12");
     }
 
+    #[test]
+    fn test_hex_signedness() {
+        let signed = |bit_width| EvalContext {
+            hex_signedness: HexSignedness::Signed { bit_width },
+            ..Default::default()
+        };
+        let signed_32 = signed(32);
+        test("0xFFFFFFFF", "4294967295");
+        test_vars_with_ctx(&create_vars(), "0xFFFFFFFF", "-1", 0, &signed_32);
+        test_vars_with_ctx(&create_vars(), "0XFFFFFFFF", "-1", 0, &signed_32);
+        test_vars_with_ctx(&create_vars(), "0x7FFFFFFF", "2147483647", 0, &signed_32);
+        test_vars_with_ctx(&create_vars(), "0x80000000", "-2147483648", 0, &signed_32);
+        test_vars_with_ctx(&create_vars(), "0xFFFFFFFF + 2", "1", 0, &signed_32);
+        // only hex literals are reinterpreted
+        test_vars_with_ctx(&create_vars(), "4294967295", "4294967295", 0, &signed_32);
+        // literals wider than the width are kept as they are
+        test_vars_with_ctx(&create_vars(), "0x1FFFFFFFF", "8589934591", 0, &signed_32);
+        test_vars_with_ctx(&create_vars(), "0xFF", "-1", 0, &signed(8));
+    }
+
+    #[test]
+    fn test_custom_fn() {
+        let ctx = EvalContext {
+            fns: FnRegistry::new()
+                .with("double", |args| match args {
+                    [CalcResult {
+                        typ: CalcResultType::Number(num),
+                        ..
+                    }] => Ok(CalcResult::new(CalcResultType::Number(num * dec(2)), 0)),
+                    _ => Err(()),
+                })
+                .with("answer", |_args| {
+                    Ok(CalcResult::new(CalcResultType::Number(dec(42)), 0))
+                }),
+            ..Default::default()
+        };
+        test_vars_with_ctx(&create_vars(), "double(21)", "42", 0, &ctx);
+        test_vars_with_ctx(&create_vars(), "double(21) + 1", "43", 0, &ctx);
+        test_vars_with_ctx(&create_vars(), "answer()", "42", 0, &ctx);
+        test_vars_with_ctx(&create_vars(), "double(1, 2)", "Err", 0, &ctx);
+        // unregistered names are not functions
+        test("double(21)", "21");
+    }
+
+    #[test]
+    fn test_constants_with_units() {
+        test("c", "299792458 m / s");
+        test("c in km/s", "299792.458 km / s");
+        test("c in km/h", "1079252848.8 km / h");
+        test("g in ft/s^2", "32.174 ft / s^2");
+        test("g * 2", "19.6133 m / s^2");
+        // grams
+        test("5 g", "5 g");
+        test("1 kg in g", "1000 g");
+        test("5 * g", "5 g");
+        test("2 * g + 3 g", "5 g");
+        // not an expression
+        test("plan c", " ");
+        test("c is fast", " ");
+    }
+
+    #[test]
+    fn test_e_constant_and_exponential_notation() {
+        // the constant
+        test("e", "2.7183");
+        test("2 * e", "5.4366");
+        test("e^2", "7.3891");
+        test("e()", "2.7183");
+        // exponential notation
+        test("3e4", "30000");
+        test("3e4 * e", "81548.4549");
+        // not a multiplication
+        test("3e", "3");
+        test("3 e", "3");
+        test("3e * 2", "6");
+    }
+
+    #[test]
+    fn test_unit_aware_comparison() {
+        test("2 > 1", "1");
+        test("1 > 2", "0");
+        test("1 m > 99 cm", "1");
+        test("1 m < 99 cm", "0");
+        test("1 m >= 100 cm", "1");
+        test("1 m <= 100 cm", "1");
+        test("1 + 1 > 1", "1");
+        test("1 m > 1 kg", "Err");
+        test("1 m > 1", "Err");
+    }
+
+    #[test]
+    fn test_unit_aware_min_max_abs() {
+        test("max(1 m, 50 cm)", "1 m");
+        test("min(1 m, 50 cm)", "50 cm");
+        test("max(3, 7, 5)", "7");
+        test("min([3, 7, 5])", "3");
+        test("max(1 m, 1 kg)", "Err");
+        test("abs(-2)", "2");
+        test("abs(-2 m)", "2 m");
+        test("abs(3 m - 5 m)", "2 m");
+    }
+
+    #[test]
+    fn test_min_max_keep_the_unit_of_the_selected_argument() {
+        test("max(500 m, 1 km)", "1 km");
+        test("min(1 km, 500 m)", "500 m");
+        test("max([500 m, 1 km, 20 cm])", "1 km");
+        // on a tie the first argument wins
+        test("max(1 km, 1000 m)", "1 km");
+        test("min(1000 m, 1 km)", "1000 m");
+    }
+
+    #[test]
+    fn test_modulo_with_units() {
+        test("7 m mod 2 m", "1 m");
+        test("90 min mod 60 min", "30 min");
+        test("90 min mod 1 h", "30 min");
+        test("1 m mod 30 cm", "0.1 m");
+        test("-7 m mod 2 m", "1 m");
+        test("-7 m rem 2 m", "-1 m");
+        test("7 m mod 0 m", "Err");
+        test("7 m mod 2 kg", "Err");
+        test("7 m mod 2", "Err");
+        test("7 mod 2 m", "Err");
+    }
+
+    #[test]
+    fn test_modulo() {
+        test("17 % 5", "2");
+        test("7 mod 3", "1");
+        test("-7 mod 3", "2");
+        test("7 mod -3", "-2");
+        test("-7 mod -3", "-1");
+        test("7 rem 3", "1");
+        test("-7 rem 3", "-1");
+        test("7 rem -3", "1");
+        test("-7 rem -3", "-1");
+        test("6 mod -3", "0");
+        test("7.5 mod 2", "1.5");
+        test("7 mod 0", "Err");
+        test("1 + 17 mod 5 * 2", "5");
+        test("17%5", "2");
+        test("20 % 4", "0");
+        test("20%", "20 %");
+        test("200 + 20%", "240");
+        test("1 + 17 % 5 * 2", "5");
+        test("17 % 0", "Err");
+    }
+
+    #[test]
+    fn test_increased_and_decreased_by() {
+        test("100 increased by 20%", "120");
+        test("100 decreased by 15%", "85");
+        test("100 increased by 20% decreased by 50%", "60");
+        test("2 m increased by 10%", "2.2 m");
+        test("100 increased by 20", "120");
+        // the words alone remain text
+        test("prices increased by much", " ");
+        test("decreased by", " ");
+    }
+
+    #[test]
+    fn test_func_count() {
+        test("count([5, 6, 7])", "3");
+        test("count([5; 6; 7; 8])", "4");
+        test("count(1, 2 m, 30%, 4, 5)", "5");
+        // non-numeric arguments are skipped
+        test("count(1, [1, 2], 3)", "2");
+        test("count(1, 2) * 10", "20");
+    }
+
+    #[test]
+    fn test_func_statistics() {
+        test("mean([1, 2, 3, 4])", "2.5");
+        test("median([3, 1, 2])", "2");
+        test("median([4, 1, 3, 2])", "2.5");
+        test("median([5; 1; 3])", "3");
+        test("variance([2, 4, 4, 4, 5, 5, 7, 9])", "4");
+        test("stddev([2, 4, 4, 4, 5, 5, 7, 9])", "2");
+        test("variance([1, 2, 3])", "0.6667");
+    }
+
+    #[test]
+    fn test_func_statistics_errors() {
+        test("median([1, 2 m, 3])", "Err");
+        test("mean(2)", "Err");
+        test("variance(2 m)", "Err");
+    }
+
     #[test]
     fn test_func_transpose() {
         test("transpose([5, 6, 7])", "[5; 6; 7]");
@@ -1789,6 +2802,454 @@ mod tests {
     fn test_func_pi() {
         test_with_dec_count(1000, "pi()", "3.1415926535897932384626433833");
         test("pi(1)", "Err");
+        test_with_dec_count(1000, "π", "3.1415926535897932384626433833");
+        test("pi() - π", "0");
+    }
+
+    #[test]
+    fn test_matrix_elementwise_pow() {
+        test("[1,2;3,4]^2", "[1, 4; 9, 16]");
+        test("[2 m, 3 m]^2", "[4 m^2, 9 m^2]");
+        test("[1,2;3,4]^[1,2]", "Err");
+    }
+
+    #[test]
+    fn test_func_hadamard() {
+        test("hadamard([1,2;3,4],[2,2;2,2])", "[2, 4; 6, 8]");
+        test("hadamard([1,2],[3 m,4 m])", "[3 m, 8 m]");
+        test("hadamard([1,2;3,4],[1,2])", "Err");
+        test("hadamard([1,2;3,4])", "Err");
+    }
+
+    #[test]
+    fn test_func_sort_and_reverse() {
+        test("sort([3,1,2])", "[1, 2, 3]");
+        test("sort([3;1;2])", "[1; 2; 3]");
+        test("sort([3,1,2], 1)", "[3, 2, 1]");
+        test("sort([3,1,2], 0)", "[1, 2, 3]");
+        test("sort([2 m, 50 cm, 1 m])", "[50 cm, 1 m, 2 m]");
+        test("sort([1 kg, 1 m])", "Err");
+        test("sort([1,2;3,4])", "Err");
+        test("sort(3)", "Err");
+        test("reverse([1,2,3])", "[3, 2, 1]");
+        test("reverse([1;2;3])", "[3; 2; 1]");
+        test("reverse(sort([2,3,1]))", "[3, 2, 1]");
+        test("reverse(1)", "Err");
+    }
+
+    #[test]
+    fn test_func_first_and_last() {
+        test("first([10,20,30])", "10");
+        test("last([10,20,30])", "30");
+        test("first([10;20;30])", "10");
+        test("last([10;20;30])", "30");
+        test("first([5 m])", "5 m");
+        test("last([1 km, 2 km]) in m", "2000 m");
+        test("last(sort([3,1,2]))", "3");
+        test("first(10)", "Err");
+        test("last([1,2;3,4])", "Err");
+    }
+
+    #[test]
+    fn test_abs_bars() {
+        test("|-5|", "5");
+        test("|-5 kg|", "5 kg");
+        test("abs(-5 kg)", "5 kg");
+        test("|-3| + |2 - 7|", "8");
+        test("2 * |1 - 4|", "6");
+        test("|1 - |2 - 5||", "2");
+        test("|[-1, 2, -3]|", "[1, 2, 3]");
+        test("abs([-1, 2, -3])", "[1, 2, 3]");
+        test("|[-1 m, 2 m]|", "[1 m, 2 m]");
+    }
+
+    #[test]
+    fn test_matrix_elements_in_all_number_formats() {
+        test("[0xFF, 0b10; 3, 4]", "[255, 2; 3, 4]");
+        test("[0xFF, 0b10; 1e3, 2k]", "[255, 2; 1000, 2000]");
+        test("[0xFF,0b10,1e3,2k,1M]", "[255, 2, 1000, 2000, 1000000]");
+        test("[0x10; 0b1_0; 1.5e-1; 3 thousand]", "[16; 2; 0.15; 3000]");
+        test("[0xFF, 0b10] * 2", "[510, 4]");
+    }
+
+    #[test]
+    fn test_func_solve() {
+        test("solve([2,1;1,3], [5;10])", "[1; 3]");
+        test("solve([2,1;1,3], [5,10])", "[1, 3]");
+        test("solve([0,1;1,0], [2;3])", "[3; 2]");
+        test("solve([1,2;2,4], [3;6])", "Err");
+        test("solve([1,2,3;4,5,6], [1;2])", "Err");
+        test("solve([2,1;1,3], [5;10;15])", "Err");
+        test("solve([2,1;1,3])", "Err");
+        test("solve(2, 3)", "Err");
+    }
+
+    #[test]
+    fn test_func_norm_and_normalize() {
+        test("norm([3, 4])", "5");
+        test("norm([3; 4])", "5");
+        test("norm([1, 1])", "1.4142");
+        test("norm([-2])", "2");
+        test("norm([1, 2; 3, 4])", "Err");
+        test("norm(5)", "Err");
+        test("normalize([3, 4])", "[0.6, 0.8]");
+        test("normalize([0; 5])", "[0; 1]");
+        test("normalize([0, 0])", "Err");
+        test("normalize(5)", "Err");
+    }
+
+    #[test]
+    fn test_func_deg2rad_and_rad2deg() {
+        test("deg2rad(180)", "3.1416");
+        test("deg2rad(90)", "1.5708");
+        test("equal(deg2rad(180), π, 0.0000001)", "1");
+        test("sin(deg2rad(30))", "0.5");
+        test("rad2deg(1)", "57.2958");
+        test("equal(rad2deg(π), 180, 0.0000001)", "1");
+        test("equal(rad2deg(deg2rad(42)), 42, 0.0000001)", "1");
+        // only for unitless numbers
+        test("deg2rad(90 degree)", "Err");
+        test("rad2deg(1 m)", "Err");
+        test("deg2rad([1, 2])", "Err");
+    }
+
+    #[test]
+    fn test_func_equal() {
+        test("equal([1, 2; 3, 4], [1, 2; 3, 4])", "1");
+        test("equal([1, 2; 3, 4], [1, 2; 3, 5])", "0");
+        // shape mismatch is not an error
+        test("equal([1, 2, 3, 4], [1, 2; 3, 4])", "0");
+        test("equal([1, 2], [1, 2, 3])", "0");
+        test("equal([1, 2], 1)", "0");
+        // scalars
+        test("equal(2, 2)", "1");
+        test("equal(1/3, 0.3333)", "0");
+        test("equal(1 km, 1000 m)", "1");
+        test("equal(1 km, 1 kg)", "0");
+        // tolerance
+        test("equal(1/3, 0.3333, 0.001)", "1");
+        test("equal([1, 2], [1.05, 1.98], 0.1)", "1");
+        test("equal([1, 2], [1.05, 1.98], 0.01)", "0");
+        test("equal(1, 2, -1)", "Err");
+        test("equal(1)", "Err");
+    }
+
+    #[test]
+    fn test_func_sin_cos() {
+        test("sin(0)", "0");
+        test("sin(π/2)", "1");
+        test("cos(π)", "-1");
+        test("sin(1 m)", "Err");
+        let deg = EvalContext {
+            angle_mode: AngleMode::Deg,
+            ..Default::default()
+        };
+        test_vars_with_ctx(&create_vars(), "sin(90)", "1", 4, &deg);
+        test_vars_with_ctx(&create_vars(), "cos(60)", "0.5", 4, &deg);
+        let grad = EvalContext {
+            angle_mode: AngleMode::Grad,
+            ..Default::default()
+        };
+        test_vars_with_ctx(&create_vars(), "sin(100)", "1", 4, &grad);
+    }
+
+    #[test]
+    fn test_func_round() {
+        test("round(2.4)", "2");
+        test("round(2.6)", "3");
+        test("round(-2.6)", "-3");
+        test("round(3.14159, 2)", "3.14");
+        test("round(2.4 cm)", "2 cm");
+        test("round(2.5, 0.5)", "Err");
+        test("round(2.5, -1)", "Err");
+    }
+
+    #[test]
+    fn test_rounding_functions_keep_the_unit() {
+        test("round(3.7 kg)", "4 kg");
+        test("floor(2.9 m)", "2 m");
+        test("ceil(2.1 m)", "3 m");
+        test("round(3.14159 m, 2)", "3.14 m");
+        // rounded in the unit of the quantity, not in its base unit
+        test("round(2.4 km)", "2 km");
+        test("floor(2.9 km) in m", "2000 m");
+        test("ceil(150.2 cm)", "151 cm");
+        // compound units as well
+        test("round(3.6 km/h)", "4 km / h");
+        test("floor(-2.5 m)", "-3 m");
+        test("floor(2.5)", "2");
+        test("floor([1.5, 2.5])", "Err");
+    }
+
+    #[test]
+    fn test_rounding_percentages() {
+        test("round(33.333%)", "33 %");
+        test("equal(round(33.333%), 33%)", "1");
+        test("round(2.5%, 1)", "2.5 %");
+        test("round(2.55%, 1)", "2.6 %");
+        test("floor(33.9%)", "33 %");
+        test("ceil(33.1%)", "34 %");
+        test("200 + round(12.4%)", "224");
+    }
+
+    #[test]
+    fn test_rounding_modes() {
+        // half-even by default
+        test("round(2.5)", "2");
+        test("round(3.5)", "4");
+        test("round(0.125, 2)", "0.12");
+        let half_up = EvalContext {
+            rounding_mode: RoundingMode::HalfUp,
+            ..Default::default()
+        };
+        test_vars_with_ctx(&create_vars(), "round(2.5)", "3", 4, &half_up);
+        test_vars_with_ctx(&create_vars(), "round(3.5)", "4", 4, &half_up);
+        test_vars_with_ctx(&create_vars(), "round(-2.5)", "-3", 4, &half_up);
+        test_vars_with_ctx(&create_vars(), "round(0.125, 2)", "0.13", 4, &half_up);
+    }
+
+    #[test]
+    fn test_displayed_results_are_rounded_with_the_rounding_mode() {
+        let units = Units::new();
+        let num =
+            |n: &str| CalcResult::new(CalcResultType::Number(Decimal::from_str(n).unwrap()), 0);
+        let mut cfg = RenderConfig::new(&units);
+        // half-even by default
+        assert_eq!(format_result(&num("0.03125"), &cfg), "0.0312");
+        assert_eq!(format_result(&num("0.03135"), &cfg), "0.0314");
+        cfg.decimal_count = Some(0);
+        assert_eq!(format_result(&num("2.5"), &cfg), "2");
+        assert_eq!(format_result(&num("-2.5"), &cfg), "-2");
+        cfg.format = ResultFormat::Eng;
+        cfg.decimal_count = Some(1);
+        assert_eq!(format_result(&num("12250"), &cfg), "12.2e3");
+
+        cfg.rounding_mode = RoundingMode::HalfUp;
+        assert_eq!(format_result(&num("12250"), &cfg), "12.3e3");
+        cfg.format = ResultFormat::Dec;
+        cfg.decimal_count = Some(0);
+        assert_eq!(format_result(&num("2.5"), &cfg), "3");
+        assert_eq!(format_result(&num("-2.5"), &cfg), "-3");
+        cfg.decimal_count = Some(4);
+        assert_eq!(format_result(&num("0.03125"), &cfg), "0.0313");
+        assert_eq!(format_result(&num("0.03135"), &cfg), "0.0314");
+
+        let half_up = EvalContext {
+            rounding_mode: RoundingMode::HalfUp,
+            ..Default::default()
+        };
+        test("1/32", "0.0312");
+        test_vars_with_ctx(&create_vars(), "1/32", "0.0313", 4, &half_up);
+    }
+
+    #[test]
+    fn test_conversion_to_number() {
+        test("(5 kg / kg) in number", "5");
+        test("(5 km / m) in number", "5000");
+        test("5 kg in number", "Err");
+        test("5 kg in scalar", "Err");
+        test("(6 m / 3 m) in scalar", "2");
+        test("7 in number", "7");
+        test("50% in number", "0.5");
+        test("5000 ppm in number", "0.005");
+        test("[6 m / 3 m, 2] in number", "[2, 2]");
+        test("[1 m, 2] in number", "Err");
+    }
+
+    #[test]
+    fn test_unit_after_percentage_is_an_error() {
+        test("30% kg", "Err");
+        test("30 % kg", "Err");
+        test("30 kg * 50%", "15 kg");
+        test("(30 kg) * 50%", "15 kg");
+        test("10% of 50 kg", "5 kg");
+        test("1 % in ppm", "10000 ppm");
+    }
+
+    #[test]
+    fn test_percentage_of_quantities() {
+        test("50 kg + 10%", "55 kg");
+        test("200 m - 25%", "150 m");
+        test("2 km + 50% in m", "3000 m");
+        // only the left hand side can be the base of the percentage
+        test("10% + 50 kg", "Err");
+        test("10% - 50 kg", "Err");
+    }
+
+    #[test]
+    fn test_degree_symbol() {
+        test("90°", "90 °");
+        test("90° in degree", "90 degree");
+        test("sin(90°)", "1");
+        test("cos(180 degree)", "-1");
+        test("360° in turn", "1 turn");
+        test("20°C in °F", "68 °F");
+        test("20 °C in K", "293.15 K");
+        test("50°F in degC", "10 degC");
+    }
+
+    #[test]
+    fn test_degrees_minutes_seconds() {
+        test("40° 26' 46\" in degree", "40.4461 degree");
+        test("40°26'46\" in degree", "40.4461 degree");
+        test("90° 30' in degree", "90.5 degree");
+        test("10° 36\" in degree", "10.01 degree");
+        test("1° 30' + 1° 30' in degree", "3 degree");
+        test("sin(30° 0')", "0.5");
+    }
+
+    #[test]
+    fn test_logical_operators() {
+        test("true", "1");
+        test("false", "0");
+        test("true and false", "0");
+        test("true and true", "1");
+        test("false or true", "1");
+        test("not true", "0");
+        test("not false", "1");
+        test("not (1 < 2)", "0");
+        test("1<2 or 2<1", "1");
+        test("1<2 and 2<1", "0");
+        test("5 and 3", "1");
+        test("0 or 0", "0");
+        test("1 kg and true", "Err");
+        // the words in prose are not operators
+        test("this is not true", " ");
+        test("5 kg or so", "5 kg");
+        test("false alarm", " ");
+        // the bitwise operators are unaffected
+        test("0b110 AND 0b011", "2");
+        test("0b110 OR 0b011", "7");
+    }
+
+    #[test]
+    fn test_rounding_results_in_bitwise_operations() {
+        test("0xF AND floor(3.9)", "3");
+        test("0b1000 OR ceil(2.1)", "11");
+        test("round(6.6) XOR 1", "6");
+        test("1 << round(2.5)", "4");
+        test("floor(7.9) >> 1", "3");
+        test("NOT(floor(0.5))", "-1");
+    }
+
+    #[test]
+    fn test_conversion_overflow() {
+        test("1e15 km in nm", "1000000000000000000000000000 nm");
+        test("1e25 km in nm", "Err");
+        test("[1 km, 1e25 km] in nm", "Err");
+    }
+
+    #[test]
+    fn test_conversion_chains() {
+        test("1 mi in km in m", "1609.344 m");
+        test("1 day in hour in min", "1440 min");
+        test("1 mi in km", "1.6093 km");
+        test("37 °C in °F in K", "310.15 K");
+        test("1 mi in kg in m", "Err");
+    }
+
+    #[test]
+    fn test_temperature_conversions() {
+        test("37 °C in °F", "98.6 °F");
+        test("98.6 °F in °C", "37 °C");
+        test("300 K in °C", "26.85 °C");
+        test("26.85 °C in K", "300 K");
+        test("212 °F in K", "373.15 K");
+        test("373.15 K in °F", "212 °F");
+        test("-40 °C in °F", "-40 °F");
+        test("0 K in °C", "-273.15 °C");
+    }
+
+    #[test]
+    fn test_func_arity() {
+        test("sin()", "Err");
+        test("sin(1, 2)", "Err");
+        test("nth([1, 2])", "Err");
+        test("nth([1, 2], 1, 2)", "Err");
+        test("nth([1, 2], 1)", "2");
+        test("count()", "Err");
+        test("count(1, 2, 3)", "3");
+        test("max(1, 5, 3)", "5");
+    }
+
+    #[test]
+    fn test_all_functions() {
+        let fns = crate::functions::all_functions();
+        let find = |name: &str| fns.iter().find(|it| it.name == name).unwrap();
+        assert_eq!(find("nth").min_arg_count, 2);
+        assert_eq!(find("nth").max_arg_count, Some(2));
+        assert_eq!(find("sin").min_arg_count, 1);
+        assert_eq!(find("sin").max_arg_count, Some(1));
+        assert_eq!(find("pi").max_arg_count, Some(0));
+        // variadic
+        assert_eq!(find("max").max_arg_count, None);
+        assert!(fns
+            .iter()
+            .all(|it| !it.name.is_empty() && !it.description.is_empty()));
+    }
+
+    #[test]
+    fn test_func_log2() {
+        test("log2(8)", "3");
+        test("log2(1)", "0");
+        test("log2(2)", "1");
+        test("log2(1024)", "10");
+        test("log2(0.5)", "-1");
+        test("log2(10)", "3.3219");
+        test("log2(0)", "Err");
+        test("log2(-8)", "Err");
+        test("log2(8 m)", "Err");
+    }
+
+    #[test]
+    fn test_func_bitlength() {
+        test("bitlength(0)", "0");
+        test("bitlength(1)", "1");
+        test("bitlength(255)", "8");
+        test("bitlength(256)", "9");
+        test("bitlength(0xFFFFFFFF)", "32");
+        test("bitlength(0x100000000)", "33");
+        test("bitlength(1.5)", "Err");
+        test("bitlength(-1)", "Err");
+    }
+
+    #[test]
+    fn test_nested_func_calls() {
+        test("max(min(3,5),2)", "3");
+        test("max(min(3, 5), min(8, 2), 1)", "3");
+        test("max(1, min(2, max(3, 4)))", "2");
+        test("abs(min(-3, -1))", "3");
+        test("mean([1, max(2, 3), 5])", "3");
+        test("count(1, max(2, 3), [4, 5])", "2");
+        test("pi( ) - π", "0");
+    }
+
+    #[test]
+    fn test_func_wrong_arity_marks_the_fn_token() {
+        let temp = "sin(1, 2)".chars().collect::<Vec<char>>();
+        let units = Units::new();
+        let vars = create_vars();
+        let ctx = EvalContext::default();
+        let mut tokens = vec![];
+        let arena = Bump::new();
+        let mut shunting_output = crate::shunting_yard::tests::do_shunting_yard_with_fns(
+            &temp,
+            &units,
+            &mut tokens,
+            &vars,
+            &ctx.fns,
+            &arena,
+        );
+        let result = evaluate_tokens(&mut tokens, &mut shunting_output, &vars, &ctx);
+        assert!(result.is_err());
+        assert!(tokens[0].has_error());
+    }
+
+    #[test]
+    fn test_func_e() {
+        test_with_dec_count(1000, "e()", "2.7182818284590452353602874714");
+        test("2 * e()", "5.4366");
+        test("e(1)", "Err");
     }
 
     #[test]
@@ -1828,6 +3289,57 @@ mod tests {
         test("0^-1", "Err");
     }
 
+    #[test]
+    fn test_fractional_pow() {
+        test("4^0.5", "2");
+        test("27^(1/3)", "3");
+        test("(-4)^0.5", "Err");
+        test("(4 m)^0.5", "2 m^0.5");
+    }
+
+    #[test]
+    fn test_pow_of_quantities() {
+        test("(2 m)^3", "8 m^3");
+        test("(3 s)^-1", "0.3333 s^-1");
+        test("(2 m)^2 in cm^2", "40000 cm^2");
+        test("(4 m^2)^0.5", "2 m");
+        test("(8 m^3)^(1/3)", "2 m");
+        test("(8 m^6)^(1/3)", "2 m^2");
+        // m^(1/3) has no finite decimal exponent
+        test("(2 m)^(1/3)", "Err");
+        test("(2 m^2)^(1/3)", "Err");
+        test("(2 m)^(1/6)", "Err");
+        test("(2 m)^1.5", "2.8284 m^1.5");
+        test("(-4 m^2)^0.5", "Err");
+        // the unit can't have an irrational exponent
+        test("(2 m)^π", "Err");
+    }
+
+    #[test]
+    fn test_fractional_unit_exponents() {
+        test("sqrt(1 m^2)", "1 m");
+        test("sqrt(16 m^2)", "4 m");
+        test("√(9 m^2)", "3 m");
+        test("sqrt(4 m)", "2 m^0.5");
+        test("2 s^0.5", "2 s^0.5");
+        test("2 s^0.5 * 3 s^0.5", "6 s");
+        test("sqrt(4 m) * sqrt(9 m)", "6 m");
+        test("2 s^0.5 + 3 s^0.5", "5 s^0.5");
+        test("2 s^0.5 + 3 s", "Err");
+        test("sqrt(1 m^2) in cm", "100 cm");
+    }
+
+    #[test]
+    fn test_func_pow() {
+        test("pow(2, 10)", "1024");
+        test("pow(2,10)", "1024");
+        test("pow(4, 0.5)", "2");
+        test("pow(2 m, 2)", "4 m^2");
+        test("pow(2, 3) + 1", "9");
+        test("pow(2)", "Err");
+        test("pow(4 m, 0.5)", "2 m^0.5");
+    }
+
     #[test]
     fn test_simple_unit() {
         test("30 years", "30 year");
@@ -1903,11 +3415,101 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_magnitude_words() {
+        test("3 million", "3000000");
+        test("3 thousand + 2k", "5000");
+        test("2.5 million kg", "2500000 kg");
+    }
+
+    #[test]
+    fn test_exponent_before_unit() {
+        test("1e3m", "1000 m");
+        test("1e3 m", "1000 m");
+        test("2.5e-2 kg", "0.025 kg");
+    }
+
+    #[test]
+    fn test_to_f64() {
+        let num = CalcResult::new(CalcResultType::Number(dec(12)), 0);
+        assert_eq!(num.to_f64(), Some((12.0, false)));
+
+        let units = Units::new();
+        let (km, _) = units.parse(&['k', 'm']);
+        let quantity = CalcResult::new(
+            CalcResultType::Quantity(km.normalize(&dec(2)).unwrap(), km),
+            0,
+        );
+        assert_eq!(quantity.to_f64(), Some((2.0, false)));
+
+        let lossy = CalcResult::new(
+            CalcResultType::Number(Decimal::from_str("0.1234567890123456789").unwrap()),
+            0,
+        );
+        assert!(lossy.to_f64().unwrap().1);
+
+        let mat = CalcResult::new(
+            CalcResultType::Matrix(MatrixData::new(vec![num.clone()], 1, 1)),
+            0,
+        );
+        assert_eq!(mat.to_f64(), None);
+    }
+
     #[test]
     fn test_pi() {
         test("π", "3.1416");
     }
 
+    #[test]
+    fn test_unicode_multiplication_and_division_signs() {
+        test("2 · 3", "6");
+        test("2 × 3", "6");
+        test("6 ÷ 2", "3");
+        test("2 m × 3 m", "6 m^2");
+        test("1 + 6÷2·3", "10");
+    }
+
+    #[test]
+    fn test_prefixed_derived_units() {
+        test("5 kN in N", "5000 N");
+        test("2 MHz in Hz", "2000000 Hz");
+        test("1 mJ in J", "0.001 J");
+        test("1 GPa in kPa", "1000000 kPa");
+        test("3 kW * 2 h in kWh", "6 kWh");
+    }
+
+    #[test]
+    fn test_scalar_broadcasting_over_matrices() {
+        test("[1,2,3] + 10", "[11, 12, 13]");
+        test("10 + [1,2,3]", "[11, 12, 13]");
+        test("[1,2,3] - 1", "[0, 1, 2]");
+        test("10 - [1,2,3]", "[9, 8, 7]");
+        test("[1,2,3] * 2", "[2, 4, 6]");
+        test("[2,4,6] / 2", "[1, 2, 3]");
+        test("[10,20] + 10%", "[11, 22]");
+        test("[1,2] kg", "[1 kg, 2 kg]");
+        test("[1,2] kg + 1 kg", "[2 kg, 3 kg]");
+        test("1 kg + [1 kg, 2 kg]", "[2 kg, 3 kg]");
+        test("[1,2] kg + 1 m", "Err");
+        test("[1,2] + 1 m", "Err");
+    }
+
+    #[test]
+    fn test_unicode_minus_signs() {
+        test("5 − 3", "2");
+        test("5 – 3", "2");
+        test("−7", "-7");
+        test("2 * −7", "-14");
+        test("2e−3", "0.002");
+    }
+
+    #[test]
+    fn test_leading_equal_sign_is_ignored() {
+        test("=2+2", "4");
+        test("= 2 * 3", "6");
+        test("  =2+2", "4");
+    }
+
     #[test]
     fn test_multiple_equal_signs2() {
         test("=(Blq9h/Oq=7y^$o[/kR]*$*oReyMo-M++]", "7");
@@ -1971,6 +3573,13 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_unparsable_number_makes_the_line_error() {
+        test("017327229991661686687892454247286090975M + 1", "Err");
+        test("1 + 017327229991661686687892454247286090975M", "Err");
+        test("1 + 2 + x 017327229991661686687892454247286090975M", "Err");
+    }
+
     #[test]
     fn test_multiplying_bug_numbers_via_unit_no_panic() {
         test("909636Yl", "909636 Yl");
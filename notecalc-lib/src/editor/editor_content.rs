@@ -275,6 +275,22 @@ impl<T: Default + Clone + Debug> EditorContent<T> {
         self.set_str_at(text, 0, 0);
     }
 
+    /// Replaces the content of the given row, the text is cut at the first line break
+    /// or at the maximum line length.
+    pub fn set_line(&mut self, row_index: usize, text: &str) {
+        self.line_lens[row_index] = 0;
+        for ch in text.chars() {
+            if ch == '\n' || self.line_lens[row_index] == self.max_line_len {
+                break;
+            } else if ch == '\r' {
+                continue;
+            }
+            let len = self.line_lens[row_index];
+            self.set_char(row_index, len, ch);
+            self.line_lens[row_index] += 1;
+        }
+    }
+
     pub fn get_content(&self) -> String {
         let mut result = String::with_capacity(self.canvas.len() * self.max_line_len);
         self.write_content_into(&mut result);
@@ -1,6 +1,13 @@
-use crate::calc::{add_op, CalcResult, CalcResultType};
-use crate::token_parser::Token;
+use crate::calc::{
+    add_op, bool_result, compare, divide_op, multiply_op, pow_op, sqrt_op, CalcResult,
+    CalcResultType, EvalContext,
+};
+use crate::matrix::MatrixData;
+use crate::token_parser::{Token, PI};
+use crate::units::consts::ANGLE_UNIT_DIMENSIONS;
 use rust_decimal::prelude::*;
+use std::cmp::Ordering;
+use std::rc::Rc;
 use std::str::FromStr;
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
@@ -12,14 +19,47 @@ pub enum FnType {
     Nth,
     Sum,
     Transpose,
+    Hadamard,
+    Pow,
+    Sqrt,
     Pi,
+    E,
     Ceil,
+    Floor,
+    Round,
+    Row,
+    Col,
+    Count,
+    Mean,
+    Median,
+    Variance,
+    Stddev,
+    Abs,
+    Min,
+    Max,
+    Solve,
+    Sort,
+    Reverse,
+    Log2,
+    BitLength,
+    First,
+    Last,
+    Equal,
+    Norm,
+    Normalize,
+    Deg2Rad,
+    Rad2Deg,
+    /// index into the `FnRegistry` of the evaluation
+    Custom(usize),
 }
 
 impl FnType {
     #[inline]
     pub fn value_of(ptr: &[char]) -> Option<FnType> {
         for fn_type in FnType::iter() {
+            if matches!(fn_type, FnType::Custom(..)) {
+                continue;
+            }
             if ptr == fn_type.name() {
                 return Some(fn_type);
             }
@@ -35,8 +75,123 @@ impl FnType {
             FnType::Nth => &['n', 't', 'h'],
             FnType::Sum => &['s', 'u', 'm'],
             FnType::Transpose => &['t', 'r', 'a', 'n', 's', 'p', 'o', 's', 'e'],
+            FnType::Hadamard => &['h', 'a', 'd', 'a', 'm', 'a', 'r', 'd'],
+            FnType::Pow => &['p', 'o', 'w'],
+            FnType::Sqrt => &['s', 'q', 'r', 't'],
             FnType::Pi => &['p', 'i'],
+            FnType::E => &['e'],
             FnType::Ceil => &['c', 'e', 'i', 'l'],
+            FnType::Floor => &['f', 'l', 'o', 'o', 'r'],
+            FnType::Round => &['r', 'o', 'u', 'n', 'd'],
+            FnType::Equal => &['e', 'q', 'u', 'a', 'l'],
+            FnType::Norm => &['n', 'o', 'r', 'm'],
+            FnType::Normalize => &['n', 'o', 'r', 'm', 'a', 'l', 'i', 'z', 'e'],
+            FnType::Deg2Rad => &['d', 'e', 'g', '2', 'r', 'a', 'd'],
+            FnType::Rad2Deg => &['r', 'a', 'd', '2', 'd', 'e', 'g'],
+            FnType::Row => &['r', 'o', 'w'],
+            FnType::Col => &['c', 'o', 'l'],
+            FnType::Count => &['c', 'o', 'u', 'n', 't'],
+            FnType::Mean => &['m', 'e', 'a', 'n'],
+            FnType::Median => &['m', 'e', 'd', 'i', 'a', 'n'],
+            FnType::Variance => &['v', 'a', 'r', 'i', 'a', 'n', 'c', 'e'],
+            FnType::Stddev => &['s', 't', 'd', 'd', 'e', 'v'],
+            FnType::Abs => &['a', 'b', 's'],
+            FnType::Min => &['m', 'i', 'n'],
+            FnType::Max => &['m', 'a', 'x'],
+            FnType::Solve => &['s', 'o', 'l', 'v', 'e'],
+            FnType::Sort => &['s', 'o', 'r', 't'],
+            FnType::Reverse => &['r', 'e', 'v', 'e', 'r', 's', 'e'],
+            FnType::Log2 => &['l', 'o', 'g', '2'],
+            FnType::BitLength => &['b', 'i', 't', 'l', 'e', 'n', 'g', 't', 'h'],
+            FnType::First => &['f', 'i', 'r', 's', 't'],
+            FnType::Last => &['l', 'a', 's', 't'],
+            // the name is stored in the registry
+            FnType::Custom(..) => &[],
+        }
+    }
+
+    /// the minimum and maximum number of arguments, `None` means variadic
+    pub fn arity(&self) -> (usize, Option<usize>) {
+        match self {
+            FnType::Pi | FnType::E => (0, Some(0)),
+            FnType::Sin
+            | FnType::Cos
+            | FnType::Sum
+            | FnType::Transpose
+            | FnType::Ceil
+            | FnType::Floor
+            | FnType::Mean
+            | FnType::Median
+            | FnType::Variance
+            | FnType::Stddev
+            | FnType::Abs
+            | FnType::Sqrt
+            | FnType::Reverse
+            | FnType::Log2
+            | FnType::BitLength
+            | FnType::First
+            | FnType::Last
+            | FnType::Norm
+            | FnType::Normalize
+            | FnType::Deg2Rad
+            | FnType::Rad2Deg => (1, Some(1)),
+            // the optional second argument is the descending flag
+            FnType::Sort => (1, Some(2)),
+            // the optional second argument is the number of decimal places
+            FnType::Round => (1, Some(2)),
+            // the optional third argument is the tolerance
+            FnType::Equal => (2, Some(3)),
+            FnType::Nth
+            | FnType::Hadamard
+            | FnType::Pow
+            | FnType::Row
+            | FnType::Col
+            | FnType::Solve => (2, Some(2)),
+            FnType::Count | FnType::Min | FnType::Max => (1, None),
+            // checked by the function itself
+            FnType::Custom(..) => (0, None),
+        }
+    }
+
+    /// A short, one line description of the function, e.g. for tooltips
+    pub fn description(&self) -> &'static str {
+        match self {
+            FnType::Sin => "Sine of an angle",
+            FnType::Cos => "Cosine of an angle",
+            FnType::Nth => "The element of a matrix at the given (0 based) index",
+            FnType::Sum => "Sum of the elements of a matrix",
+            FnType::Transpose => "Transpose of a matrix",
+            FnType::Hadamard => "Element-wise product of two matrices",
+            FnType::Pow => "The first argument raised to the power of the second",
+            FnType::Sqrt => "Square root",
+            FnType::Pi => "The ratio of a circle's circumference to its diameter",
+            FnType::E => "Euler's number",
+            FnType::Ceil => "The smallest integer greater than or equal to the argument",
+            FnType::Floor => "The largest integer less than or equal to the argument",
+            FnType::Round => "Rounds to the given number of decimal places (0 by default)",
+            FnType::Row => "The row of a matrix at the given (0 based) index",
+            FnType::Col => "The column of a matrix at the given (0 based) index",
+            FnType::Count => "The number of numeric arguments or matrix elements",
+            FnType::Mean => "Arithmetic mean of the elements of a matrix",
+            FnType::Median => "Median of the elements of a matrix",
+            FnType::Variance => "Variance of the elements of a matrix",
+            FnType::Stddev => "Standard deviation of the elements of a matrix",
+            FnType::Abs => "Absolute value",
+            FnType::Min => "The smallest of the arguments",
+            FnType::Max => "The largest of the arguments",
+            FnType::Solve => "Solves the linear system of equations A*x = b for x",
+            FnType::Sort => "Sorts a vector, descending if the second argument is true",
+            FnType::Reverse => "Reverses the order of the elements of a vector",
+            FnType::Log2 => "Base 2 logarithm",
+            FnType::BitLength => "The number of bits needed to represent a non-negative integer",
+            FnType::First => "The first element of a vector",
+            FnType::Last => "The last element of a vector",
+            FnType::Equal => "1 if two values or matrices are equal within a tolerance, else 0",
+            FnType::Norm => "The Euclidean length of a vector",
+            FnType::Normalize => "The unit vector of the same direction as the argument",
+            FnType::Deg2Rad => "Converts a number of degrees to radians",
+            FnType::Rad2Deg => "Converts a number of radians to degrees",
+            FnType::Custom(..) => "",
         }
     }
 
@@ -47,17 +202,212 @@ impl FnType {
         stack: &mut Vec<CalcResult>,
         fn_token_index: usize,
         tokens: &mut [Token<'text_ptr>],
+        ctx: &EvalContext,
     ) -> bool {
+        let (min_arg_count, max_arg_count) = self.arity();
+        if arg_count < min_arg_count
+            || max_arg_count.map(|max| arg_count > max).unwrap_or(false)
+            || stack.len() < arg_count
+        {
+            Token::set_token_error_flag_by_index(fn_token_index, tokens);
+            return false;
+        }
         match self {
             FnType::Nth => fn_nth(arg_count, stack, tokens, fn_token_index),
             FnType::Sum => fn_sum(arg_count, stack),
             FnType::Transpose => fn_transpose(arg_count, stack),
+            FnType::Hadamard => fn_hadamard(arg_count, stack, tokens, fn_token_index),
+            FnType::Pow => fn_pow(arg_count, stack, tokens, fn_token_index),
+            FnType::Sqrt => fn_sqrt(arg_count, stack, tokens, fn_token_index),
             FnType::Pi => fn_pi(arg_count, stack, fn_token_index),
-            FnType::Sin => true,
-            FnType::Cos => true,
-            FnType::Ceil => fn_ceil(arg_count, stack, tokens, fn_token_index),
+            FnType::E => fn_e(arg_count, stack, fn_token_index),
+            FnType::Sin => {
+                fn_trigonometric(f64::sin, arg_count, stack, tokens, fn_token_index, ctx)
+            }
+            FnType::Cos => {
+                fn_trigonometric(f64::cos, arg_count, stack, tokens, fn_token_index, ctx)
+            }
+            FnType::Ceil => fn_floor_or_ceil(true, arg_count, stack, tokens, fn_token_index),
+            FnType::Floor => fn_floor_or_ceil(false, arg_count, stack, tokens, fn_token_index),
+            FnType::Round => fn_round(arg_count, stack, tokens, fn_token_index, ctx),
+            FnType::Row => fn_row_or_col(true, arg_count, stack, tokens, fn_token_index),
+            FnType::Col => fn_row_or_col(false, arg_count, stack, tokens, fn_token_index),
+            FnType::Count => fn_count(arg_count, stack, fn_token_index),
+            FnType::Mean | FnType::Median | FnType::Variance | FnType::Stddev => {
+                fn_statistics(*self, arg_count, stack, tokens, fn_token_index)
+            }
+            FnType::Abs => fn_abs(arg_count, stack, tokens, fn_token_index),
+            FnType::Min => fn_min_or_max(false, arg_count, stack, tokens, fn_token_index),
+            FnType::Max => fn_min_or_max(true, arg_count, stack, tokens, fn_token_index),
+            FnType::Solve => fn_solve(arg_count, stack, tokens, fn_token_index),
+            FnType::Sort => fn_sort(arg_count, stack, tokens, fn_token_index),
+            FnType::Reverse => fn_reverse(arg_count, stack, tokens, fn_token_index),
+            FnType::Log2 => fn_log2(arg_count, stack, tokens, fn_token_index),
+            FnType::BitLength => fn_bitlength(arg_count, stack, tokens, fn_token_index),
+            FnType::First => fn_first_or_last(false, arg_count, stack, tokens, fn_token_index),
+            FnType::Last => fn_first_or_last(true, arg_count, stack, tokens, fn_token_index),
+            FnType::Equal => fn_equal(arg_count, stack, tokens, fn_token_index),
+            FnType::Norm => fn_norm_or_normalize(false, arg_count, stack, tokens, fn_token_index),
+            FnType::Normalize => {
+                fn_norm_or_normalize(true, arg_count, stack, tokens, fn_token_index)
+            }
+            FnType::Deg2Rad => {
+                fn_deg2rad_or_rad2deg(true, arg_count, stack, tokens, fn_token_index)
+            }
+            FnType::Rad2Deg => {
+                fn_deg2rad_or_rad2deg(false, arg_count, stack, tokens, fn_token_index)
+            }
+            FnType::Custom(index) => {
+                fn_custom(&ctx.fns, *index, arg_count, stack, tokens, fn_token_index)
+            }
+        }
+    }
+}
+
+/// A builtin function, e.g. for listing the suggestions of an autocompletion.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FnInfo {
+    pub name: String,
+    pub min_arg_count: usize,
+    /// `None` means variadic
+    pub max_arg_count: Option<usize>,
+    pub description: &'static str,
+}
+
+/// All the builtin functions, functions registered into a `FnRegistry` are not listed.
+pub fn all_functions() -> Vec<FnInfo> {
+    FnType::iter()
+        .filter(|it| !matches!(it, FnType::Custom(..)))
+        .map(|it| {
+            let (min_arg_count, max_arg_count) = it.arity();
+            FnInfo {
+                name: it.name().iter().collect(),
+                min_arg_count,
+                max_arg_count,
+                description: it.description(),
+            }
+        })
+        .collect()
+}
+
+pub type CustomFn = Rc<dyn Fn(&[CalcResult]) -> Result<CalcResult, ()>>;
+
+/// Functions registered by the host application.
+/// They are looked up only if there is no builtin function with the same name.
+#[derive(Clone)]
+pub struct FnRegistry {
+    fns: Vec<(Box<[char]>, CustomFn)>,
+}
+
+impl Default for FnRegistry {
+    fn default() -> Self {
+        FnRegistry::new()
+    }
+}
+
+impl FnRegistry {
+    pub fn new() -> FnRegistry {
+        FnRegistry { fns: Vec::new() }
+    }
+
+    pub fn with(
+        mut self,
+        name: &str,
+        f: impl Fn(&[CalcResult]) -> Result<CalcResult, ()> + 'static,
+    ) -> FnRegistry {
+        self.register(name, f);
+        self
+    }
+
+    /// a function with the same name is replaced
+    pub fn register(
+        &mut self,
+        name: &str,
+        f: impl Fn(&[CalcResult]) -> Result<CalcResult, ()> + 'static,
+    ) {
+        let name: Vec<char> = name.chars().collect();
+        if let Some(index) = self.index_of(&name) {
+            self.fns[index].1 = Rc::new(f);
+        } else {
+            self.fns.push((Box::from(&name[..]), Rc::new(f)));
         }
     }
+
+    pub fn index_of(&self, name: &[char]) -> Option<usize> {
+        self.fns.iter().position(|(it, _)| **it == *name)
+    }
+
+    pub fn name(&self, index: usize) -> &[char] {
+        &self.fns[index].0
+    }
+}
+
+fn fn_custom<'text_ptr>(
+    fns: &FnRegistry,
+    index: usize,
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+) -> bool {
+    if stack.len() < arg_count || index >= fns.fns.len() {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return false;
+    }
+    let args_from = stack.len() - arg_count;
+    match (fns.fns[index].1)(&stack[args_from..]) {
+        Ok(result) => {
+            stack.truncate(args_from);
+            stack.push(CalcResult::new(result.typ, fn_token_index));
+            true
+        }
+        Err(()) => {
+            Token::set_token_error_flag_by_index(fn_token_index, tokens);
+            false
+        }
+    }
+}
+
+fn fn_trigonometric<'text_ptr>(
+    f: fn(f64) -> f64,
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+    ctx: &EvalContext,
+) -> bool {
+    if arg_count != 1 || stack.len() < 1 {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return false;
+    }
+    let param = &stack[stack.len() - 1];
+    let radians = match &param.typ {
+        CalcResultType::Number(num) => num.to_f64().map(|it| ctx.angle_mode.to_radians(it)),
+        // angles are stored in radians
+        CalcResultType::Quantity(num, unit)
+            if unit.root == 1 && unit.dimensions == ANGLE_UNIT_DIMENSIONS =>
+        {
+            num.to_f64()
+        }
+        _ => None,
+    };
+    let result = radians
+        .map(f)
+        .and_then(Decimal::from_f64)
+        // get rid of the floating point noise, e.g. cos(π/2)
+        .map(|mut it| {
+            it.rescale(16);
+            it.normalize()
+        });
+    if let Some(result) = result {
+        let token_index = param.get_index_into_tokens();
+        stack.pop();
+        stack.push(CalcResult::new(CalcResultType::Number(result), token_index));
+        true
+    } else {
+        param.set_token_error_flag(tokens);
+        false
+    }
 }
 
 fn fn_pi(arg_count: usize, stack: &mut Vec<CalcResult>, token_index: usize) -> bool {
@@ -72,31 +422,473 @@ fn fn_pi(arg_count: usize, stack: &mut Vec<CalcResult>, token_index: usize) -> b
     true
 }
 
-fn fn_ceil<'text_ptr>(
+fn fn_e(arg_count: usize, stack: &mut Vec<CalcResult>, token_index: usize) -> bool {
+    if arg_count != 0 {
+        return false;
+    }
+
+    stack.push(CalcResult::new(
+        CalcResultType::Number(Decimal::from_str("2.7182818284590452353602874714").unwrap()),
+        token_index,
+    ));
+
+    true
+}
+
+fn fn_abs<'text_ptr>(
     arg_count: usize,
     stack: &mut Vec<CalcResult>,
     tokens: &mut [Token<'text_ptr>],
     fn_token_index: usize,
 ) -> bool {
-    if arg_count < 1 || stack.len() < 1 {
+    if arg_count != 1 || stack.len() < 1 {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return false;
+    }
+    let param = &stack[stack.len() - 1];
+    let result = if let Some(result) = abs_of(param) {
+        result
+    } else {
+        param.set_token_error_flag(tokens);
+        return false;
+    };
+    stack.pop();
+    stack.push(result);
+    true
+}
+
+/// matrices are processed elementwise, e.g. abs([-1, 2]) = [1, 2]
+fn abs_of(param: &CalcResult) -> Option<CalcResult> {
+    let result = match &param.typ {
+        CalcResultType::Number(num) => CalcResultType::Number(num.abs()),
+        CalcResultType::Percentage(num) => CalcResultType::Percentage(num.abs()),
+        // quantities are stored in base units, so it works for e.g. Celsius as well
+        CalcResultType::Quantity(num, unit) => CalcResultType::Quantity(num.abs(), unit.clone()),
+        CalcResultType::Matrix(mat) => mat.map_cells(abs_of)?.typ,
+        _ => return None,
+    };
+    Some(CalcResult::new(result, param.get_index_into_tokens()))
+}
+
+/// powers of two are exact, e.g. log2(8) = 3
+fn fn_log2<'text_ptr>(
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+) -> bool {
+    if arg_count != 1 || stack.len() < 1 {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return false;
+    }
+    let param = &stack[stack.len() - 1];
+    let result = match &param.typ {
+        CalcResultType::Number(num) if num.is_sign_positive() && !num.is_zero() => {
+            match num.to_u64() {
+                Some(int) if num.trunc() == *num && int.is_power_of_two() => {
+                    Some(Decimal::from(int.trailing_zeros()))
+                }
+                _ => num
+                    .to_f64()
+                    .map(|it| it.log2())
+                    .filter(|it| it.is_finite())
+                    .and_then(Decimal::from_f64)
+                    .map(|mut it| {
+                        // get rid of the floating point noise
+                        it.rescale(16);
+                        it.normalize()
+                    }),
+            }
+        }
+        _ => None,
+    };
+    if let Some(result) = result {
+        let token_index = param.get_index_into_tokens();
+        stack.pop();
+        stack.push(CalcResult::new(CalcResultType::Number(result), token_index));
+        true
+    } else {
+        param.set_token_error_flag(tokens);
+        false
+    }
+}
+
+/// the number of bits needed to represent a non-negative integer, e.g. bitlength(255) = 8
+fn fn_bitlength<'text_ptr>(
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+) -> bool {
+    if arg_count != 1 || stack.len() < 1 {
         Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return false;
+    }
+    let param = &stack[stack.len() - 1];
+    let int = match &param.typ {
+        CalcResultType::Number(num) if num.trunc() == *num => num.to_u64(),
+        _ => None,
+    };
+    if let Some(int) = int {
+        let bit_length = 64 - int.leading_zeros();
+        let token_index = param.get_index_into_tokens();
+        stack.pop();
+        stack.push(CalcResult::new(
+            CalcResultType::Number(Decimal::from(bit_length)),
+            token_index,
+        ));
+        true
+    } else {
+        param.set_token_error_flag(tokens);
         false
+    }
+}
+
+/// min(a, b, ...) or min(vector), the arguments can have different but compatible units
+fn fn_min_or_max<'text_ptr>(
+    is_max: bool,
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+) -> bool {
+    if arg_count < 1 || stack.len() < arg_count {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return false;
+    }
+    let args_from = stack.len() - arg_count;
+    let candidates: &[CalcResult] = match (arg_count, &stack[args_from].typ) {
+        (1, CalcResultType::Matrix(mat)) => &mat.cells,
+        _ => &stack[args_from..],
+    };
+    let wanted = if is_max {
+        Ordering::Greater
+    } else {
+        Ordering::Less
+    };
+    if candidates.is_empty() {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return false;
+    }
+    let mut selected = &candidates[0];
+    for candidate in &candidates[1..] {
+        match compare(candidate, selected) {
+            Some(ordering) if ordering == wanted => selected = candidate,
+            Some(_) => {}
+            None => {
+                Token::set_token_error_flag_by_index(fn_token_index, tokens);
+                return false;
+            }
+        }
+    }
+    if candidates.len() == 1 && compare(selected, selected).is_none() {
+        // a single argument which is not comparable, e.g. max(kg)
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return false;
+    }
+    let result = CalcResult::new(selected.typ.clone(), fn_token_index);
+    stack.truncate(args_from);
+    stack.push(result);
+    true
+}
+
+/// sort(vec) or sort(vec, descending), where a non-zero `descending` means descending order
+fn fn_sort<'text_ptr>(
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+) -> bool {
+    if arg_count < 1 || arg_count > 2 || stack.len() < arg_count {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return false;
+    }
+    let args_from = stack.len() - arg_count;
+    let descending = if arg_count == 2 {
+        match &stack[args_from + 1].typ {
+            CalcResultType::Number(flag) => !flag.is_zero(),
+            _ => {
+                stack[args_from + 1].set_token_error_flag(tokens);
+                return false;
+            }
+        }
     } else {
+        false
+    };
+    let mat = match &stack[args_from].typ {
+        CalcResultType::Matrix(mat) if mat.row_count == 1 || mat.col_count == 1 => mat,
+        _ => {
+            Token::set_token_error_flag_by_index(fn_token_index, tokens);
+            return false;
+        }
+    };
+    // e.g. mixed units like [1 kg, 1 m] cannot be sorted
+    let comparable = mat
+        .cells
+        .iter()
+        .all(|cell| compare(cell, &mat.cells[0]).is_some());
+    if !comparable {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return false;
+    }
+    let mut cells = mat.cells.clone();
+    cells.sort_by(|a, b| {
+        let ordering = compare(a, b).unwrap_or(Ordering::Equal);
+        if descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+    let result = MatrixData::new(cells, mat.row_count, mat.col_count);
+    stack.truncate(args_from);
+    stack.push(CalcResult::new(
+        CalcResultType::Matrix(result),
+        fn_token_index,
+    ));
+    true
+}
+
+fn fn_reverse<'text_ptr>(
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+) -> bool {
+    if arg_count != 1 || stack.len() < 1 {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return false;
+    }
+    let result = match &stack[stack.len() - 1].typ {
+        CalcResultType::Matrix(mat) if mat.row_count == 1 || mat.col_count == 1 => {
+            let cells = mat.cells.iter().rev().cloned().collect();
+            MatrixData::new(cells, mat.row_count, mat.col_count)
+        }
+        _ => {
+            Token::set_token_error_flag_by_index(fn_token_index, tokens);
+            return false;
+        }
+    };
+    stack.pop();
+    stack.push(CalcResult::new(
+        CalcResultType::Matrix(result),
+        fn_token_index,
+    ));
+    true
+}
+
+/// Only for plain numbers, angles with units can be converted with `in`, e.g. `90 degree in rad`
+fn fn_deg2rad_or_rad2deg<'text_ptr>(
+    to_rad: bool,
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+) -> bool {
+    if arg_count != 1 || stack.len() < 1 {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return false;
+    }
+    let param = &stack[stack.len() - 1];
+    let result = match &param.typ {
+        CalcResultType::Number(num) => {
+            if to_rad {
+                num.checked_mul(&PI)
+                    .and_then(|it| it.checked_div(&Decimal::from(180)))
+            } else {
+                num.checked_mul(&Decimal::from(180))
+                    .and_then(|it| it.checked_div(&PI))
+            }
+        }
+        _ => None,
+    };
+    if let Some(result) = result {
+        let token_index = param.get_index_into_tokens();
+        stack.pop();
+        stack.push(CalcResult::new(CalcResultType::Number(result), token_index));
+        true
+    } else {
+        param.set_token_error_flag(tokens);
+        false
+    }
+}
+
+/// normalizing a zero vector is an error
+fn fn_norm_or_normalize<'text_ptr>(
+    normalize: bool,
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+) -> bool {
+    if arg_count != 1 || stack.len() < 1 {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return false;
+    }
+    let result = match &stack[stack.len() - 1].typ {
+        CalcResultType::Matrix(mat) => vector_norm(mat).and_then(|norm| {
+            if !normalize {
+                Some(norm)
+            } else if is_zero(&norm) {
+                None
+            } else {
+                mat.map_cells(|cell| divide_op(cell, &norm))
+            }
+        }),
+        _ => None,
+    };
+    if let Some(result) = result {
+        stack.pop();
+        stack.push(CalcResult::new(result.typ, fn_token_index));
+        true
+    } else {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        false
+    }
+}
+
+fn vector_norm(mat: &MatrixData) -> Option<CalcResult> {
+    if mat.row_count != 1 && mat.col_count != 1 {
+        return None;
+    }
+    let first = mat.cells.first()?;
+    let mut sum_of_squares = multiply_op(first, first)?;
+    for cell in mat.cells.iter().skip(1) {
+        sum_of_squares = add_op(&sum_of_squares, &multiply_op(cell, cell)?)?;
+    }
+    sqrt_op(&sum_of_squares)
+}
+
+fn is_zero(value: &CalcResult) -> bool {
+    match &value.typ {
+        CalcResultType::Number(num) | CalcResultType::Quantity(num, _) => num.is_zero(),
+        _ => false,
+    }
+}
+
+fn fn_first_or_last<'text_ptr>(
+    last: bool,
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+) -> bool {
+    if arg_count != 1 || stack.len() < 1 {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return false;
+    }
+    let result = match &stack[stack.len() - 1].typ {
+        CalcResultType::Matrix(mat) if mat.row_count == 1 || mat.col_count == 1 => {
+            if last {
+                mat.cells.last()
+            } else {
+                mat.cells.first()
+            }
+        }
+        _ => None,
+    };
+    let result = if let Some(result) = result {
+        result.typ.clone()
+    } else {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return false;
+    };
+    stack.pop();
+    stack.push(CalcResult::new(result, fn_token_index));
+    true
+}
+
+fn fn_floor_or_ceil<'text_ptr>(
+    ceil: bool,
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+) -> bool {
+    if arg_count < 1 || stack.len() < 1 {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return false;
+    }
+    let param = &stack[stack.len() - 1];
+    let result = if ceil {
+        round_keeping_unit(param, |it| it.ceil())
+    } else {
+        round_keeping_unit(param, |it| it.floor())
+    };
+    if let Some(result) = result {
+        let token_index = param.get_index_into_tokens();
+        stack.pop();
+        stack.push(CalcResult::new(result, token_index));
+        true
+    } else {
+        param.set_token_error_flag(tokens);
+        false
+    }
+}
+
+/// Quantities are rounded in their own unit and not in the base unit,
+/// e.g. round(2.4 km) = 2 km and not 2400 m, and round(3.6 km/h) = 4 km/h.
+/// Percentages remain percentages, e.g. round(33.333%) = 33%.
+fn round_keeping_unit(
+    param: &CalcResult,
+    round: impl Fn(&Decimal) -> Decimal,
+) -> Option<CalcResultType> {
+    match &param.typ {
+        CalcResultType::Number(num) => Some(CalcResultType::Number(round(num))),
+        CalcResultType::Percentage(num) => Some(CalcResultType::Percentage(round(num))),
+        CalcResultType::Quantity(num, unit) => {
+            let num = unit.from_base_to_this_unit(num)?;
+            let rounded = unit.normalize(&round(&num))?;
+            Some(CalcResultType::Quantity(rounded, unit.clone()))
+        }
+        _ => None,
+    }
+}
+
+/// ties are broken according to the `RoundingMode` of the evaluation
+fn fn_round<'text_ptr>(
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+    ctx: &EvalContext,
+) -> bool {
+    if arg_count < 1 || stack.len() < arg_count {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return false;
+    }
+    let decimal_places = if arg_count == 2 {
         let param = &stack[stack.len() - 1];
         match &param.typ {
-            CalcResultType::Number(num) => {
-                let result = num.ceil();
-                let token_index = param.get_index_into_tokens();
-                stack.pop();
-                stack.push(CalcResult::new(CalcResultType::Number(result), token_index));
-                true
+            CalcResultType::Number(num) if num.trunc() == *num => {
+                if let Some(decimal_places) = num.to_u32() {
+                    decimal_places
+                } else {
+                    param.set_token_error_flag(tokens);
+                    return false;
+                }
             }
             _ => {
                 param.set_token_error_flag(tokens);
-                false
+                return false;
             }
         }
-    }
+    } else {
+        0
+    };
+    let param = &stack[stack.len() - arg_count];
+    let result = if let Some(result) =
+        round_keeping_unit(param, |it| ctx.rounding_mode.round_dp(it, decimal_places))
+    {
+        result
+    } else {
+        param.set_token_error_flag(tokens);
+        return false;
+    };
+    let token_index = param.get_index_into_tokens();
+    stack.truncate(stack.len() - arg_count);
+    stack.push(CalcResult::new(result, token_index));
+    true
 }
 
 fn fn_nth<'text_ptr>(
@@ -145,6 +937,61 @@ fn fn_nth<'text_ptr>(
     }
 }
 
+fn fn_row_or_col<'text_ptr>(
+    is_row: bool,
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+) -> bool {
+    if arg_count < 2 || stack.len() < 2 {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        false
+    } else {
+        let index_token = &stack[stack.len() - 1];
+        let mat_token = &stack[stack.len() - 2];
+        match (&index_token.typ, &mat_token.typ) {
+            (CalcResultType::Number(n), CalcResultType::Matrix(mat)) => {
+                let max_index = if is_row { mat.row_count } else { mat.col_count };
+                if let Some(index) = n.to_u32().filter(|it| (*it as usize) < max_index) {
+                    let index = index as usize;
+                    let result = if is_row {
+                        let cells = (0..mat.col_count)
+                            .map(|col| mat.cell(index, col).clone())
+                            .collect();
+                        MatrixData::new(cells, 1, mat.col_count)
+                    } else {
+                        let cells = (0..mat.row_count)
+                            .map(|row| mat.cell(row, index).clone())
+                            .collect();
+                        MatrixData::new(cells, mat.row_count, 1)
+                    };
+                    let token_index = mat_token.get_index_into_tokens();
+                    stack.truncate(stack.len() - 2);
+                    stack.push(CalcResult::new(CalcResultType::Matrix(result), token_index));
+                    true
+                } else {
+                    index_token.set_token_error_flag(tokens);
+                    false
+                }
+            }
+            (CalcResultType::Number(_), _) => {
+                mat_token.set_token_error_flag(tokens);
+                false
+            }
+            (_, CalcResultType::Matrix(_)) => {
+                index_token.set_token_error_flag(tokens);
+                false
+            }
+            _ => {
+                index_token.set_token_error_flag(tokens);
+                mat_token.set_token_error_flag(tokens);
+                false
+            }
+        }
+    }
+}
+
 fn fn_sum(arg_count: usize, stack: &mut Vec<CalcResult>) -> bool {
     if arg_count < 1 {
         false
@@ -169,6 +1016,276 @@ fn fn_sum(arg_count: usize, stack: &mut Vec<CalcResult>) -> bool {
     }
 }
 
+fn is_numeric(result: &CalcResult) -> bool {
+    matches!(
+        result.typ,
+        CalcResultType::Number(..) | CalcResultType::Quantity(..) | CalcResultType::Percentage(..)
+    )
+}
+
+fn fn_count(arg_count: usize, stack: &mut Vec<CalcResult>, fn_token_index: usize) -> bool {
+    if arg_count < 1 || stack.len() < arg_count {
+        return false;
+    }
+    let args = &stack[stack.len() - arg_count..];
+    let count = match (arg_count, &args[0].typ) {
+        // count(vector)
+        (1, CalcResultType::Matrix(mat)) => mat.cells.iter().filter(|it| is_numeric(it)).count(),
+        // count(a, b, c, ...), non-numeric arguments are skipped
+        _ => args.iter().filter(|it| is_numeric(it)).count(),
+    };
+    stack.truncate(stack.len() - arg_count);
+    stack.push(CalcResult::new(
+        CalcResultType::Number(Decimal::from(count)),
+        fn_token_index,
+    ));
+    true
+}
+
+fn mean(nums: &[Decimal]) -> Option<Decimal> {
+    let mut sum = Decimal::zero();
+    for num in nums {
+        sum = sum.checked_add(num)?;
+    }
+    sum.checked_div(&Decimal::from(nums.len()))
+}
+
+fn median(nums: &mut [Decimal]) -> Option<Decimal> {
+    nums.sort();
+    let mid = nums.len() / 2;
+    if nums.len() % 2 == 1 {
+        Some(nums[mid].clone())
+    } else {
+        mean(&nums[mid - 1..=mid])
+    }
+}
+
+/// population variance
+fn variance(nums: &[Decimal]) -> Option<Decimal> {
+    let mean = mean(nums)?;
+    let mut squared_diffs = Vec::with_capacity(nums.len());
+    for num in nums {
+        let diff = num.checked_sub(&mean)?;
+        squared_diffs.push(diff.checked_mul(&diff)?);
+    }
+    self::mean(&squared_diffs)
+}
+
+fn fn_statistics<'text_ptr>(
+    fn_type: FnType,
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+) -> bool {
+    if arg_count < 1 || stack.len() < 1 {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return false;
+    }
+    let param = &stack[stack.len() - 1];
+    let mut nums: Vec<Decimal> = match &param.typ {
+        CalcResultType::Matrix(mat) if !mat.cells.is_empty() => {
+            let nums: Option<Vec<Decimal>> = mat
+                .cells
+                .iter()
+                .map(|cell| match &cell.typ {
+                    CalcResultType::Number(num) => Some(num.clone()),
+                    _ => None,
+                })
+                .collect();
+            if let Some(nums) = nums {
+                nums
+            } else {
+                param.set_token_error_flag(tokens);
+                return false;
+            }
+        }
+        _ => {
+            param.set_token_error_flag(tokens);
+            return false;
+        }
+    };
+    let result = match fn_type {
+        FnType::Mean => mean(&nums),
+        FnType::Median => median(&mut nums),
+        FnType::Variance => variance(&nums),
+        FnType::Stddev => variance(&nums)
+            .and_then(|it| it.to_f64())
+            .and_then(|it| Decimal::from_f64(it.sqrt())),
+        _ => None,
+    };
+    if let Some(result) = result {
+        let token_index = param.get_index_into_tokens();
+        stack.pop();
+        stack.push(CalcResult::new(CalcResultType::Number(result), token_index));
+        true
+    } else {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        false
+    }
+}
+
+fn fn_hadamard<'text_ptr>(
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+) -> bool {
+    if arg_count != 2 || stack.len() < 2 {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return false;
+    }
+    let lhs = &stack[stack.len() - 2];
+    let rhs = &stack[stack.len() - 1];
+    let result = match (&lhs.typ, &rhs.typ) {
+        (CalcResultType::Matrix(a), CalcResultType::Matrix(b)) => a.hadamard(b),
+        _ => None,
+    };
+    if let Some(result) = result {
+        stack.truncate(stack.len() - 2);
+        stack.push(CalcResult::new(
+            CalcResultType::Matrix(result),
+            fn_token_index,
+        ));
+        true
+    } else {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        false
+    }
+}
+
+fn fn_solve<'text_ptr>(
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+) -> bool {
+    if arg_count != 2 || stack.len() < 2 {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return false;
+    }
+    let a = &stack[stack.len() - 2];
+    let b = &stack[stack.len() - 1];
+    let result = match (&a.typ, &b.typ) {
+        (CalcResultType::Matrix(a), CalcResultType::Matrix(b)) => a.solve(b),
+        _ => None,
+    };
+    if let Some(result) = result {
+        stack.truncate(stack.len() - 2);
+        stack.push(CalcResult::new(
+            CalcResultType::Matrix(result),
+            fn_token_index,
+        ));
+        true
+    } else {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        false
+    }
+}
+
+/// Incomparable arguments (e.g. matrices of different shapes or quantities of different
+/// dimensions) are not equal, it is not an error.
+/// The tolerance of quantities is in base units, e.g. equal(1 km, 1001 m, 1) is true.
+fn fn_equal<'text_ptr>(
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+) -> bool {
+    if arg_count < 2 || stack.len() < arg_count {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return false;
+    }
+    let tolerance = if arg_count == 3 {
+        let param = &stack[stack.len() - 1];
+        match &param.typ {
+            CalcResultType::Number(num) if !num.is_sign_negative() => num.clone(),
+            _ => {
+                param.set_token_error_flag(tokens);
+                return false;
+            }
+        }
+    } else {
+        Decimal::zero()
+    };
+    let a = &stack[stack.len() - arg_count];
+    let b = &stack[stack.len() - arg_count + 1];
+    let result = bool_result(are_equal(a, b, &tolerance));
+    stack.truncate(stack.len() - arg_count);
+    stack.push(CalcResult::new(result, fn_token_index));
+    true
+}
+
+fn are_equal(a: &CalcResult, b: &CalcResult, tolerance: &Decimal) -> bool {
+    let within_tolerance = |a: &Decimal, b: &Decimal| {
+        a.checked_sub(b)
+            .map(|diff| diff.abs() <= *tolerance)
+            .unwrap_or(false)
+    };
+    match (&a.typ, &b.typ) {
+        (CalcResultType::Matrix(a), CalcResultType::Matrix(b)) => {
+            a.row_count == b.row_count
+                && a.col_count == b.col_count
+                && a.cells
+                    .iter()
+                    .zip(b.cells.iter())
+                    .all(|(a, b)| are_equal(a, b, tolerance))
+        }
+        (CalcResultType::Number(a), CalcResultType::Number(b))
+        | (CalcResultType::Percentage(a), CalcResultType::Percentage(b)) => within_tolerance(a, b),
+        (CalcResultType::Quantity(a, a_unit), CalcResultType::Quantity(b, b_unit)) => {
+            a_unit.dimensions == b_unit.dimensions && within_tolerance(a, b)
+        }
+        _ => false,
+    }
+}
+
+fn fn_pow<'text_ptr>(
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+) -> bool {
+    if arg_count != 2 || stack.len() < 2 {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return false;
+    }
+    let base = &stack[stack.len() - 2];
+    let exp = &stack[stack.len() - 1];
+    // the same rules as for the '^' operator
+    if let Some(result) = pow_op(base, exp) {
+        let token_index = base.get_index_into_tokens();
+        stack.truncate(stack.len() - 2);
+        stack.push(CalcResult::new(result.typ, token_index));
+        true
+    } else {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        false
+    }
+}
+
+fn fn_sqrt<'text_ptr>(
+    arg_count: usize,
+    stack: &mut Vec<CalcResult>,
+    tokens: &mut [Token<'text_ptr>],
+    fn_token_index: usize,
+) -> bool {
+    if arg_count != 1 || stack.len() < 1 {
+        Token::set_token_error_flag_by_index(fn_token_index, tokens);
+        return false;
+    }
+    let param = &stack[stack.len() - 1];
+    // the same rules as for the '√' operator
+    if let Some(result) = sqrt_op(param) {
+        stack.pop();
+        stack.push(result);
+        true
+    } else {
+        param.set_token_error_flag(tokens);
+        false
+    }
+}
+
 fn fn_transpose(arg_count: usize, stack: &mut Vec<CalcResult>) -> bool {
     if arg_count < 1 {
         false
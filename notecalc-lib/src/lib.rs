@@ -13,19 +13,24 @@
     clippy::all
 )]
 
+use std::collections::HashMap;
 use std::io::Cursor;
 use std::mem::MaybeUninit;
 use std::ops::Range;
+use std::str::FromStr;
 use std::time::Duration;
 
 use bumpalo::Bump;
+use rust_decimal::prelude::{FromPrimitive, One, Zero};
+use rust_decimal::Decimal;
 use smallvec::SmallVec;
 use strum_macros::EnumDiscriminants;
 
 use helper::*;
 
 use crate::calc::{
-    add_op, evaluate_tokens, CalcResult, CalcResultType, EvaluationResult, ShuntingYardResult,
+    add_op, divide_op, evaluate_tokens, sub_op, AngleMode, CalcResult, CalcResultType, EvalContext,
+    EvaluationResult, RoundingMode, ShuntingYardResult,
 };
 use crate::consts::{LINE_NUM_CONSTS, LINE_NUM_CONSTS2, LINE_NUM_CONSTS3};
 use crate::editor::editor::{
@@ -33,11 +38,19 @@ use crate::editor::editor::{
 };
 use crate::editor::editor_content::EditorContent;
 use crate::matrix::MatrixData;
-use crate::renderer::{get_int_frac_part_len, render_result, render_result_into};
+use crate::renderer::{
+    format_result, get_int_frac_part_len, render_result, render_result_into, RenderConfig,
+};
 use crate::shunting_yard::ShuntingYard;
-use crate::token_parser::{OperatorTokenType, Token, TokenParser, TokenType};
+use crate::token_parser::{
+    line_is_text_only, OperatorTokenType, ParseOptions, Token, TokenParser, TokenType,
+};
 use crate::units::units::Units;
 
+use crate::functions::FnType;
+pub use crate::functions::{all_functions, CustomFn, FnInfo, FnRegistry};
+pub use crate::token_parser::tokens_to_string;
+
 mod functions;
 mod matrix;
 mod shunting_yard;
@@ -65,6 +78,10 @@ const REFERENCE_PULSE_PULSE_START_COLOR: u32 = 0x00FF7F_33;
 const MIN_RESULT_PANEL_WIDTH: usize = 7;
 const DEFAULT_RESULT_PANEL_WIDTH_PERCENT: usize = 30;
 const SUM_VARIABLE_INDEX: usize = MAX_LINE_COUNT;
+const AVG_VARIABLE_INDEX: usize = MAX_LINE_COUNT + 1;
+const COUNT_VARIABLE_INDEX: usize = MAX_LINE_COUNT + 2;
+/// the variables of the lines followed by `sum`, `avg` and `count`
+pub const VARIABLE_COUNT: usize = MAX_LINE_COUNT + 3;
 const MATRIX_ASCII_HEADER_FOOTER_LINE_COUNT: usize = 2;
 const ACTIVE_LINE_REF_HIGHLIGHT_COLORS: [u32; 9] = [
     0xFFD300, 0xDE3163, 0x73c2fb, 0xc7ea46, 0x702963, 0x997950, 0x777b73, 0xFC6600, 0xED2939,
@@ -96,12 +113,9 @@ pub mod helper {
     use crate::calc::CalcResultType;
     pub use crate::{MAX_LINE_COUNT, *};
 
-    pub fn create_vars() -> [Option<Variable>; MAX_LINE_COUNT + 1] {
-        let mut vars = [None; MAX_LINE_COUNT + 1];
-        vars[SUM_VARIABLE_INDEX] = Some(Variable {
-            name: Box::from(&['s', 'u', 'm'][..]),
-            value: Err(()),
-        });
+    pub fn create_vars() -> [Option<Variable>; VARIABLE_COUNT] {
+        let mut vars = [None; VARIABLE_COUNT];
+        init_section_aggregate_vars(&mut vars);
         return vars;
     }
 
@@ -806,6 +820,8 @@ pub enum ResultFormat {
     Bin,
     Dec,
     Hex,
+    /// engineering notation, e.g. 12.3e3
+    Eng,
 }
 
 #[derive(Clone, Debug)]
@@ -1141,15 +1157,20 @@ pub struct EditorObject {
 pub struct Variable {
     pub name: Box<[char]>,
     pub value: Result<CalcResult, ()>,
+    /// The line has no result (e.g. it is blank or a comment), the variable only exists
+    /// so the references to the line can be resolved. Its value is always an error.
+    pub placeholder: bool,
 }
 
-type LineResult = Result<Option<CalcResult>, ()>;
+pub type LineResult = Result<Option<CalcResult>, ()>;
 type Variables = [Option<Variable>];
 
 #[derive(Debug)]
 pub struct Tokens<'a> {
     tokens: Vec<Token<'a>>,
     shunting_output_stack: Vec<ShuntingYardResult>,
+    /// the line is a `>> deg` like directive
+    angle_mode_directive: Option<AngleMode>,
 }
 
 pub enum MouseClickType {
@@ -1190,6 +1211,10 @@ pub struct NoteCalcApp {
     pub render_data: GlobalRenderData,
     // when pressing Ctrl-c without any selection, the result of the current line will be put into this clipboard
     pub clipboard: Option<String>,
+    // if true, the result of a header line is the sum of its section
+    pub show_header_sums: bool,
+    // evaluation settings (e.g. signedness of hex literals) and the functions registered by the host
+    pub eval_context: EvalContext,
 }
 
 pub const EMPTY_FILE_DEFUALT_CONTENT: &str = "\n\n\n\n\n\n\n\n\n\n";
@@ -1217,6 +1242,8 @@ impl NoteCalcApp {
                 RIGHT_GUTTER_WIDTH,
             ),
             clipboard: None,
+            show_header_sums: false,
+            eval_context: EvalContext::default(),
         }
     }
 
@@ -1267,10 +1294,7 @@ impl NoteCalcApp {
         for v in vars.iter_mut() {
             *v = None;
         }
-        vars[SUM_VARIABLE_INDEX] = Some(Variable {
-            name: Box::from(&['s', 'u', 'm'][..]),
-            value: Err(()),
-        });
+        init_section_aggregate_vars(vars);
         self.render_data.clear();
         self.editor_objs_referencing_current_line.clear();
         self.process_and_render_tokens(
@@ -1327,6 +1351,7 @@ impl NoteCalcApp {
         updated_line_ref_obj_indices: &[EditorObjId],
         editor_objs_referencing_current_line: &mut Vec<EditorObjId>,
         mouse_hover_type: MouseHoverType,
+        ctx: &EvalContext,
     ) {
         gr.longest_visible_editor_line_len = 0;
         // x, h
@@ -1394,6 +1419,7 @@ impl NoteCalcApp {
                         &units,
                         need_matrix_renderer,
                         Some(RENDERED_RESULT_PRECISION),
+                        ctx.rounding_mode,
                     );
                     // don't highlight refs in the current row as they will be pulsing in different colors
                     if editor.get_selection().get_cursor_pos().row != r.editor_y.as_usize() {
@@ -1574,6 +1600,7 @@ impl NoteCalcApp {
             &gr,
             vars,
             allocator,
+            ctx,
         );
 
         let mut tmp = ResultRender::new(SmallVec::with_capacity(MAX_LINE_COUNT));
@@ -1585,6 +1612,7 @@ impl NoteCalcApp {
             &editor_content,
             gr,
             Some(RENDERED_RESULT_PRECISION),
+            ctx.rounding_mode,
         );
         tmp.max_len = create_render_commands_for_results_and_render_matrices(
             &tmp,
@@ -1593,6 +1621,7 @@ impl NoteCalcApp {
             render_buckets,
             gr,
             Some(RENDERED_RESULT_PRECISION),
+            ctx.rounding_mode,
         )
         .max(tmp.max_len);
         gr.longest_visible_result_len = tmp.max_len;
@@ -2501,7 +2530,7 @@ impl NoteCalcApp {
                     let new_format = match &app.editor_content.get_data(row_i).result_format {
                         ResultFormat::Bin => ResultFormat::Hex,
                         ResultFormat::Dec => ResultFormat::Bin,
-                        ResultFormat::Hex => ResultFormat::Dec,
+                        ResultFormat::Hex | ResultFormat::Eng => ResultFormat::Dec,
                     };
                     app.editor_content.mut_data(row_i).result_format = new_format;
                 }
@@ -2511,7 +2540,7 @@ impl NoteCalcApp {
                 let (start, end) = selection.get_range();
                 for row_i in start.row..=end.row {
                     let new_format = match &app.editor_content.get_data(row_i).result_format {
-                        ResultFormat::Bin => ResultFormat::Dec,
+                        ResultFormat::Bin | ResultFormat::Eng => ResultFormat::Dec,
                         ResultFormat::Dec => ResultFormat::Hex,
                         ResultFormat::Hex => ResultFormat::Bin,
                     };
@@ -2595,6 +2624,7 @@ impl NoteCalcApp {
                     &self.editor_content.get_data(row).result_format,
                     false,
                     Some(RENDERED_RESULT_PRECISION),
+                    self.eval_context.rounding_mode,
                     true,
                 ));
             }
@@ -2698,6 +2728,56 @@ impl NoteCalcApp {
         return modif;
     }
 
+    /// Replaces the content of a single line and reevaluates it along with the lines
+    /// depending on it.
+    /// Returns the indices of the lines below `line_index` whose result has changed,
+    /// so only those have to be repainted.
+    pub fn reevaluate_line<'b>(
+        &mut self,
+        line_index: usize,
+        new_text: &str,
+        units: &Units,
+        allocator: &'b Bump,
+        tokens: &mut AppTokens<'b>,
+        results: &mut Results,
+        vars: &mut Variables,
+        editor_objs: &mut EditorObjects,
+        render_buckets: &mut RenderBuckets<'b>,
+    ) -> Vec<usize> {
+        if line_index >= self.editor_content.line_count() {
+            return Vec::new();
+        }
+        self.editor_content.set_line(line_index, new_text);
+        // the new line might be shorter than the cursor or the selection on it
+        let line_len = self.editor_content.line_len(line_index);
+        let clamp = |pos: Pos| {
+            if pos.row == line_index && pos.column > line_len {
+                pos.with_column(line_len)
+            } else {
+                pos
+            }
+        };
+        let selection = self.editor.get_selection();
+        self.editor.set_selection_save_col(match selection.end {
+            Some(end) => Selection::range(clamp(selection.start), clamp(end)),
+            None => Selection::single(clamp(selection.start)),
+        });
+        let result_change_flag = self.process_and_render_tokens(
+            RowModificationType::SingleLine(line_index),
+            units,
+            allocator,
+            tokens,
+            results,
+            vars,
+            editor_objs,
+            render_buckets,
+        );
+        return (line_index + 1..self.editor_content.line_count().min(MAX_LINE_COUNT))
+            .filter(|it| result_change_flag.is_true(*it))
+            .collect();
+    }
+
+    /// Returns the lines whose result has changed.
     pub fn process_and_render_tokens<'b>(
         &mut self,
         input_effect: RowModificationType,
@@ -2708,7 +2788,7 @@ impl NoteCalcApp {
         vars: &mut Variables,
         editor_objs: &mut EditorObjects,
         render_buckets: &mut RenderBuckets<'b>,
-    ) {
+    ) -> BitFlag128 {
         fn eval_line<'a>(
             editor_content: &EditorContent<LineData>,
             line: &[char],
@@ -2719,25 +2799,92 @@ impl NoteCalcApp {
             vars: &mut Variables,
             editor_y: ContentIndex,
             updated_line_ref_obj_indices: &mut Vec<EditorObjId>,
+            continued_prefix: &[char],
+            ctx: &EvalContext,
+            angle_mode: AngleMode,
         ) -> (bool, BitFlag128) {
             // TODO avoid clone
             let prev_var_name = vars[editor_y.as_usize()].as_ref().map(|it| it.name.clone());
+            // the angle mode directives above the line override the default one
+            let line_ctx;
+            let ctx = if angle_mode == ctx.angle_mode {
+                ctx
+            } else {
+                line_ctx = EvalContext {
+                    angle_mode,
+                    ..ctx.clone()
+                };
+                &line_ctx
+            };
+            let is_directive = angle_mode_directive(line).is_some();
 
-            tokens_per_lines[editor_y] = Some(parse_tokens(
-                line,
-                editor_y.as_usize(),
-                units,
-                &*vars,
-                allocator,
-            ));
-            let new_result = if let Some(tokens) = &mut tokens_per_lines[editor_y] {
+            let is_last_line_of_continuation =
+                !continued_prefix.is_empty() && !ends_with_line_continuation(line) && !is_directive;
+            if !is_last_line_of_continuation {
+                tokens_per_lines[editor_y] = Some(parse_tokens(
+                    line,
+                    editor_y.as_usize(),
+                    units,
+                    &*vars,
+                    &ctx.fns,
+                    ctx.parse_options(),
+                    allocator,
+                ));
+            }
+            let new_result = if ends_with_line_continuation(line) || is_directive {
+                // the expression continues on the next line, it is evaluated there
+                // (directives have no result either)
+                evaluate_tokens_and_save_result(
+                    &mut *vars,
+                    editor_y.as_usize(),
+                    editor_content.get_data(editor_y.as_usize()).line_id,
+                    &mut [],
+                    &mut Vec::new(),
+                    line,
+                    units,
+                    ctx,
+                )
+                .map(|_| None)
+            } else if is_last_line_of_continuation {
+                // the whole logical line is evaluated, but only the tokens
+                // of the physical line are kept for rendering
+                let logical_line = [continued_prefix, line].concat();
+                let mut logical_tokens = parse_tokens(
+                    &logical_line,
+                    editor_y.as_usize(),
+                    units,
+                    &*vars,
+                    &ctx.fns,
+                    ctx.parse_options(),
+                    allocator,
+                );
+                let result = evaluate_tokens_and_save_result(
+                    &mut *vars,
+                    editor_y.as_usize(),
+                    editor_content.get_data(editor_y.as_usize()).line_id,
+                    &mut logical_tokens.tokens,
+                    &mut logical_tokens.shunting_output_stack,
+                    &logical_line,
+                    units,
+                    ctx,
+                );
+                tokens_per_lines[editor_y] = Some(Tokens {
+                    tokens: tokens_after(logical_tokens.tokens, continued_prefix.len()),
+                    // the line is always evaluated as part of its logical line
+                    shunting_output_stack: Vec::new(),
+                    angle_mode_directive: None,
+                });
+                result.map(|it| it.map(|it| it.result))
+            } else if let Some(tokens) = &mut tokens_per_lines[editor_y] {
                 let result = evaluate_tokens_and_save_result(
                     &mut *vars,
                     editor_y.as_usize(),
-                    editor_content,
+                    editor_content.get_data(editor_y.as_usize()).line_id,
                     &mut tokens.tokens,
                     &mut tokens.shunting_output_stack,
                     editor_content.get_line_valid_chars(editor_y.as_usize()),
+                    units,
+                    ctx,
                 );
                 let result = result.map(|it| it.map(|it| it.result));
                 result
@@ -2787,9 +2934,35 @@ impl NoteCalcApp {
                 tokens_per_lines,
                 editor_y.as_usize(),
             ));
+            rows_to_recalc.merge(find_line_ranges_containing(
+                tokens_per_lines,
+                editor_y.as_usize(),
+            ));
             return (result_has_changed, rows_to_recalc);
         }
 
+        fn find_line_ranges_containing(
+            tokens_per_lines: &AppTokens,
+            editor_y: usize,
+        ) -> BitFlag128 {
+            let mut rows_to_recalc = BitFlag128::empty();
+            for (line_index, tokens) in tokens_per_lines.iter().skip(editor_y + 1).enumerate() {
+                if let Some(tokens) = tokens {
+                    let contains = tokens.tokens.iter().any(|token| match token.typ {
+                        TokenType::LineRange {
+                            from_var_index,
+                            to_var_index,
+                        } => (from_var_index..=to_var_index).contains(&editor_y),
+                        _ => false,
+                    });
+                    if contains {
+                        rows_to_recalc.merge(BitFlag128::single_row(editor_y + 1 + line_index));
+                    }
+                }
+            }
+            return rows_to_recalc;
+        }
+
         fn find_sum_variable_name(tokens_per_lines: &AppTokens, editor_y: usize) -> BitFlag128 {
             let mut rows_to_recalc = BitFlag128::empty();
             'outer: for (line_index, tokens) in
@@ -2802,7 +2975,7 @@ impl NoteCalcApp {
                                 break 'outer;
                             }
                             TokenType::Variable { var_index }
-                                if var_index == SUM_VARIABLE_INDEX =>
+                                if is_section_aggregate_var(var_index) =>
                             {
                                 rows_to_recalc
                                     .merge(BitFlag128::single_row(editor_y + 1 + line_index));
@@ -2900,9 +3073,52 @@ impl NoteCalcApp {
             return rows_to_recalc;
         }
 
+        /// Makes `sum` the result of the header line, so it is also the value of the references
+        /// to the header. Returns whether the result has changed and the rows depending on it.
+        fn set_header_sum<'b>(
+            editor_content: &EditorContent<LineData>,
+            header_y: usize,
+            sum: LineResult,
+            tokens_per_lines: &AppTokens<'b>,
+            results: &mut Results,
+            vars: &mut Variables,
+            updated_line_ref_obj_indices: &mut Vec<EditorObjId>,
+        ) -> (bool, BitFlag128) {
+            let y = content_y(header_y);
+            let result_has_changed = match (&results[y], &sum) {
+                (Ok(Some(prev_r)), Ok(Some(new_r))) => prev_r.typ != new_r.typ,
+                (Ok(None), Ok(None)) | (Err(_), Err(_)) => false,
+                _ => true,
+            };
+            let line_ref_name = NoteCalcApp::get_line_ref_name(editor_content, header_y);
+            vars[header_y] = Some(Variable {
+                name: Box::from(&line_ref_name[..]),
+                value: match &sum {
+                    Ok(Some(sum)) => Ok(sum.clone()),
+                    Ok(None) | Err(_) => Err(()),
+                },
+                placeholder: matches!(sum, Ok(None)),
+            });
+            results[y] = sum;
+            let rows_to_recalc = if result_has_changed {
+                NoteCalcApp::find_line_ref_dependant_lines(
+                    &line_ref_name,
+                    tokens_per_lines,
+                    header_y,
+                    updated_line_ref_obj_indices,
+                )
+            } else {
+                BitFlag128::empty()
+            };
+            return (result_has_changed, rows_to_recalc);
+        }
+
         let mut sum_is_null = true;
+        let mut section_header_y: Option<usize> = None;
         let mut dependant_rows = BitFlag128::empty();
         let mut result_change_flag = BitFlag128::empty();
+        // the angle mode directives of the document override the default line by line
+        let mut angle_mode = self.eval_context.angle_mode;
         for editor_y in 0..self.editor_content.line_count().min(MAX_LINE_COUNT) {
             let recalc = match input_effect {
                 RowModificationType::SingleLine(to_change_index) if to_change_index == editor_y => {
@@ -2922,6 +3138,31 @@ impl NoteCalcApp {
                 }
                 let y = content_y(editor_y);
 
+                let mut continued_prefix: Vec<char> = Vec::new();
+                for prev_y in (0..editor_y).rev() {
+                    let prev_line = self.editor_content.get_line_valid_chars(prev_y);
+                    if !ends_with_line_continuation(prev_line) {
+                        break;
+                    }
+                    let without_backslash = prev_line
+                        .iter()
+                        .rposition(|it| *it == '\\')
+                        .map(|it| &prev_line[0..it])
+                        .unwrap_or(prev_line);
+                    let mut joined = without_backslash.to_vec();
+                    joined.push(' ');
+                    joined.extend_from_slice(&continued_prefix);
+                    continued_prefix = joined;
+                }
+                let was_continued = tokens[y]
+                    .as_ref()
+                    .map(|it| tokens_end_with_line_continuation(&it.tokens))
+                    .unwrap_or(false);
+                let was_angle_mode_directive = tokens[y]
+                    .as_ref()
+                    .map(|it| it.angle_mode_directive.is_some())
+                    .unwrap_or(false);
+
                 let (result_has_changed, rows_to_recalc) = eval_line(
                     &self.editor_content,
                     self.editor_content.get_line_valid_chars(editor_y),
@@ -2932,36 +3173,108 @@ impl NoteCalcApp {
                     &mut *vars,
                     y,
                     &mut self.updated_line_ref_obj_indices,
+                    &continued_prefix,
+                    &self.eval_context,
+                    angle_mode,
                 );
                 if result_has_changed {
                     result_change_flag.merge(BitFlag128::single_row(editor_y));
                 }
                 dependant_rows.merge(rows_to_recalc);
+                let is_continued =
+                    ends_with_line_continuation(self.editor_content.get_line_valid_chars(editor_y));
+                if (was_continued || is_continued) && editor_y + 1 < MAX_LINE_COUNT {
+                    // the next line is (or was) the continuation of this one
+                    dependant_rows.merge(BitFlag128::single_row(editor_y + 1));
+                }
+                let is_angle_mode_directive = tokens[y]
+                    .as_ref()
+                    .map(|it| it.angle_mode_directive.is_some())
+                    .unwrap_or(false);
+                if was_angle_mode_directive || is_angle_mode_directive {
+                    dependant_rows.merge(BitFlag128::all_rows_starting_at(editor_y + 1));
+                }
                 let new_h = calc_rendered_height(y, &self.matrix_editing, tokens, results, vars);
                 self.render_data.set_rendered_height(y, new_h);
             }
+            if let Some(line_angle_mode) = tokens[content_y(editor_y)]
+                .as_ref()
+                .and_then(|it| it.angle_mode_directive)
+            {
+                angle_mode = line_angle_mode;
+            }
             if self
                 .editor_content
                 .get_line_valid_chars(editor_y)
                 .starts_with(&['#'])
             {
+                if self.show_header_sums {
+                    // the sum of the previous section is complete, so it is set before
+                    // the lines after this header are evaluated (they might refer to it)
+                    if let Some(header_y) = section_header_y {
+                        let (result_has_changed, rows_to_recalc) = set_header_sum(
+                            &self.editor_content,
+                            header_y,
+                            section_sum(vars, sum_is_null),
+                            tokens,
+                            results,
+                            vars,
+                            &mut self.updated_line_ref_obj_indices,
+                        );
+                        if result_has_changed {
+                            result_change_flag.merge(BitFlag128::single_row(header_y));
+                            let y = content_y(header_y);
+                            let new_h = calc_rendered_height(
+                                y,
+                                &self.matrix_editing,
+                                tokens,
+                                results,
+                                vars,
+                            );
+                            self.render_data.set_rendered_height(y, new_h);
+                        }
+                        dependant_rows.merge(rows_to_recalc);
+                    }
+                    section_header_y = Some(editor_y);
+                }
                 sum_is_null = true;
+                if self.show_header_sums {
+                    // the header's own result (its section sum) is not part of any sum
+                    continue;
+                }
             }
 
-            match &results[content_y(editor_y)] {
-                Ok(Some(result)) => {
-                    sum_result(
-                        vars[SUM_VARIABLE_INDEX]
-                            .as_mut()
-                            .expect("SUM always exists"),
-                        result,
-                        &mut sum_is_null,
-                    );
-                }
-                Err(_) | Ok(None) => {}
+            let skipped_assignment = self.eval_context.skip_assignments_in_sum
+                && tokens[content_y(editor_y)]
+                    .as_ref()
+                    .map(|it| is_assignment(&it.tokens))
+                    .unwrap_or(false);
+            if !skipped_assignment {
+                section_aggregate_result(
+                    vars,
+                    &results[content_y(editor_y)],
+                    &mut sum_is_null,
+                    self.eval_context.skip_errors,
+                );
+            }
+        }
+        if let Some(header_y) = section_header_y {
+            let (result_has_changed, _rows_to_recalc) = set_header_sum(
+                &self.editor_content,
+                header_y,
+                section_sum(vars, sum_is_null),
+                tokens,
+                results,
+                vars,
+                &mut self.updated_line_ref_obj_indices,
+            );
+            if result_has_changed {
+                result_change_flag.merge(BitFlag128::single_row(header_y));
+                let y = content_y(header_y);
+                let new_h = calc_rendered_height(y, &self.matrix_editing, tokens, results, vars);
+                self.render_data.set_rendered_height(y, new_h);
             }
         }
-
         if self.editor_content.line_count() > 99 {
             self.render_data
                 .set_left_gutter_width(LEFT_GUTTER_MIN_WIDTH + 2);
@@ -2993,6 +3306,7 @@ impl NoteCalcApp {
             editor_objs,
             result_change_flag,
         );
+        return result_change_flag;
     }
 
     fn set_editor_and_result_panel_widths_wrt_editor_and_rerender_if_necessary<'b>(
@@ -3115,7 +3429,7 @@ impl NoteCalcApp {
                             var_index
                         }
                         TokenType::Variable { var_index }
-                            if var_index != SUM_VARIABLE_INDEX
+                            if !is_section_aggregate_var(var_index)
                                 && already_added.is_false(var_index)
                                 && token.ptr == editor_obj_name =>
                         {
@@ -3197,6 +3511,7 @@ impl NoteCalcApp {
                         &units,
                         true, // force matrix rendering
                         None,
+                        self.eval_context.rounding_mode,
                     );
                     r.line_render_ended(r.rendered_row_height);
                 }
@@ -3238,6 +3553,7 @@ impl NoteCalcApp {
             &self.editor_content,
             &gr,
             None,
+            self.eval_context.rounding_mode,
         );
         gr.longest_visible_result_len = tmp.max_len;
 
@@ -3248,6 +3564,7 @@ impl NoteCalcApp {
             render_buckets,
             &gr,
             None,
+            self.eval_context.rounding_mode,
         );
 
         for i in 0..render_height {
@@ -3776,6 +4093,7 @@ impl NoteCalcApp {
             &self.updated_line_ref_obj_indices,
             &mut self.editor_objs_referencing_current_line,
             self.mouse_hover_type,
+            &self.eval_context,
         );
         self.updated_line_ref_obj_indices.clear();
     }
@@ -3938,24 +4256,85 @@ pub fn pulse_changed_results(
     }
 }
 
+fn ends_with_line_continuation(line: &[char]) -> bool {
+    line.iter().rev().find(|it| !it.is_ascii_whitespace()) == Some(&'\\')
+}
+
+fn tokens_end_with_line_continuation(tokens: &[Token]) -> bool {
+    tokens
+        .iter()
+        .rev()
+        .flat_map(|it| it.ptr.iter().rev())
+        .find(|it| !it.is_ascii_whitespace())
+        == Some(&'\\')
+}
+
+/// The tokens which belong to the text after the first `prefix_len` chars of the line,
+/// e.g. the tokens of the last physical line of a continued expression
+fn tokens_after(tokens: Vec<Token>, prefix_len: usize) -> Vec<Token> {
+    let mut start = 0;
+    let mut result = Vec::with_capacity(tokens.len());
+    for mut token in tokens {
+        let end = start + token.ptr.len();
+        if end > prefix_len {
+            if start < prefix_len {
+                // e.g. the whitespaces at the joint of the lines
+                token.ptr = &token.ptr[prefix_len - start..];
+            }
+            result.push(token);
+        }
+        start = end;
+    }
+    return result;
+}
+
+/// `>> deg`, `>> rad` or `>> grad`, sets the angle mode of the trigonometric functions
+/// for the rest of the document
+fn angle_mode_directive(line: &[char]) -> Option<AngleMode> {
+    let start = line.iter().position(|it| !it.is_whitespace())?;
+    let end = line.iter().rposition(|it| !it.is_whitespace())? + 1;
+    let line = &line[start..end];
+    if !line.starts_with(&['>', '>']) {
+        return None;
+    }
+    let ws_count = line[2..].iter().take_while(|it| it.is_whitespace()).count();
+    match &line[2 + ws_count..] {
+        ['d', 'e', 'g'] => Some(AngleMode::Deg),
+        ['r', 'a', 'd'] => Some(AngleMode::Rad),
+        ['g', 'r', 'a', 'd'] => Some(AngleMode::Grad),
+        _ => None,
+    }
+}
+
 pub fn parse_tokens<'b>(
     line: &[char],
     editor_y: usize,
     units: &Units,
     vars: &Variables,
+    fns: &FnRegistry,
+    options: ParseOptions,
     allocator: &'b Bump,
 ) -> Tokens<'b> {
     // TODO optimize vec allocations
     let mut tokens = Vec::with_capacity(128);
-    TokenParser::parse_line(line, &vars, &mut tokens, &units, editor_y, allocator);
+    TokenParser::parse_line(
+        line,
+        &vars,
+        &mut tokens,
+        &units,
+        editor_y,
+        allocator,
+        options,
+    );
 
     // TODO: measure is 128 necessary?
     // and remove allocation
     let mut shunting_output_stack = Vec::with_capacity(128);
-    ShuntingYard::shunting_yard(&mut tokens, &mut shunting_output_stack);
+    ShuntingYard::shunting_yard(&mut tokens, &mut shunting_output_stack, fns);
     Tokens {
         tokens,
         shunting_output_stack,
+        angle_mode_directive: angle_mode_directive(line),
     }
 }
 
@@ -4015,7 +4394,7 @@ fn highlight_active_line_refs<'text_ptr>(
         match editor_obj.typ {
             EditorObjectType::LineReference { var_index }
             | EditorObjectType::Variable { var_index }
-                if var_index != SUM_VARIABLE_INDEX =>
+                if !is_section_aggregate_var(var_index) =>
             {
                 let color = if let Some(color) = colors[var_index] {
                     color
@@ -4053,6 +4432,7 @@ fn render_tokens<'text_ptr>(
     units: &Units,
     need_matrix_renderer: bool,
     decimal_count: Option<usize>,
+    rounding_mode: RoundingMode,
 ) {
     editor_objects.clear();
     let cursor_pos = editor.get_selection().get_cursor_pos();
@@ -4119,6 +4499,7 @@ fn render_tokens<'text_ptr>(
                         r,
                         gr,
                         decimal_count,
+                        rounding_mode,
                     );
 
                     let var_name_len = var.name.len();
@@ -4152,6 +4533,7 @@ fn render_tokens<'text_ptr>(
                 | TokenType::NumberLiteral(_)
                 | TokenType::Operator(_)
                 | TokenType::Unit(_)
+                | TokenType::LineRange { .. }
                 | TokenType::NumberErr => {
                     simple_draw(r, gr, render_buckets, editor_objects, token);
                     token_index += 1;
@@ -4257,7 +4639,7 @@ fn draw_right_gutter_num_prefixes(
             render_buckets.set_color(Layer::AboveText, 0x000000_FF);
             render_buckets.draw_text(Layer::AboveText, result_gutter_x, r.render_y, &['0', 'b']);
         }
-        ResultFormat::Dec => {}
+        ResultFormat::Dec | ResultFormat::Eng => {}
     }
 }
 
@@ -4283,12 +4665,39 @@ fn highlight_current_line(
 fn evaluate_tokens_and_save_result<'text_ptr>(
     vars: &mut Variables,
     editor_y: usize,
-    editor_content: &EditorContent<LineData>,
+    line_id: usize,
     tokens: &mut [Token<'text_ptr>],
     shunting_output_stack: &mut Vec<ShuntingYardResult>,
     line: &[char],
+    units: &Units,
+    ctx: &EvalContext,
 ) -> Result<Option<EvaluationResult>, ()> {
-    let result = evaluate_tokens(tokens, shunting_output_stack, &vars);
+    debug_assert!(line_id > 0);
+    // plain text lines (notes, headers, comments) have no result to render
+    let mut result = if line_is_text_only(tokens) {
+        Ok(None)
+    } else {
+        evaluate_tokens(tokens, shunting_output_stack, &vars, ctx)
+    };
+    // e.g. "2x + 3 = 11" defines x
+    let mut solved_var_name = None;
+    let mut unsolvable = false;
+    if let Ok(Some(evaluation)) = &mut result {
+        if evaluation.assignment && result_label(tokens).is_none() {
+            let lhs = assignment_lhs(tokens, line);
+            match solve_linear_equation(lhs, &evaluation.result, vars, editor_y, units, ctx) {
+                Some(Ok((name, value))) => {
+                    evaluation.result = value;
+                    solved_var_name = Some(name);
+                }
+                Some(Err(())) => unsolvable = true,
+                None => {}
+            }
+        }
+    }
+    if unsolvable {
+        result = Err(());
+    }
     if let Ok(Some(result)) = &result {
         fn replace_or_insert_var(
             vars: &mut Variables,
@@ -4303,144 +4712,789 @@ fn evaluate_tokens_and_save_result<'text_ptr>(
                 vars[editor_y] = Some(Variable {
                     name: Box::from(var_name),
                     value: Ok(result),
+                    placeholder: false,
                 });
             };
         }
 
         if result.assignment {
-            let var_name = {
-                let mut i = 0;
-                if line[0] == '=' {
-                    // it might happen that there are more '=' in a line.
-                    // To avoid panic, start the index from 1, so if the first char is
-                    // '=', it will be ignored.
-                    i += 1;
-                }
-                // skip whitespaces
-                while line[i].is_ascii_whitespace() {
-                    i += 1;
-                }
-                let start = i;
-                // take until '='
-                while line[i] != '=' {
-                    i += 1;
-                }
-                // remove trailing whitespaces
-                i -= 1;
-                while i > start && line[i].is_ascii_whitespace() {
-                    i -= 1;
-                }
-                let end = i;
-                &line[start..=end]
+            let var_name = if let Some(name) = solved_var_name {
+                name
+            } else if let Some(label) = result_label(tokens) {
+                label
+            } else {
+                assignment_lhs(tokens, line)
             };
             if !var_name.is_empty() {
                 replace_or_insert_var(vars, var_name, result.result.clone(), editor_y);
             }
         } else {
-            let line_data = editor_content.get_data(editor_y);
-            debug_assert!(line_data.line_id > 0);
-            let line_id = line_data.line_id;
             // TODO opt
             let var_name: Vec<char> = format!("&[{}]", line_id).chars().collect();
             replace_or_insert_var(vars, &var_name, result.result.clone(), editor_y);
         }
-    } else if let Some(var) = &mut vars[editor_y] {
-        let line_data = editor_content.get_data(editor_y);
-        debug_assert!(line_data.line_id > 0);
-        let line_id = line_data.line_id;
+    } else if result.is_err() || vars[editor_y].is_some() || is_blank_or_comment(line) {
+        // references to blank or comment lines must be resolved too, otherwise
+        // e.g. "&[1]" would be parsed as a matrix
         // TODO opt
         let var_name: Vec<char> = format!("&[{}]", line_id).chars().collect();
-        var.name = Box::from(var_name);
-        var.value = Err(());
+        vars[editor_y] = Some(Variable {
+            name: Box::from(var_name),
+            value: Err(()),
+            placeholder: result.is_ok(),
+        });
     } else {
         vars[editor_y] = None;
     }
     result
 }
 
-fn sum_result(sum_var: &mut Variable, result: &CalcResult, sum_is_null: &mut bool) {
-    if *sum_is_null {
-        sum_var.value = Ok(result.clone());
-        *sum_is_null = false;
-    } else {
-        sum_var.value = match &sum_var.value {
-            Ok(current_sum) => {
-                if let Some(ok) = add_op(&current_sum, &result) {
-                    Ok(ok)
+/// The fully parenthesized form of an expression according to the precedence and
+/// associativity of its operators, e.g. `2 + 3 * 4` is `(2 + (3 * 4))`.
+/// `tokens` are the tokens of a line as `TokenParser::parse_line` returns them.
+/// An empty string is returned if there is no valid expression in the line.
+pub fn explain(tokens: &[Token]) -> String {
+    fn text(token: &Token) -> String {
+        token.ptr.iter().collect::<String>().trim().to_owned()
+    }
+    fn pop_args(stack: &mut Vec<String>, count: usize) -> Option<Vec<String>> {
+        if stack.len() < count {
+            None
+        } else {
+            Some(stack.split_off(stack.len() - count))
+        }
+    }
+    let mut tokens = tokens.to_vec();
+    let mut output = Vec::with_capacity(tokens.len());
+    ShuntingYard::shunting_yard(&mut tokens, &mut output, &FnRegistry::default());
+
+    let mut stack: Vec<String> = Vec::with_capacity(output.len());
+    for out in &output {
+        let token = &tokens[out.index_into_tokens];
+        let op = match &out.typ {
+            TokenType::Operator(op) => op,
+            _ => {
+                stack.push(text(token));
+                continue;
+            }
+        };
+        let rendered = match op {
+            // only the right hand side is evaluated, the separators are not operations
+            OperatorTokenType::Assign | OperatorTokenType::Comma | OperatorTokenType::Semicolon => {
+                continue
+            }
+            OperatorTokenType::ApplyUnit(..) => {
+                let operand = stack.pop();
+                // the units of constants (e.g. c) are not in the text,
+                // nor the number of a unit after a multiplication (e.g. "5 * g")
+                match (operand, text(token)) {
+                    (Some(operand), unit) if unit.is_empty() => operand,
+                    (Some(operand), unit) if operand.is_empty() => unit,
+                    (Some(operand), unit) => format!("{} {}", operand, unit),
+                    (None, _) => return String::new(),
+                }
+            }
+            OperatorTokenType::Perc => match stack.pop() {
+                Some(operand) => format!("{}%", operand),
+                None => return String::new(),
+            },
+            OperatorTokenType::UnaryMinus
+            | OperatorTokenType::UnaryPlus
+            | OperatorTokenType::BinNot
+            | OperatorTokenType::Not
+            | OperatorTokenType::Sqrt => {
+                let op_text = text(token);
+                let separator = if op_text.chars().all(|it| it.is_alphabetic()) {
+                    " "
                 } else {
-                    Err(())
+                    ""
+                };
+                match stack.pop() {
+                    Some(operand) => format!("({}{}{})", op_text, separator, operand),
+                    None => return String::new(),
                 }
             }
-            _ => Err(()),
-        }
+            OperatorTokenType::Fn { arg_count, typ } => {
+                let args = match pop_args(&mut stack, *arg_count) {
+                    Some(args) => args,
+                    None => return String::new(),
+                };
+                let name = if matches!(typ, FnType::Custom(..)) {
+                    text(token)
+                } else {
+                    typ.name().iter().collect()
+                };
+                format!("{}({})", name, args.join(", "))
+            }
+            OperatorTokenType::Matrix {
+                row_count,
+                col_count,
+            } => {
+                let cells = match pop_args(&mut stack, row_count * col_count) {
+                    Some(cells) => cells,
+                    None => return String::new(),
+                };
+                let rows: Vec<String> = cells
+                    .chunks((*col_count).max(1))
+                    .map(|it| it.join(", "))
+                    .collect();
+                format!("[{}]", rows.join("; "))
+            }
+            _ => {
+                let mut operands = match pop_args(&mut stack, 2) {
+                    Some(operands) => operands,
+                    None => return String::new(),
+                };
+                let rhs = operands.pop().expect("there are 2 operands");
+                let lhs = operands.pop().expect("there are 2 operands");
+                let op_text = text(token);
+                // the implicit multiplication (e.g. "2(3 + 4)") belongs to the paren
+                let op_text = if op_text == "(" {
+                    "*".to_owned()
+                } else {
+                    op_text
+                };
+                format!("({} {} {})", lhs, op_text, rhs)
+            }
+        };
+        stack.push(rendered);
     }
+    return stack.pop().unwrap_or_default();
 }
 
-fn render_matrix<'text_ptr>(
-    token_index: usize,
-    tokens: &[Token<'text_ptr>],
-    row_count: usize,
-    col_count: usize,
-    r: &mut PerLineRenderData,
-    gr: &mut GlobalRenderData,
-    render_buckets: &mut RenderBuckets<'text_ptr>,
-    editor_objects: &mut Vec<EditorObject>,
-    editor: &Editor,
-    matrix_editing: &Option<MatrixEditing>,
-    // TODO: why unused?
-    _decimal_count: Option<usize>,
-) -> usize {
-    let mut text_width = 0;
-    let mut end_token_index = token_index;
-    while tokens[end_token_index].typ != TokenType::Operator(OperatorTokenType::BracketClose) {
-        text_width += tokens[end_token_index].ptr.len();
-        end_token_index += 1;
+/// The index of the variable of a line in a document evaluated by `evaluate_document`,
+/// the line indices are shifted after `SUM_VARIABLE_INDEX` so that the slots are kept
+/// for `sum`, `avg` and `count`.
+pub fn document_var_index(line_index: usize) -> usize {
+    if line_index < SUM_VARIABLE_INDEX {
+        line_index
+    } else {
+        line_index + (VARIABLE_COUNT - MAX_LINE_COUNT)
     }
-    // ignore the bracket as well
-    text_width += 1;
-    end_token_index += 1;
+}
 
-    let cursor_pos = editor.get_selection().get_cursor_pos();
-    let cursor_inside_matrix: bool = if editor.get_selection().is_range().is_none()
-        && cursor_pos.row == r.editor_y.as_usize()
-        && cursor_pos.column > r.editor_x
-        && cursor_pos.column < r.editor_x + text_width
-    {
-        // cursor is inside the matrix
-        true
-    } else {
-        false
-    };
+/// Evaluates a whole document without the editor, so the number of lines is not limited
+/// by `MAX_LINE_COUNT`.
+/// Lines are referenced by their 1-based index (e.g. `&[1]`), headers reset `sum`.
+/// Line continuations and directives are not supported here.
+pub fn evaluate_document(text: &str, units: &Units, ctx: &EvalContext) -> Vec<LineResult> {
+    evaluate_document_with_vars(text, Vec::new(), units, ctx)
+}
 
-    let new_render_x = if let (true, Some(mat_editor)) = (cursor_inside_matrix, matrix_editing) {
-        mat_editor.render(
-            r.render_x,
-            r.render_y,
-            gr.current_editor_width,
-            gr.left_gutter_width,
-            render_buckets,
-            r.rendered_row_height,
-        )
-    } else {
-        render_matrix_obj(
-            r.render_x,
-            r.render_y,
-            gr.current_editor_width,
-            gr.left_gutter_width,
-            row_count,
-            col_count,
-            &tokens[token_index..],
-            render_buckets,
-            r.rendered_row_height,
-        )
-    };
+/// Evaluates a document (see `evaluate_document`) in which the given values can be
+/// referenced as variables, e.g. `price * 3` with `{"price": 9.99}`.
+/// Lines of the document can redefine these variables.
+pub fn with_variables(doc: &str, vars: &HashMap<String, f64>) -> Vec<LineResult> {
+    let injected_vars = vars
+        .iter()
+        .map(|(name, value)| {
+            // through its shortest representation, so 9.99 does not become 9.9900000000000002
+            let value = Decimal::from_str(&value.to_string())
+                .ok()
+                .or_else(|| Decimal::from_f64(*value));
+            Variable {
+                name: name.chars().collect::<Vec<char>>().into_boxed_slice(),
+                value: value
+                    .map(|it| CalcResult::new(CalcResultType::Number(it), 0))
+                    .ok_or(()),
+                placeholder: false,
+            }
+        })
+        .collect();
+    evaluate_document_with_vars(doc, injected_vars, &Units::new(), &EvalContext::default())
+}
 
-    let rendered_width = new_render_x - r.render_x;
-    editor_objects.push(EditorObject {
-        typ: EditorObjectType::Matrix {
-            row_count,
-            col_count,
-        },
+/// the injected variables precede the lines of the document, so each line can reference them
+fn evaluate_document_with_vars(
+    text: &str,
+    injected_vars: Vec<Variable>,
+    units: &Units,
+    ctx: &EvalContext,
+) -> Vec<LineResult> {
+    let lines: Vec<Vec<char>> = text.lines().map(|it| it.chars().collect()).collect();
+    let first_line_index = injected_vars.len();
+    let var_count = document_var_index((first_line_index + lines.len()).max(MAX_LINE_COUNT));
+    let mut vars: Vec<Option<Variable>> = (0..var_count).map(|_| None).collect();
+    init_section_aggregate_vars(&mut vars);
+    for (i, var) in injected_vars.into_iter().enumerate() {
+        vars[document_var_index(i)] = Some(var);
+    }
+    let allocator = Bump::new();
+    let mut results = Vec::with_capacity(lines.len());
+    let mut sum_is_null = true;
+    for (line_index, line) in lines.iter().enumerate() {
+        let var_index = document_var_index(first_line_index + line_index);
+        if line.starts_with(&['#']) {
+            sum_is_null = true;
+            results.push(Ok(None));
+            continue;
+        }
+        let mut tokens = parse_tokens(
+            line,
+            var_index,
+            units,
+            &vars,
+            &ctx.fns,
+            ctx.parse_options(),
+            &allocator,
+        );
+        let result = evaluate_tokens_and_save_result(
+            &mut vars,
+            var_index,
+            line_index + 1,
+            &mut tokens.tokens,
+            &mut tokens.shunting_output_stack,
+            line,
+            units,
+            ctx,
+        )
+        .map(|it| it.map(|it| it.result));
+        if !(ctx.skip_assignments_in_sum && is_assignment(&tokens.tokens)) {
+            section_aggregate_result(&mut vars, &result, &mut sum_is_null, ctx.skip_errors);
+        }
+        results.push(result);
+    }
+    return results;
+}
+
+/// Evaluates a document (see `evaluate_document`) and returns the results as a JSON array
+/// with an object for each line, e.g.
+/// `{"text": "2 kg * 3", "result": "6", "unit": "kg", "error": false}`.
+/// Matrices are serialized as `{"type": "matrix", "rows": 1, "cols": 2, "cells": [...]}`
+/// where the cells are `{"result": ..., "unit": ...}` objects in row major order.
+pub fn document_to_json(doc: &str) -> String {
+    let units = Units::new();
+    let results = evaluate_document(doc, &units, &EvalContext::default());
+    let cfg = RenderConfig {
+        decimal_count: None,
+        ..RenderConfig::new(&units)
+    };
+    let mut json = String::with_capacity(doc.len() * 2);
+    json.push('[');
+    for (i, (line, result)) in doc.lines().zip(results.iter()).enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        json.push_str("{\"text\":");
+        push_json_string(&mut json, line);
+        json.push(',');
+        match result {
+            Ok(Some(result)) => push_json_result(&mut json, result, &cfg),
+            Ok(None) | Err(..) => json.push_str("\"result\":null,\"unit\":null"),
+        }
+        json.push_str(if result.is_err() {
+            ",\"error\":true}"
+        } else {
+            ",\"error\":false}"
+        });
+    }
+    json.push(']');
+    return json;
+}
+
+/// the "result" and "unit" fields of a result
+fn push_json_result(json: &mut String, result: &CalcResult, cfg: &RenderConfig) {
+    json.push_str("\"result\":");
+    match &result.typ {
+        CalcResultType::Matrix(mat) => {
+            json.push_str(&format!(
+                "{{\"type\":\"matrix\",\"rows\":{},\"cols\":{},\"cells\":[",
+                mat.row_count, mat.col_count
+            ));
+            for (i, cell) in mat.cells.iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                json.push('{');
+                push_json_result(json, cell, cfg);
+                json.push('}');
+            }
+            json.push_str("]},\"unit\":null");
+        }
+        CalcResultType::Unit(unit) => {
+            json.push_str("null,\"unit\":");
+            push_json_string(json, &unit.to_string());
+        }
+        CalcResultType::Number(..)
+        | CalcResultType::Percentage(..)
+        | CalcResultType::Quantity(..) => {
+            // without grouping the number can't contain a space, the unit follows it
+            let formatted = format_result(result, cfg);
+            let (num, unit) = match formatted.find(' ') {
+                Some(i) => (&formatted[..i], Some(&formatted[i + 1..])),
+                None => (&formatted[..], None),
+            };
+            push_json_string(json, num);
+            json.push_str(",\"unit\":");
+            if let Some(unit) = unit {
+                push_json_string(json, unit);
+            } else {
+                json.push_str("null");
+            }
+        }
+    }
+}
+
+fn push_json_string(json: &mut String, str: &str) {
+    json.push('"');
+    for ch in str.chars() {
+        match ch {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => json.push(ch),
+        }
+    }
+    json.push('"');
+}
+
+fn is_blank_or_comment(line: &[char]) -> bool {
+    let ws_count = line.iter().take_while(|it| it.is_whitespace()).count();
+    let line = &line[ws_count..];
+    line.is_empty() || line.starts_with(&['/', '/'])
+}
+
+/// Everything before the assignment operator ('=', ':=' or '≔'), usually the variable name
+fn assignment_lhs<'a>(tokens: &[Token], line: &'a [char]) -> &'a [char] {
+    let assign_pos: usize = tokens
+        .iter()
+        .take_while(|it| it.typ != TokenType::Operator(OperatorTokenType::Assign))
+        .map(|it| it.ptr.len())
+        .sum();
+    let assign_pos = assign_pos.min(line.len());
+    // it might happen that there are more '=' in a line,
+    // a leading one is ignored
+    let mut start = if !line.is_empty() && line[0] == '=' && assign_pos > 0 {
+        1
+    } else {
+        0
+    };
+    // skip whitespaces
+    while start < assign_pos && line[start].is_ascii_whitespace() {
+        start += 1;
+    }
+    // remove trailing whitespaces
+    let mut end = assign_pos;
+    while end > start && line[end - 1].is_ascii_whitespace() {
+        end -= 1;
+    }
+    &line[start..end]
+}
+
+/// Solves equations like "2x + 3 = 11" where the left hand side is linear in a single
+/// unknown name, the result is the name and its value.
+/// `None` means that the left hand side is not an equation but the name of a variable
+/// (e.g. "cost/day" or "2021 budget").
+fn solve_linear_equation<'a>(
+    lhs: &'a [char],
+    rhs: &CalcResult,
+    vars: &Variables,
+    editor_y: usize,
+    units: &Units,
+    ctx: &EvalContext,
+) -> Option<Result<(&'a [char], CalcResult), ()>> {
+    let arena = Bump::new();
+    let mut tokens = Vec::with_capacity(32);
+    TokenParser::parse_line(
+        lhs,
+        vars,
+        &mut tokens,
+        units,
+        editor_y,
+        &arena,
+        ctx.parse_options(),
+    );
+    let is_ws = |token: &Token| {
+        token.typ == TokenType::StringLiteral && token.ptr.iter().all(|it| it.is_whitespace())
+    };
+    // the names which are not variables, units or functions, with their positions in `lhs`
+    let mut unknowns: SmallVec<[(usize, Range<usize>); 4]> = SmallVec::new();
+    let mut offset = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        let is_name = token.typ == TokenType::StringLiteral
+            && token
+                .ptr
+                .first()
+                .map(|it| it.is_alphabetic() || *it == '_')
+                .unwrap_or(false);
+        let is_fn_call = tokens
+            .get(i + 1)
+            .map(|it| it.ptr.first() == Some(&'('))
+            .unwrap_or(false)
+            && (FnType::value_of(token.ptr).is_some() || ctx.fns.index_of(token.ptr).is_some());
+        if is_name && !is_fn_call {
+            unknowns.push((i, offset..offset + token.ptr.len()));
+        }
+        offset += token.ptr.len();
+    }
+    let (_, first_range) = unknowns.first()?;
+    let name = &lhs[first_range.clone()];
+    // e.g. "2x + 3", "x/2" or "2x", but not "2021 budget" or "cost/day"
+    let has_number = tokens
+        .iter()
+        .any(|it| matches!(it.typ, TokenType::NumberLiteral(..)));
+    // the units of quantities are not operations here, e.g. "5 kg budget" is a name
+    let has_operator = tokens.iter().any(|it| match &it.typ {
+        TokenType::Operator(OperatorTokenType::ParenOpen)
+        | TokenType::Operator(OperatorTokenType::ParenClose)
+        | TokenType::Operator(OperatorTokenType::ApplyUnit(..)) => false,
+        TokenType::Operator(..) => true,
+        _ => false,
+    });
+    let has_coefficient = unknowns
+        .iter()
+        .any(|(i, _)| *i > 0 && matches!(tokens[*i - 1].typ, TokenType::NumberLiteral(..)));
+    if !has_number || !(has_operator || has_coefficient) {
+        return None;
+    }
+    if unknowns
+        .iter()
+        .any(|(_, range)| lhs[range.clone()] != *name)
+    {
+        // more than one unknown
+        return Some(Err(()));
+    }
+
+    // the left hand side where the unknown is `value`, a coefficient multiplies it
+    let mult_ptr = arena.alloc_slice_copy(&['*']);
+    let tokens_at = |value: Decimal| {
+        let mut result: Vec<Token> = Vec::with_capacity(tokens.len() + unknowns.len());
+        let mut unknown_indices: SmallVec<[usize; 4]> = SmallVec::new();
+        let mut unknowns = unknowns.iter().peekable();
+        for (i, token) in tokens.iter().enumerate() {
+            if unknowns
+                .peek()
+                .map(|(index, _)| *index == i)
+                .unwrap_or(false)
+            {
+                unknowns.next();
+                let after_operand = result.last().map(|it| {
+                    matches!(
+                        it.typ,
+                        TokenType::NumberLiteral(..)
+                            | TokenType::Operator(OperatorTokenType::ParenClose)
+                    )
+                });
+                if after_operand == Some(true) {
+                    result.push(Token {
+                        ptr: mult_ptr,
+                        typ: TokenType::Operator(OperatorTokenType::Mult),
+                        has_error: false,
+                        is_ambiguous: false,
+                    });
+                }
+                unknown_indices.push(result.len());
+                result.push(Token {
+                    ptr: token.ptr,
+                    typ: TokenType::NumberLiteral(value),
+                    has_error: false,
+                    is_ambiguous: false,
+                });
+            } else if !is_ws(token) {
+                result.push(token.clone());
+            }
+        }
+        (result, unknown_indices)
+    };
+    let evaluate_at = |value: Decimal| {
+        let (mut tokens, _) = tokens_at(value);
+        let mut shunting_output_stack = Vec::with_capacity(32);
+        ShuntingYard::shunting_yard(&mut tokens, &mut shunting_output_stack, &ctx.fns);
+        match evaluate_tokens(&mut tokens, &mut shunting_output_stack, vars, ctx) {
+            Ok(Some(it)) => Some(it.result),
+            _ => None,
+        }
+    };
+
+    // the degree of the left hand side in the unknown, it must be exactly 1
+    let (mut tokens_at_zero, unknown_indices) = tokens_at(Decimal::zero());
+    let mut shunting_output_stack = Vec::with_capacity(32);
+    ShuntingYard::shunting_yard(&mut tokens_at_zero, &mut shunting_output_stack, &ctx.fns);
+    if !is_linear_in(&shunting_output_stack, &unknown_indices) {
+        return Some(Err(()));
+    }
+    // lhs = a*x + b
+    let b = evaluate_at(Decimal::zero());
+    let a = evaluate_at(Decimal::one())
+        .and_then(|lhs_at_one| b.as_ref().and_then(|b| sub_op(&lhs_at_one, b)));
+    let is_zero = |it: &CalcResult| match &it.typ {
+        CalcResultType::Number(num) | CalcResultType::Quantity(num, _) => num.is_zero(),
+        _ => true,
+    };
+    let x = match (&a, &b) {
+        (Some(a), Some(b)) if !is_zero(a) => sub_op(rhs, b).and_then(|it| divide_op(&it, a)),
+        _ => None,
+    };
+    Some(x.map(|x| (name, x)).ok_or(()))
+}
+
+/// Whether the expression in reverse polish notation is linear (of degree 1) in the operands
+/// at `unknown_indices`, e.g. "2 * x + 3" is, but "x * x", "1 / x" or "sin(x)" are not.
+fn is_linear_in(shunting_output: &[ShuntingYardResult], unknown_indices: &[usize]) -> bool {
+    // the degree of the operands, 2 stands for anything which is not linear
+    let mut stack: SmallVec<[u8; 16]> = SmallVec::new();
+    for out in shunting_output {
+        let degree = match &out.typ {
+            TokenType::NumberLiteral(..) if unknown_indices.contains(&out.index_into_tokens) => 1,
+            TokenType::Operator(op) => {
+                let operand_count = match op {
+                    OperatorTokenType::UnaryPlus
+                    | OperatorTokenType::UnaryMinus
+                    | OperatorTokenType::ApplyUnit(..)
+                    | OperatorTokenType::Perc
+                    | OperatorTokenType::BinNot
+                    | OperatorTokenType::Sqrt
+                    | OperatorTokenType::Not => 1,
+                    OperatorTokenType::Fn { arg_count, .. } => *arg_count,
+                    OperatorTokenType::Matrix {
+                        row_count,
+                        col_count,
+                    } => row_count * col_count,
+                    _ => 2,
+                };
+                if stack.len() < operand_count {
+                    return false;
+                }
+                let from = stack.len() - operand_count;
+                let operands: SmallVec<[u8; 4]> = stack.drain(from..).collect();
+                match op {
+                    OperatorTokenType::UnaryPlus
+                    | OperatorTokenType::UnaryMinus
+                    | OperatorTokenType::ApplyUnit(..) => operands[0],
+                    OperatorTokenType::Add | OperatorTokenType::Sub => operands[0].max(operands[1]),
+                    OperatorTokenType::Mult => (operands[0] + operands[1]).min(2),
+                    OperatorTokenType::Div if operands[1] == 0 => operands[0],
+                    // the other operations are linear only in constants
+                    _ if operands.iter().all(|it| *it == 0) => 0,
+                    _ => 2,
+                }
+            }
+            _ => 0,
+        };
+        stack.push(degree);
+    }
+    stack.len() == 1 && stack[0] == 1
+}
+
+/// e.g. `x = 5` or `5 = x`
+fn is_assignment(tokens: &[Token]) -> bool {
+    tokens
+        .iter()
+        .any(|it| it.typ == TokenType::Operator(OperatorTokenType::Assign))
+}
+
+/// The name in the "expr = name" form of assignment.
+/// In this form, only string literals can follow the assignment operator.
+fn result_label<'text_ptr>(tokens: &[Token<'text_ptr>]) -> Option<&'text_ptr [char]> {
+    let assign_pos = tokens
+        .iter()
+        .position(|it| it.typ == TokenType::Operator(OperatorTokenType::Assign))?;
+    let mut label = None;
+    for token in &tokens[assign_pos + 1..] {
+        if token.typ != TokenType::StringLiteral {
+            return None;
+        } else if !token.ptr.iter().all(|it| it.is_whitespace()) {
+            label = Some(token.ptr);
+        }
+    }
+    return label;
+}
+
+fn init_section_aggregate_vars(vars: &mut Variables) {
+    for (index, name) in &[
+        (SUM_VARIABLE_INDEX, &['s', 'u', 'm'][..]),
+        (AVG_VARIABLE_INDEX, &['a', 'v', 'g'][..]),
+        (COUNT_VARIABLE_INDEX, &['c', 'o', 'u', 'n', 't'][..]),
+    ] {
+        vars[*index] = Some(Variable {
+            name: Box::from(*name),
+            value: Err(()),
+            placeholder: false,
+        });
+    }
+}
+
+fn is_section_aggregate_var(var_index: usize) -> bool {
+    var_index >= SUM_VARIABLE_INDEX && var_index < VARIABLE_COUNT
+}
+
+/// Updates the running `sum`, `avg` and `count` of the section with the result of a line.
+/// `count` is the number of lines with a result, `avg` is `sum / count`.
+fn section_aggregate_result(
+    vars: &mut Variables,
+    result: &LineResult,
+    sum_is_null: &mut bool,
+    skip_errors: bool,
+) {
+    let section_started = *sum_is_null;
+    sum_result(
+        vars[SUM_VARIABLE_INDEX]
+            .as_mut()
+            .expect("SUM always exists"),
+        result,
+        sum_is_null,
+        skip_errors,
+    );
+    if *sum_is_null {
+        // no result in the section yet
+        return;
+    }
+    let prev_count = if section_started {
+        Decimal::zero()
+    } else {
+        match &vars[COUNT_VARIABLE_INDEX]
+            .as_ref()
+            .expect("COUNT always exists")
+            .value
+        {
+            Ok(CalcResult {
+                typ: CalcResultType::Number(count),
+                ..
+            }) => *count,
+            _ => Decimal::zero(),
+        }
+    };
+    let count = if matches!(result, Ok(Some(_))) {
+        prev_count + Decimal::one()
+    } else {
+        prev_count
+    };
+    let count = CalcResult::new(CalcResultType::Number(count), 0);
+    let avg = match &vars[SUM_VARIABLE_INDEX]
+        .as_ref()
+        .expect("SUM always exists")
+        .value
+    {
+        Ok(sum) => divide_op(sum, &count).ok_or(()),
+        Err(()) => Err(()),
+    };
+    vars[AVG_VARIABLE_INDEX]
+        .as_mut()
+        .expect("AVG always exists")
+        .value = avg;
+    vars[COUNT_VARIABLE_INDEX]
+        .as_mut()
+        .expect("COUNT always exists")
+        .value = Ok(count);
+}
+
+fn sum_result(
+    sum_var: &mut Variable,
+    result: &LineResult,
+    sum_is_null: &mut bool,
+    skip_errors: bool,
+) {
+    let result = match result {
+        Ok(Some(result)) => result,
+        Ok(None) => return,
+        Err(_) => {
+            if !skip_errors {
+                sum_var.value = Err(());
+                *sum_is_null = false;
+            }
+            return;
+        }
+    };
+    if *sum_is_null {
+        sum_var.value = Ok(result.clone());
+        *sum_is_null = false;
+    } else {
+        sum_var.value = match &sum_var.value {
+            Ok(current_sum) => {
+                if let Some(ok) = add_op(&current_sum, &result) {
+                    Ok(ok)
+                } else if skip_errors {
+                    Ok(current_sum.clone())
+                } else {
+                    Err(())
+                }
+            }
+            _ => Err(()),
+        }
+    }
+}
+
+fn section_sum(vars: &Variables, sum_is_null: bool) -> LineResult {
+    if sum_is_null {
+        Ok(None)
+    } else {
+        vars[SUM_VARIABLE_INDEX]
+            .as_ref()
+            .expect("SUM always exists")
+            .value
+            .clone()
+            .map(Some)
+    }
+}
+
+fn render_matrix<'text_ptr>(
+    token_index: usize,
+    tokens: &[Token<'text_ptr>],
+    row_count: usize,
+    col_count: usize,
+    r: &mut PerLineRenderData,
+    gr: &mut GlobalRenderData,
+    render_buckets: &mut RenderBuckets<'text_ptr>,
+    editor_objects: &mut Vec<EditorObject>,
+    editor: &Editor,
+    matrix_editing: &Option<MatrixEditing>,
+    // TODO: why unused?
+    _decimal_count: Option<usize>,
+) -> usize {
+    let mut text_width = 0;
+    let mut end_token_index = token_index;
+    while tokens[end_token_index].typ != TokenType::Operator(OperatorTokenType::BracketClose) {
+        text_width += tokens[end_token_index].ptr.len();
+        end_token_index += 1;
+    }
+    // ignore the bracket as well
+    text_width += 1;
+    end_token_index += 1;
+
+    let cursor_pos = editor.get_selection().get_cursor_pos();
+    let cursor_inside_matrix: bool = if editor.get_selection().is_range().is_none()
+        && cursor_pos.row == r.editor_y.as_usize()
+        && cursor_pos.column > r.editor_x
+        && cursor_pos.column < r.editor_x + text_width
+    {
+        // cursor is inside the matrix
+        true
+    } else {
+        false
+    };
+
+    let new_render_x = if let (true, Some(mat_editor)) = (cursor_inside_matrix, matrix_editing) {
+        mat_editor.render(
+            r.render_x,
+            r.render_y,
+            gr.current_editor_width,
+            gr.left_gutter_width,
+            render_buckets,
+            r.rendered_row_height,
+        )
+    } else {
+        render_matrix_obj(
+            r.render_x,
+            r.render_y,
+            gr.current_editor_width,
+            gr.left_gutter_width,
+            row_count,
+            col_count,
+            &tokens[token_index..],
+            render_buckets,
+            r.rendered_row_height,
+        )
+    };
+
+    let rendered_width = new_render_x - r.render_x;
+    editor_objects.push(EditorObject {
+        typ: EditorObjectType::Matrix {
+            row_count,
+            col_count,
+        },
         row: r.editor_y,
         start_x: r.editor_x,
         end_x: r.editor_x + text_width,
@@ -4470,6 +5524,7 @@ fn evaluate_selection(
     vars: &Variables,
     results: &[LineResult],
     allocator: &Bump,
+    ctx: &EvalContext,
 ) -> Option<String> {
     let sel = editor.get_selection();
     // TODO optimize vec allocations
@@ -4484,6 +5539,7 @@ fn evaluate_selection(
                 &mut tokens,
                 sel.start.row,
                 allocator,
+                ctx,
             ) {
                 if result.there_was_operation {
                     let result_str = render_result(
@@ -4492,6 +5548,7 @@ fn evaluate_selection(
                         &editor_content.get_data(sel.start.row).result_format,
                         result.there_was_unit_conversion,
                         Some(RENDERED_RESULT_PRECISION),
+                        ctx.rounding_mode,
                         true,
                     );
                     return Some(result_str);
@@ -4526,6 +5583,7 @@ fn evaluate_selection(
                 &editor_content.get_data(sel.start.row).result_format,
                 false,
                 Some(RENDERED_RESULT_PRECISION),
+                ctx.rounding_mode,
                 true,
             );
             return Some(result_str);
@@ -4541,11 +5599,20 @@ fn evaluate_text<'text_ptr>(
     tokens: &mut Vec<Token<'text_ptr>>,
     editor_y: usize,
     allocator: &'text_ptr Bump,
+    ctx: &EvalContext,
 ) -> Result<Option<EvaluationResult>, ()> {
-    TokenParser::parse_line(text, vars, tokens, &units, editor_y, allocator);
+    TokenParser::parse_line(
+        text,
+        vars,
+        tokens,
+        &units,
+        editor_y,
+        allocator,
+        ctx.parse_options(),
+    );
     let mut shunting_output_stack = Vec::with_capacity(4);
-    ShuntingYard::shunting_yard(tokens, &mut shunting_output_stack);
-    return evaluate_tokens(tokens, &mut shunting_output_stack, &vars);
+    ShuntingYard::shunting_yard(tokens, &mut shunting_output_stack, &ctx.fns);
+    return evaluate_tokens(tokens, &mut shunting_output_stack, &vars, ctx);
 }
 
 fn render_matrix_obj<'text_ptr>(
@@ -4742,6 +5809,7 @@ fn render_matrix_result<'text_ptr>(
     prev_mat_result_lengths: Option<&ResultLengths>,
     rendered_row_height: usize,
     decimal_count: Option<usize>,
+    rounding_mode: RoundingMode,
 ) -> usize {
     let start_x = render_x;
 
@@ -4759,8 +5827,15 @@ fn render_matrix_result<'text_ptr>(
         let mut tokens_per_cell: SmallVec<[String; 32]> = SmallVec::with_capacity(32);
 
         for cell in mat.cells.iter() {
-            let result_str =
-                render_result(units, cell, &ResultFormat::Dec, false, decimal_count, true);
+            let result_str = render_result(
+                units,
+                cell,
+                &ResultFormat::Dec,
+                false,
+                decimal_count,
+                rounding_mode,
+                true,
+            );
             tokens_per_cell.push(result_str);
         }
         tokens_per_cell
@@ -4874,6 +5949,7 @@ fn render_result_inside_editor<'text_ptr>(
     r: &PerLineRenderData,
     gr: &GlobalRenderData,
     decimal_count: Option<usize>,
+    rounding_mode: RoundingMode,
 ) -> (usize, usize) {
     return match &result {
         Ok(CalcResult {
@@ -4889,6 +5965,7 @@ fn render_result_inside_editor<'text_ptr>(
                 None,
                 r.rendered_row_height,
                 decimal_count,
+                rounding_mode,
             );
             (rendered_width, mat.render_height())
         }
@@ -4900,6 +5977,7 @@ fn render_result_inside_editor<'text_ptr>(
                 &ResultFormat::Dec,
                 false,
                 decimal_count,
+                rounding_mode,
                 true,
             );
             let text_len = result_str.chars().count();
@@ -4961,6 +6039,7 @@ fn render_results_into_buf_and_calc_len<'text_ptr>(
     editor_content: &EditorContent<LineData>,
     gr: &GlobalRenderData,
     decimal_count: Option<usize>,
+    rounding_mode: RoundingMode,
 ) {
     let mut result_buffer_index = 0;
     let result_buffer = unsafe { &mut RESULT_BUFFER };
@@ -5034,6 +6113,7 @@ fn render_results_into_buf_and_calc_len<'text_ptr>(
                         false,
                         &mut c,
                         decimal_count,
+                        rounding_mode,
                         true,
                     );
                     let len = c.position() as usize;
@@ -5079,6 +6159,7 @@ fn create_render_commands_for_results_and_render_matrices<'text_ptr>(
     render_buckets: &mut RenderBuckets<'text_ptr>,
     gr: &GlobalRenderData,
     decimal_count: Option<usize>,
+    rounding_mode: RoundingMode,
 ) -> usize {
     let mut prev_result_matrix_length = None;
     let mut matrix_len = 0;
@@ -5201,6 +6282,7 @@ fn create_render_commands_for_results_and_render_matrices<'text_ptr>(
                         prev_result_matrix_length = calc_consecutive_matrices_max_lengths(
                             units,
                             &results[result_tmp.editor_y.as_usize()..],
+                            rounding_mode,
                         );
                     }
                     let width = render_matrix_result(
@@ -5212,6 +6294,7 @@ fn create_render_commands_for_results_and_render_matrices<'text_ptr>(
                         prev_result_matrix_length.as_ref(),
                         gr.get_rendered_height(result_tmp.editor_y),
                         decimal_count,
+                        rounding_mode,
                     );
                     if width > matrix_len {
                         matrix_len = width;
@@ -5245,6 +6328,7 @@ fn create_render_commands_for_results_and_render_matrices<'text_ptr>(
 fn calc_consecutive_matrices_max_lengths(
     units: &Units,
     results: &[LineResult],
+    rounding_mode: RoundingMode,
 ) -> Option<ResultLengths> {
     let mut max_lengths: Option<ResultLengths> = None;
     for result in results.iter() {
@@ -5253,7 +6337,7 @@ fn calc_consecutive_matrices_max_lengths(
                 typ: CalcResultType::Matrix(mat),
                 ..
             })) => {
-                let lengths = calc_matrix_max_lengths(units, mat);
+                let lengths = calc_matrix_max_lengths(units, mat, rounding_mode);
                 if let Some(max_lengths) = &mut max_lengths {
                     max_lengths.set_max(&lengths);
                 } else {
@@ -5268,7 +6352,11 @@ fn calc_consecutive_matrices_max_lengths(
     return max_lengths;
 }
 
-fn calc_matrix_max_lengths(units: &Units, mat: &MatrixData) -> ResultLengths {
+fn calc_matrix_max_lengths(
+    units: &Units,
+    mat: &MatrixData,
+    rounding_mode: RoundingMode,
+) -> ResultLengths {
     let cells_strs = {
         let mut tokens_per_cell: SmallVec<[String; 32]> = SmallVec::with_capacity(32);
 
@@ -5279,6 +6367,7 @@ fn calc_matrix_max_lengths(units: &Units, mat: &MatrixData) -> ResultLengths {
                 &ResultFormat::Dec,
                 false,
                 Some(RENDERED_RESULT_PRECISION),
+                rounding_mode,
                 true,
             );
             tokens_per_cell.push(result_str);
@@ -5312,7 +6401,7 @@ fn draw_line_refs_and_vars_referenced_from_cur_row<'b>(
         match editor_obj.typ {
             EditorObjectType::LineReference { var_index }
             | EditorObjectType::Variable { var_index } => {
-                if var_index == SUM_VARIABLE_INDEX {
+                if is_section_aggregate_var(var_index) {
                     continue;
                 }
                 let color = if highlighted.is_true(var_index) {
@@ -5357,7 +6446,9 @@ fn draw_token<'text_ptr>(
             TokenType::StringLiteral => &mut render_buckets.utf8_texts,
             TokenType::Header => &mut render_buckets.headers,
             TokenType::Variable { .. } => &mut render_buckets.variable,
-            TokenType::LineReference { .. } => &mut render_buckets.variable,
+            TokenType::LineReference { .. } | TokenType::LineRange { .. } => {
+                &mut render_buckets.variable
+            }
             TokenType::NumberLiteral(_) => &mut render_buckets.numbers,
             TokenType::NumberErr => &mut render_buckets.number_errors,
             TokenType::Operator(OperatorTokenType::ApplyUnit(_)) => &mut render_buckets.units,
@@ -5473,6 +6564,7 @@ fn render_selection_and_its_sum<'text_ptr>(
     gr: &GlobalRenderData,
     vars: &Variables,
     allocator: &'text_ptr Bump,
+    ctx: &EvalContext,
 ) {
     render_buckets.set_color(Layer::BehindText, 0xA6D2FF_FF);
     if let Some((start, end)) = editor.get_selection().is_range() {
@@ -5531,6 +6623,7 @@ fn render_selection_and_its_sum<'text_ptr>(
             &vars,
             results.as_slice(),
             allocator,
+            ctx,
         ) {
             if start.row == end.row {
                 if let Some(start_render_y) = gr.get_render_y(content_y(start.row)) {
@@ -5794,6 +6887,7 @@ fn default_result_gutter_x(client_width: usize) -> usize {
 #[cfg(test)]
 mod main_tests {
     use super::*;
+    use crate::token_parser::{ConversionKeyword, NumberInputMode};
 
     const fn result_panel_w(client_width: usize) -> usize {
         client_width * (100 - DEFAULT_RESULT_PANEL_WIDTH_PERCENT) / 100
@@ -5912,9 +7006,7 @@ mod main_tests {
         }
 
         fn mut_vars<'a>(&self) -> &'a mut [Option<Variable>] {
-            unsafe {
-                &mut (&mut *(self.vars_ptr as *mut [Option<Variable>; MAX_LINE_COUNT + 1]))[..]
-            }
+            unsafe { &mut (&mut *(self.vars_ptr as *mut [Option<Variable>; VARIABLE_COUNT]))[..] }
         }
 
         fn allocator<'a>(&self) -> &'a Bump {
@@ -6285,6 +7377,27 @@ mod main_tests {
         create_app3(120, client_height)
     }
 
+    /// The results of the lines as they are displayed, "Err" for the erroneous lines and ""
+    /// for the lines without a result.
+    fn render_results(units: &Units, results: &[LineResult]) -> Vec<String> {
+        results
+            .iter()
+            .map(|it| match it {
+                Ok(Some(result)) => render_result(
+                    units,
+                    result,
+                    &ResultFormat::Dec,
+                    false,
+                    Some(4),
+                    RoundingMode::default(),
+                    false,
+                ),
+                Ok(None) => String::new(),
+                Err(()) => "Err".to_owned(),
+            })
+            .collect()
+    }
+
     #[test]
     fn bug1() {
         let test = create_app2(35);
@@ -7567,38 +8680,171 @@ sum",
     }
 
     #[test]
-    fn test_that_header_lengths_are_separate_and_not_add() {
-        let test = create_app3(79, 32);
-        test.set_normalized_content(
-            "# Header 0\n\
-                123\n\
-                # Header 1\n\
-                123\n\
-                # Header 2\n\
-                123",
+    fn test_bare_avg_and_count_are_the_running_stats_of_the_section() {
+        let test = create_app2(35);
+        test.paste(
+            "2
+4
+
+6
+avg
+# new header
+10
+count
+# new header
+count(1, 2, 3)
+avg",
         );
-        assert_eq!(test.get_render_data().longest_visible_result_len, 3);
+        test.assert_results(&["2", "4", "", "6", "4", "", "10", "1", "", "3", "3"][..]);
     }
 
     #[test]
-    fn no_sum_value_in_case_of_error() {
+    fn test_avg_and_count_in_prose_are_not_the_running_stats() {
         let test = create_app2(35);
         test.paste(
-            "3m * 2m\n\
-                    4\n\
-                    sum",
+            "2
+4
+avg speed
+count + 1
+avg",
         );
-        test.assert_results(&["6 m^2", "4", "Err"][..]);
+        test.assert_results(&["2", "4", "", "3", "3"][..]);
     }
 
     #[test]
-    fn test_ctrl_c() {
+    fn test_variables_shadow_the_running_stats() {
         let test = create_app2(35);
-        test.paste("aaaaaaaaa");
-        test.render();
-        test.input(EditorInputEvent::Left, InputModifiers::shift());
-        test.input(EditorInputEvent::Left, InputModifiers::shift());
-        test.input(EditorInputEvent::Left, InputModifiers::shift());
+        test.paste(
+            "1
+2
+count = 10
+count * 2
+avg",
+        );
+        test.assert_results(&["1", "2", "10", "20", "8.25"][..]);
+    }
+
+    #[test]
+    fn test_sum_with_and_without_the_assignments() {
+        let text = "rate = 3
+10
+20
+total = 30
+sum";
+        let test = create_app2(35);
+        test.paste(text);
+        test.assert_results(&["3", "10", "20", "30", "63"][..]);
+
+        let test = create_app2(35);
+        test.mut_app().eval_context.skip_assignments_in_sum = true;
+        test.paste(text);
+        test.assert_results(&["3", "10", "20", "30", "30"][..]);
+    }
+
+    #[test]
+    fn test_evaluating_documents_without_the_assignments_in_sum() {
+        let units = Units::new();
+        let ctx = EvalContext {
+            skip_assignments_in_sum: true,
+            ..EvalContext::default()
+        };
+        let results = evaluate_document("x = 5\n1\n2 * x\ncount\nsum", &units, &ctx);
+        assert_eq!(
+            render_results(&units, &results),
+            &["5", "1", "10", "2", "13"]
+        );
+    }
+
+    #[test]
+    fn test_header_shows_its_section_sum() {
+        use crate::calc::dec;
+        let test = create_app2(35);
+        test.mut_app().show_header_sums = true;
+        test.paste(
+            "# Groceries
+12
+30
+# Rent
+1000
+200",
+        );
+        match &test.mut_results()[content_y(0)] {
+            Ok(Some(result)) => assert_eq!(result.typ, CalcResultType::Number(dec(42))),
+            _ => panic!(),
+        }
+        match &test.mut_results()[content_y(3)] {
+            Ok(Some(result)) => assert_eq!(result.typ, CalcResultType::Number(dec(1200))),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_header_sum_is_updated_and_can_be_referenced() {
+        use crate::calc::dec;
+        let test = create_app2(35);
+        test.mut_app().show_header_sums = true;
+        test.paste("# Groceries\n12\n30\n# Total\n&[1] * 2");
+        match &test.mut_results()[content_y(4)] {
+            Ok(Some(result)) => assert_eq!(result.typ, CalcResultType::Number(dec(84))),
+            _ => panic!(),
+        }
+
+        test.set_cursor_row_col(1, 2);
+        test.input(EditorInputEvent::Char('0'), InputModifiers::none());
+        match &test.mut_results()[content_y(0)] {
+            Ok(Some(result)) => assert_eq!(result.typ, CalcResultType::Number(dec(150))),
+            _ => panic!(),
+        }
+        match &test.mut_results()[content_y(4)] {
+            Ok(Some(result)) => assert_eq!(result.typ, CalcResultType::Number(dec(300))),
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_header_has_no_result_without_the_flag() {
+        let test = create_app2(35);
+        test.paste(
+            "# Groceries
+12
+30",
+        );
+        assert!(matches!(test.mut_results()[content_y(0)], Ok(None)));
+    }
+
+    #[test]
+    fn test_that_header_lengths_are_separate_and_not_add() {
+        let test = create_app3(79, 32);
+        test.set_normalized_content(
+            "# Header 0\n\
+                123\n\
+                # Header 1\n\
+                123\n\
+                # Header 2\n\
+                123",
+        );
+        assert_eq!(test.get_render_data().longest_visible_result_len, 3);
+    }
+
+    #[test]
+    fn no_sum_value_in_case_of_error() {
+        let test = create_app2(35);
+        test.paste(
+            "3m * 2m\n\
+                    4\n\
+                    sum",
+        );
+        test.assert_results(&["6 m^2", "4", "Err"][..]);
+    }
+
+    #[test]
+    fn test_ctrl_c() {
+        let test = create_app2(35);
+        test.paste("aaaaaaaaa");
+        test.render();
+        test.input(EditorInputEvent::Left, InputModifiers::shift());
+        test.input(EditorInputEvent::Left, InputModifiers::shift());
+        test.input(EditorInputEvent::Left, InputModifiers::shift());
         test.input(EditorInputEvent::Char('c'), InputModifiers::ctrl());
         assert_eq!("aaa", &test.app().editor.clipboard);
         assert_eq!(&None, &test.app().clipboard);
@@ -7808,6 +9054,568 @@ sum",
         test.assert_results(&["12", "14"][..]);
     }
 
+    #[test]
+    fn test_referencing_blank_and_comment_lines_is_an_error() {
+        let test = create_app2(35);
+        test.paste("\n// comment\n&[1] + 5\n&[2] + 5\n5");
+        test.assert_results(&["5"][..]);
+        assert!(test.mut_results()[content_y(2)].is_err());
+        assert!(test.mut_results()[content_y(3)].is_err());
+    }
+
+    #[test]
+    fn test_evaluating_documents_longer_than_the_editor() {
+        let units = Units::new();
+        let mut text = "1\n".repeat(MAX_LINE_COUNT + 72);
+        text.push_str("x = 3\nx * &[150]\n# header\n2\nsum");
+        let results = evaluate_document(&text, &units, &EvalContext::default());
+        assert_eq!(results.len(), MAX_LINE_COUNT + 77);
+        let rendered = render_results(&units, &results);
+        assert_eq!(rendered[MAX_LINE_COUNT], "1");
+        assert_eq!(rendered[MAX_LINE_COUNT + 72], "3");
+        assert_eq!(rendered[MAX_LINE_COUNT + 73], "3");
+        assert_eq!(rendered[MAX_LINE_COUNT + 74], "");
+        // the sum is reset by the header
+        assert_eq!(rendered[MAX_LINE_COUNT + 76], "2");
+    }
+
+    #[test]
+    fn test_evaluating_documents_sums_the_lines() {
+        let units = Units::new();
+        let mut text = "1\n".repeat(MAX_LINE_COUNT + 2);
+        text.push_str("sum");
+        let results = evaluate_document(&text, &units, &EvalContext::default());
+        assert_eq!(render_results(&units, &results).last().unwrap(), "130");
+    }
+
+    #[test]
+    fn test_evaluating_documents_with_injected_variables() {
+        let units = Units::new();
+        let mut vars = HashMap::new();
+        vars.insert("price".to_owned(), 9.99);
+        vars.insert("count".to_owned(), 4.0);
+        let results = with_variables("price * 3\ncount * &[1]\nprice = 1\nprice * 3", &vars);
+        // line references are not shifted by the variables
+        assert_eq!(
+            render_results(&units, &results),
+            &["29.97", "119.88", "1", "3"]
+        );
+    }
+
+    #[test]
+    fn test_explain_precedence() {
+        let explain_text = |text: &str| {
+            let units = Units::new();
+            let vars = create_vars();
+            let arena = Bump::new();
+            let line: Vec<char> = text.chars().collect();
+            let mut tokens = vec![];
+            TokenParser::parse_line(
+                &line,
+                &vars,
+                &mut tokens,
+                &units,
+                0,
+                &arena,
+                ParseOptions::default(),
+            );
+            explain(&tokens)
+        };
+        assert_eq!(explain_text("2 + 3 * 4"), "(2 + (3 * 4))");
+        assert_eq!(explain_text("(2 + 3) * 4"), "((2 + 3) * 4)");
+        assert_eq!(explain_text("1+2*3^4"), "(1 + (2 * (3 ^ 4)))");
+        // left and right associativity
+        assert_eq!(explain_text("10 - 4 - 3"), "((10 - 4) - 3)");
+        assert_eq!(explain_text("2 ^ 3 ^ 2"), "(2 ^ (3 ^ 2))");
+        // units bind tighter than the arithmetic operators
+        assert_eq!(explain_text("2 * 3 m"), "(2 * 3 m)");
+        assert_eq!(explain_text("10 km in m"), "(10 km in m)");
+        assert_eq!(explain_text("1 + 2 > 2"), "((1 + 2) > 2)");
+        assert_eq!(explain_text("max(1, 2) * 3"), "(max(1, 2) * 3)");
+        assert_eq!(explain_text("apples"), "");
+    }
+
+    #[test]
+    fn test_document_to_json() {
+        let json = document_to_json("2 kg * 3\n\n// \"comment\"\n10%\n[1 m, 2 m]\n2 kg + 3 m");
+        assert_eq!(
+            json,
+            concat!(
+                "[",
+                r#"{"text":"2 kg * 3","result":"6","unit":"kg","error":false},"#,
+                r#"{"text":"","result":null,"unit":null,"error":false},"#,
+                r#"{"text":"// \"comment\"","result":null,"unit":null,"error":false},"#,
+                r#"{"text":"10%","result":"10","unit":"%","error":false},"#,
+                r#"{"text":"[1 m, 2 m]","result":{"type":"matrix","rows":1,"cols":2,"cells":["#,
+                r#"{"result":"1","unit":"m"},{"result":"2","unit":"m"}]},"unit":null,"error":false},"#,
+                r#"{"text":"2 kg + 3 m","result":null,"unit":null,"error":true}"#,
+                "]"
+            )
+        );
+    }
+
+    #[test]
+    fn test_evaluating_documents_with_avg_and_count() {
+        let units = Units::new();
+        let results = evaluate_document("1\n2\n6\navg\ncount", &units, &EvalContext::default());
+        assert_eq!(render_results(&units, &results), &["1", "2", "6", "3", "4"]);
+    }
+
+    #[test]
+    fn test_sum_of_a_section_with_an_error_line_is_an_error() {
+        let test = create_app2(35);
+        test.paste("1\n2 kg + 3 m\n3\nsum\n# next\n4\nsum");
+        // the error does not leak into the next section
+        test.assert_results(&["1", "Err", "3", "Err", "", "4", "4"][..]);
+
+        // neither can a line be skipped which can't be added to it
+        let test = create_app2(35);
+        test.paste("1\n2 kg\n3\nsum");
+        test.assert_results(&["1", "2 kg", "3", "Err"][..]);
+    }
+
+    #[test]
+    fn test_sum_skips_error_lines_if_configured() {
+        let test = create_app2(35);
+        test.mut_app().eval_context.skip_errors = true;
+        test.paste("1\n2 kg + 3 m\n3\nsum");
+        test.assert_results(&["1", "Err", "3", "4"][..]);
+
+        let test = create_app2(35);
+        test.mut_app().eval_context.skip_errors = true;
+        test.paste("1\n2 kg\n3\nsum");
+        test.assert_results(&["1", "2 kg", "3", "4"][..]);
+    }
+
+    #[test]
+    fn test_evaluating_documents_with_error_lines() {
+        let units = Units::new();
+        let text = "1\n2 kg + 3 m\n3\nsum";
+        let results = evaluate_document(text, &units, &EvalContext::default());
+        assert_eq!(render_results(&units, &results), &["1", "Err", "3", "Err"]);
+
+        let ctx = EvalContext {
+            skip_errors: true,
+            ..Default::default()
+        };
+        let results = evaluate_document(text, &units, &ctx);
+        assert_eq!(render_results(&units, &results), &["1", "Err", "3", "4"]);
+    }
+
+    #[test]
+    fn test_decimal_comma_input_mode() {
+        let units = Units::new();
+        let ctx = EvalContext {
+            number_input_mode: NumberInputMode::DecimalComma,
+            ..Default::default()
+        };
+        let results = evaluate_document("3,14\n[1,5; 2,5] * 2\nmax(1,5, 2)", &units, &ctx);
+        let rendered = render_results(&units, &results);
+        assert_eq!(rendered, &["3.14", "[3; 5]", "2"]);
+
+        let test = create_app2(35);
+        test.mut_app().eval_context.number_input_mode = NumberInputMode::DecimalComma;
+        test.paste("3,14 * 2\n1.000,5 + 1");
+        test.assert_results(&["6.28", "1 001.5"][..]);
+    }
+
+    #[test]
+    fn test_results_are_displayed_with_the_rounding_mode() {
+        let test = create_app2(35);
+        test.paste("1/32\n-1/32");
+        test.assert_results(&["0.0312", "-0.0312"][..]);
+
+        let test = create_app2(35);
+        test.mut_app().eval_context.rounding_mode = RoundingMode::HalfUp;
+        test.paste("1/32\n-1/32");
+        test.assert_results(&["0.0313", "-0.0313"][..]);
+    }
+
+    #[test]
+    fn test_percent_of_a_line_reference() {
+        let test = create_app2(35);
+        test.paste("1\n2\n200 kg\n10% of &[3]\n10% of &[3] in g\n&[3] + 10%");
+        test.assert_results(&["1", "2", "200 kg", "20 kg", "20 000 g", "220 kg"][..]);
+    }
+
+    #[test]
+    fn test_line_reference_to_a_matrix() {
+        use crate::calc::dec;
+        let units = Units::new();
+        let results = evaluate_document(
+            "1\n2\n[1,2;3,4]\n&[3] * 2\n&[3] + 1\n2 * &[3] - &[3]\n&[3] * [1; 1]",
+            &units,
+            &EvalContext::default(),
+        );
+        let rendered = render_results(&units, &results);
+        assert_eq!(
+            rendered,
+            &[
+                "1",
+                "2",
+                "[1, 2; 3, 4]",
+                "[2, 4; 6, 8]",
+                "[2, 3; 4, 5]",
+                "[1, 2; 3, 4]",
+                "[3; 7]"
+            ]
+        );
+
+        // in the editor as well
+        let test = create_app2(35);
+        test.paste("[1,2;3,4]\n&[1] * 2");
+        match &test.mut_results()[content_y(1)] {
+            Ok(Some(CalcResult {
+                typ: CalcResultType::Matrix(mat),
+                ..
+            })) => {
+                assert_eq!((mat.row_count, mat.col_count), (2, 2));
+                assert_eq!(mat.cells[3].typ, CalcResultType::Number(dec(8)));
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_line_range() {
+        let test = create_app2(35);
+        test.paste("1\n2\n3\nsum(&[1:3])\nmean(&[2:3]) * 2\ncount(&[1:3])");
+        test.assert_results(&["1", "2", "3", "6", "5", "3"][..]);
+
+        // text lines are skipped
+        let test = create_app2(35);
+        test.paste("1\napples\n3\nsum(&[1:3])");
+        test.assert_results(&["1", "", "3", "4"][..]);
+
+        // so are the blank and comment lines
+        let test = create_app2(35);
+        test.paste("1\n\n// comment\n3\nsum(&[1:4])");
+        test.assert_results(&["1", "", "", "3", "4"][..]);
+    }
+
+    #[test]
+    fn test_line_range_skips_a_line_which_became_text() {
+        let test = create_app2(35);
+        test.paste("1\n2\n3\nsum(&[1:3])");
+        test.set_cursor_row_col(1, 1);
+        test.input(EditorInputEvent::Backspace, InputModifiers::none());
+        test.input(EditorInputEvent::Char('x'), InputModifiers::none());
+        test.assert_results(&["1", "", "3", "4"][..]);
+    }
+
+    #[test]
+    fn test_line_range_is_recalculated_if_a_line_in_it_changes() {
+        let test = create_app2(35);
+        test.paste("1\n2\n3\nsum(&[1:3])");
+        test.set_cursor_row_col(1, 1);
+        test.input(EditorInputEvent::Char('0'), InputModifiers::none());
+        test.assert_results(&["1", "20", "3", "24"][..]);
+    }
+
+    #[test]
+    fn test_invalid_line_ranges() {
+        let test = create_app2(35);
+        test.paste("1\n2\n3\nsum(&[3:1])\nsum(&[1:9])");
+        assert!(test.mut_results()[content_y(3)].is_err());
+        assert!(test.mut_results()[content_y(4)].is_err());
+
+        let test = create_app2(35);
+        test.paste("1\n2 kg + 3 m\n3\nsum(&[1:3])");
+        assert!(test.mut_results()[content_y(3)].is_err());
+
+        let test = create_app2(35);
+        test.mut_app().eval_context.skip_errors = true;
+        test.paste("1\n2 kg + 3 m\n3\nsum(&[1:3])");
+        test.assert_results(&["1", "Err", "3", "4"][..]);
+    }
+
+    #[test]
+    fn test_reevaluate_line_returns_the_changed_dependants() {
+        let test = create_app2(35);
+        test.paste("a = 2\nb = 3\na * 10\nb * 10\na + b");
+        test.assert_results(&["2", "3", "20", "30", "5"][..]);
+
+        let changed_lines = test.mut_app().reevaluate_line(
+            0,
+            "a = 5",
+            test.units(),
+            test.allocator(),
+            test.mut_tokens(),
+            test.mut_results(),
+            test.mut_vars(),
+            test.mut_editor_objects(),
+            test.mut_render_bucket(),
+        );
+        assert_eq!(changed_lines, vec![2, 4]);
+        assert_eq!(
+            test.get_editor_content(),
+            "a = 5\nb = 3\na * 10\nb * 10\na + b"
+        );
+        test.assert_results(&["5", "3", "50", "30", "8"][..]);
+
+        // the same value, nothing to repaint
+        let changed_lines = test.mut_app().reevaluate_line(
+            0,
+            "a = 5",
+            test.units(),
+            test.allocator(),
+            test.mut_tokens(),
+            test.mut_results(),
+            test.mut_vars(),
+            test.mut_editor_objects(),
+            test.mut_render_bucket(),
+        );
+        assert!(changed_lines.is_empty());
+    }
+
+    #[test]
+    fn test_reevaluate_line_clamps_the_cursor_and_the_selection() {
+        let test = create_app2(35);
+        test.paste("a = 2000\nb = 3\na * 10");
+        test.set_cursor_row_col(0, 8);
+        test.mut_app().reevaluate_line(
+            0,
+            "a = 5",
+            test.units(),
+            test.allocator(),
+            test.mut_tokens(),
+            test.mut_results(),
+            test.mut_vars(),
+            test.mut_editor_objects(),
+            test.mut_render_bucket(),
+        );
+        assert_eq!(test.get_cursor_pos(), Pos::from_row_column(0, 5));
+
+        test.set_selection(Selection::range(
+            Pos::from_row_column(0, 4),
+            Pos::from_row_column(2, 3),
+        ));
+        test.mut_app().reevaluate_line(
+            0,
+            "a",
+            test.units(),
+            test.allocator(),
+            test.mut_tokens(),
+            test.mut_results(),
+            test.mut_vars(),
+            test.mut_editor_objects(),
+            test.mut_render_bucket(),
+        );
+        assert_eq!(
+            test.get_selection(),
+            Selection::range(Pos::from_row_column(0, 1), Pos::from_row_column(2, 3))
+        );
+    }
+
+    #[test]
+    fn test_solving_linear_equations() {
+        let test = create_app2(35);
+        test.paste("2x + 3 = 11\nx * 2\ny/2 = 5\ny");
+        test.assert_results(&["4", "8", "10", "10"][..]);
+    }
+
+    #[test]
+    fn test_solving_nonlinear_equations_or_more_unknowns_is_an_error() {
+        let test = create_app2(35);
+        test.paste("x^2 = 4\n2x + y = 3\nfloor(x) + 2x = 5\n3 / x = 1");
+        test.assert_results(&["Err", "Err", "Err", "Err"][..]);
+    }
+
+    #[test]
+    fn test_labels_with_operators_are_not_equations() {
+        let test = create_app2(35);
+        test.paste(
+            "cost/day = 5\ncost/day * 2\ntotal - discount = 3\ntotal - discount\n5 kg budget = 4",
+        );
+        test.assert_results(&["5", "10", "3", "3", "4"][..]);
+    }
+
+    #[test]
+    fn test_assignments_which_are_not_equations() {
+        let test = create_app2(35);
+        test.paste("2m = 4\n2021 budget = 100");
+        test.assert_results(&["4", "100"][..]);
+    }
+
+    #[test]
+    fn test_as_conversion_keyword() {
+        let test = create_app2(35);
+        test.mut_app().eval_context.conversion_keyword = ConversionKeyword::As;
+        test.paste("10 km as m");
+        test.assert_results(&["10 000 m"][..]);
+
+        // the selection is evaluated with the same keyword
+        test.set_selection(Selection::range(
+            Pos::from_row_column(0, 0),
+            Pos::from_row_column(0, 10),
+        ));
+        let app = test.app();
+        let partial_result = evaluate_selection(
+            test.units(),
+            &app.editor,
+            &app.editor_content,
+            test.mut_vars(),
+            test.mut_results().as_slice(),
+            test.allocator(),
+            &app.eval_context,
+        );
+        assert_eq!(partial_result.as_deref(), Some("10 000 m"));
+    }
+
+    #[test]
+    fn test_converting_to_a_variable_holding_a_unit() {
+        let test = create_app2(35);
+        test.paste(
+            "target = 1 m
+500 cm in target
+2 kg in target
+five = 5
+500 cm in five
+two_km = 2 km
+500 m in two_km
+one_km = 1 km
+500 m in one_km",
+        );
+        // the magnitude of the target is not dropped silently
+        test.assert_results(
+            &[
+                "1 m", "5 m", "Err", "5", "Err", "2 km", "Err", "1 km", "0.5 km",
+            ][..],
+        );
+    }
+
+    #[test]
+    fn test_line_continuation() {
+        let test = create_app2(35);
+        test.paste("1 + \\\n2");
+        test.assert_results(&["", "3"][..]);
+        // only the tokens of the physical line are rendered
+        let tokens = &test.tokens()[content_y(1)].as_ref().unwrap().tokens;
+        assert_eq!(tokens_to_string(tokens), "2");
+        assert!(matches!(tokens[0].typ, TokenType::NumberLiteral(_)));
+    }
+
+    #[test]
+    fn test_removing_the_line_continuation() {
+        let test = create_app2(35);
+        test.paste("1 + \\\n2");
+        test.set_cursor_row_col(0, 5);
+        for _ in 0..4 {
+            test.input(EditorInputEvent::Backspace, InputModifiers::none());
+        }
+        test.assert_results(&["1", "2"][..]);
+    }
+
+    #[test]
+    fn test_line_continuation_over_multiple_lines() {
+        let test = create_app2(35);
+        test.paste("1 + \\\n2 * \\\n3\n&[3] * 2");
+        test.assert_results(&["", "", "7", "14"][..]);
+    }
+
+    #[test]
+    fn test_alternative_assignment_operators() {
+        let test = create_app2(35);
+        test.paste("x := 5\nx * 2\ny≔3\ny * x\nz:=1\nz + 1");
+        test.assert_results(&["5", "10", "3", "15", "1", "2"][..]);
+    }
+
+    #[test]
+    fn test_leading_equal_sign_does_not_create_a_variable() {
+        let test = create_app2(35);
+        test.paste("=2+2\nx = 2+2\nx * 2");
+        test.assert_results(&["4", "4", "8"][..]);
+    }
+
+    #[test]
+    fn test_angle_mode_directives() {
+        let test = create_app2(35);
+        test.paste("sin(π/2)\n>> deg\nsin(90)\ncos(60)\n>> rad\nsin(π/2)\ncos(π)");
+        test.assert_results(&["1", "", "1", "0.5", "", "1", "-1"][..]);
+    }
+
+    #[test]
+    fn test_angle_mode_directives_do_not_change_the_default() {
+        let test = create_app2(35);
+        test.paste(">> deg\nsin(90)");
+        test.assert_results(&["", "1"][..]);
+        assert_eq!(test.mut_app().eval_context.angle_mode, AngleMode::Rad);
+
+        // editing the directive recalculates the lines below it
+        test.set_cursor_row_col(0, 6);
+        for _ in 0..3 {
+            test.input(EditorInputEvent::Backspace, InputModifiers::none());
+        }
+        for ch in "rad".chars() {
+            test.input(EditorInputEvent::Char(ch), InputModifiers::none());
+        }
+        test.assert_results(&["", "0.894"][..]);
+    }
+
+    #[test]
+    fn test_custom_fn_registered_by_the_host() {
+        use crate::calc::dec;
+        let test = create_app2(35);
+        test.mut_app()
+            .eval_context
+            .fns
+            .register("double", |args| match args {
+                [CalcResult {
+                    typ: CalcResultType::Number(num),
+                    ..
+                }] => Ok(CalcResult::new(CalcResultType::Number(num * dec(2)), 0)),
+                _ => Err(()),
+            });
+        test.paste("double(21)");
+        test.assert_results(&["42"][..]);
+    }
+
+    #[test]
+    fn test_selection_is_evaluated_with_the_eval_context_of_the_app() {
+        use crate::calc::dec;
+        let test = create_app2(35);
+        test.mut_app()
+            .eval_context
+            .fns
+            .register("double", |args| match args {
+                [CalcResult {
+                    typ: CalcResultType::Number(num),
+                    ..
+                }] => Ok(CalcResult::new(CalcResultType::Number(num * dec(2)), 0)),
+                _ => Err(()),
+            });
+        test.paste("double(21) + 1");
+        test.set_selection(Selection::range(
+            Pos::from_row_column(0, 0),
+            Pos::from_row_column(0, 10),
+        ));
+        let app = test.app();
+        let partial_result = evaluate_selection(
+            test.units(),
+            &app.editor,
+            &app.editor_content,
+            test.mut_vars(),
+            test.mut_results().as_slice(),
+            test.allocator(),
+            &app.eval_context,
+        );
+        assert_eq!(partial_result.as_deref(), Some("42"));
+    }
+
+    #[test]
+    fn test_result_label() {
+        let test = create_app2(35);
+        test.paste("2+2 = result\nresult * 10");
+        test.assert_results(&["4", "40"][..]);
+    }
+
+    #[test]
+    fn test_result_label_redefinition() {
+        let test = create_app2(35);
+        test.paste("x = 3\n2+2 = x\nx * 10");
+        test.assert_results(&["3", "4", "40"][..]);
+    }
+
     #[test]
     fn test_variable_must_be_defined() {
         let test = create_app2(35);
@@ -7898,10 +9706,7 @@ sum",
         test.alt_key_released();
 
         match &test.tokens()[content_y(3)] {
-            Some(Tokens {
-                tokens,
-                shunting_output_stack: _,
-            }) => {
+            Some(Tokens { tokens, .. }) => {
                 match tokens[0].typ {
                     TokenType::LineReference { var_index } => assert_eq!(var_index, 0),
                     _ => panic!(),
@@ -7928,10 +9733,7 @@ sum",
         test.assert_results(&["1", "", "2", "3", "6", "", "50 year"][..]);
 
         match &test.tokens()[content_y(4)] {
-            Some(Tokens {
-                tokens,
-                shunting_output_stack: _,
-            }) => {
+            Some(Tokens { tokens, .. }) => {
                 match tokens[0].typ {
                     TokenType::LineReference { var_index } => assert_eq!(var_index, 0),
                     _ => panic!("{:?}", &tokens[0]),
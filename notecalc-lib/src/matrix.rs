@@ -1,5 +1,6 @@
 use crate::calc::{divide_op, multiply_op, CalcResult, CalcResultType};
 use crate::MATRIX_ASCII_HEADER_FOOTER_LINE_COUNT;
+use rust_decimal::prelude::*;
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct MatrixData {
@@ -44,6 +45,17 @@ impl MatrixData {
         todo!()
     }
 
+    /// applies `f` on each cell, fails if any of them fails
+    pub fn map_cells(&self, f: impl Fn(&CalcResult) -> Option<CalcResult>) -> Option<CalcResult> {
+        let cells: Option<Vec<CalcResult>> = self.cells.iter().map(f).collect();
+        cells.map(|it| {
+            CalcResult::new(
+                CalcResultType::Matrix(MatrixData::new(it, self.row_count, self.col_count)),
+                0,
+            )
+        })
+    }
+
     pub fn mult_scalar(&self, scalar: &CalcResult) -> Option<CalcResult> {
         let cells: Option<Vec<CalcResult>> = self
             .cells
@@ -73,6 +85,76 @@ impl MatrixData {
         })
     }
 
+    /// elementwise product, the matrices must have the same shape
+    pub fn hadamard(&self, other: &MatrixData) -> Option<MatrixData> {
+        if self.row_count != other.row_count || self.col_count != other.col_count {
+            return None;
+        }
+        let cells: Option<Vec<CalcResult>> = self
+            .cells
+            .iter()
+            .zip(other.cells.iter())
+            .map(|(a, b)| multiply_op(a, b))
+            .collect();
+        cells.map(|it| MatrixData::new(it, self.row_count, self.col_count))
+    }
+
+    /// Solves `self * x = b` with Gaussian elimination, `self` must be a square matrix of numbers
+    /// and `b` a vector of numbers with the same length, the result has the shape of `b`.
+    /// `None` if the matrix is singular.
+    pub fn solve(&self, b: &MatrixData) -> Option<MatrixData> {
+        let n = self.row_count;
+        if self.col_count != n || b.cells.len() != n || (b.row_count != 1 && b.col_count != 1) {
+            return None;
+        }
+        fn num(cell: &CalcResult) -> Option<Decimal> {
+            match &cell.typ {
+                CalcResultType::Number(num) => Some(*num),
+                _ => None,
+            }
+        }
+        // the augmented matrix, row by row
+        let mut rows: Vec<Vec<Decimal>> = Vec::with_capacity(n);
+        for row_i in 0..n {
+            let mut row = Vec::with_capacity(n + 1);
+            for col_i in 0..n {
+                row.push(num(self.cell(row_i, col_i))?);
+            }
+            row.push(num(&b.cells[row_i])?);
+            rows.push(row);
+        }
+        for col_i in 0..n {
+            // the row with the largest absolute value is the pivot
+            let pivot_i =
+                (col_i..n).max_by(|a, b| rows[*a][col_i].abs().cmp(&rows[*b][col_i].abs()))?;
+            if rows[pivot_i][col_i].is_zero() {
+                return None;
+            }
+            rows.swap(col_i, pivot_i);
+            for row_i in col_i + 1..n {
+                let factor = rows[row_i][col_i].checked_div(&rows[col_i][col_i])?;
+                for k in col_i..=n {
+                    let sub = factor.checked_mul(&rows[col_i][k])?;
+                    rows[row_i][k] = rows[row_i][k].checked_sub(&sub)?;
+                }
+            }
+        }
+        // back substitution
+        let mut x = vec![Decimal::zero(); n];
+        for row_i in (0..n).rev() {
+            let mut sum = rows[row_i][n];
+            for k in row_i + 1..n {
+                sum = sum.checked_sub(&rows[row_i][k].checked_mul(&x[k])?)?;
+            }
+            x[row_i] = sum.checked_div(&rows[row_i][row_i])?.normalize();
+        }
+        let cells = x
+            .into_iter()
+            .map(|it| CalcResult::new(CalcResultType::Number(it), 0))
+            .collect();
+        Some(MatrixData::new(cells, b.row_count, b.col_count))
+    }
+
     pub fn transposed(&self) -> MatrixData {
         let mut result = MatrixData::new(
             Vec::with_capacity(self.cells.len()),
@@ -1,4 +1,4 @@
-use crate::calc::{CalcResult, CalcResultType};
+use crate::calc::{dec, pow, CalcResult, CalcResultType, RoundingMode};
 use crate::units::units::Units;
 use crate::{ResultFormat, ResultLengths};
 use byteorder::WriteBytesExt;
@@ -7,12 +7,147 @@ use rust_decimal::Decimal;
 use smallvec::SmallVec;
 use std::io::Cursor;
 
+/// How a result is rendered by [`format_result`].
+#[derive(Clone, Debug)]
+pub struct RenderConfig<'a> {
+    pub units: &'a Units,
+    pub format: ResultFormat,
+    /// `None` means as many as needed
+    pub decimal_count: Option<usize>,
+    /// how ties are broken when the result is rounded to `decimal_count` places
+    pub rounding_mode: RoundingMode,
+    /// e.g. "1 000 000" instead of "1000000"
+    pub use_grouping: bool,
+    /// e.g. "kg*m/s^2" is rendered as "N"
+    pub simplify_units: bool,
+    /// If set, lengths, masses and volumes are displayed in the units of this system
+    /// (only if `simplify_units` is true, i.e. there was no explicit conversion)
+    pub unit_system: Option<UnitSystem>,
+    /// e.g. "25 % (0.25)" instead of "25 %"
+    pub show_percent_decimal: bool,
+    /// The SI prefix of a unit is chosen so that the number is in [1, 1000),
+    /// e.g. "1500 m" is displayed as "1.5 km" (only if `simplify_units` is true)
+    pub auto_prefix: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnitSystem {
+    Metric,
+    Imperial,
+    UsCustomary,
+}
+
+impl UnitSystem {
+    /// the units for lengths, masses and volumes
+    fn preferred_units(&self) -> &'static [&'static str] {
+        match self {
+            UnitSystem::Metric => &["m", "kg", "m^3"],
+            UnitSystem::Imperial => &["ft", "lbm", "impgal"],
+            UnitSystem::UsCustomary => &["ft", "lbm", "gal"],
+        }
+    }
+
+    /// The same quantity with the unit of this system, `None` if it is not a length, mass or
+    /// volume.
+    /// Quantities are stored in base units, so only the unit has to be replaced.
+    pub fn convert(&self, units: &Units, result: &CalcResult) -> Option<CalcResult> {
+        let (num, unit) = match &result.typ {
+            CalcResultType::Quantity(num, unit) if unit.root == 1 => (num, unit),
+            _ => return None,
+        };
+        for name in self.preferred_units() {
+            let name: Vec<char> = name.chars().collect();
+            let (preferred, parsed_len) = units.parse(&name);
+            if parsed_len != name.len() || preferred.dimensions != unit.dimensions {
+                continue;
+            }
+            return Some(CalcResult::new(
+                CalcResultType::Quantity(num.clone(), preferred),
+                result.get_index_into_tokens(),
+            ));
+        }
+        return None;
+    }
+}
+
+impl<'a> RenderConfig<'a> {
+    pub fn new(units: &'a Units) -> RenderConfig<'a> {
+        RenderConfig {
+            units,
+            format: ResultFormat::Dec,
+            decimal_count: Some(4),
+            rounding_mode: RoundingMode::default(),
+            use_grouping: false,
+            simplify_units: true,
+            unit_system: None,
+            show_percent_decimal: false,
+            auto_prefix: false,
+        }
+    }
+}
+
+/// Renders a result the same way as the editor does, without the editor.
+pub fn format_result(result: &CalcResult, cfg: &RenderConfig) -> String {
+    let converted = cfg
+        .unit_system
+        .filter(|_| cfg.simplify_units)
+        .and_then(|system| system.convert(cfg.units, result));
+    let converted = if cfg.auto_prefix && cfg.simplify_units {
+        with_auto_prefix(cfg.units, converted.as_ref().unwrap_or(result)).or(converted)
+    } else {
+        converted
+    };
+    let mut rendered = render_result(
+        cfg.units,
+        converted.as_ref().unwrap_or(result),
+        &cfg.format,
+        converted.is_some() || !cfg.simplify_units,
+        cfg.decimal_count,
+        cfg.rounding_mode,
+        cfg.use_grouping,
+    );
+    let percent_decimal = match &result.typ {
+        CalcResultType::Percentage(num) if cfg.show_percent_decimal => num.checked_div(&dec(100)),
+        _ => None,
+    };
+    if let Some(decimal) = percent_decimal {
+        let decimal = CalcResult::new(CalcResultType::Number(decimal), 0);
+        rendered.push_str(" (");
+        rendered.push_str(&render_result(
+            cfg.units,
+            &decimal,
+            &cfg.format,
+            false,
+            cfg.decimal_count,
+            cfg.rounding_mode,
+            cfg.use_grouping,
+        ));
+        rendered.push(')');
+    }
+    return rendered;
+}
+
+fn with_auto_prefix(units: &Units, result: &CalcResult) -> Option<CalcResult> {
+    match &result.typ {
+        CalcResultType::Quantity(num, unit) => {
+            let unit = unit.simplify(units).unwrap_or_else(|| unit.clone());
+            let prefixed = units.auto_prefix(num, &unit)?;
+            Some(CalcResult::new(
+                CalcResultType::Quantity(num.clone(), prefixed),
+                result.get_index_into_tokens(),
+            ))
+        }
+        _ => None,
+    }
+}
+
 pub fn render_result(
     units: &Units,
     result: &CalcResult,
     format: &ResultFormat,
     there_was_unit_conversion: bool,
     decimal_count: Option<usize>,
+    rounding_mode: RoundingMode,
     use_grouping: bool,
 ) -> String {
     let mut c = Cursor::new(Vec::with_capacity(64));
@@ -23,6 +158,7 @@ pub fn render_result(
         there_was_unit_conversion,
         &mut c,
         decimal_count,
+        rounding_mode,
         use_grouping,
     );
     return unsafe { String::from_utf8_unchecked(c.into_inner()) };
@@ -35,11 +171,12 @@ pub fn render_result_into(
     there_was_unit_conversion: bool,
     f: &mut impl std::io::Write,
     decimal_count: Option<usize>,
+    rounding_mode: RoundingMode,
     use_grouping: bool,
 ) -> ResultLengths {
     match &result.typ {
         CalcResultType::Quantity(num, unit) => {
-            if *format != ResultFormat::Dec {
+            if *format != ResultFormat::Dec && *format != ResultFormat::Eng {
                 f.write_u8(b'E').expect("");
                 f.write_u8(b'r').expect("");
                 f.write_u8(b'r').expect("");
@@ -56,15 +193,16 @@ pub fn render_result_into(
             };
             let unit = final_unit.as_ref().unwrap_or(unit);
             if unit.units.is_empty() {
-                num_to_string(f, &num, &ResultFormat::Dec, decimal_count, use_grouping)
+                num_to_string(f, &num, format, decimal_count, rounding_mode, use_grouping)
             } else {
                 let denormalized_num = unit.from_base_to_this_unit(num);
                 if let Some(denormalized_num) = denormalized_num {
                     let mut lens = num_to_string(
                         f,
                         &denormalized_num,
-                        &ResultFormat::Dec,
+                        format,
                         decimal_count,
+                        rounding_mode,
                         use_grouping,
                     );
                     f.write_u8(b' ').expect("");
@@ -100,10 +238,10 @@ pub fn render_result_into(
         }
         CalcResultType::Number(num) => {
             // TODO optimize
-            num_to_string(f, num, format, decimal_count, use_grouping)
+            num_to_string(f, num, format, decimal_count, rounding_mode, use_grouping)
         }
         CalcResultType::Percentage(num) => {
-            if *format != ResultFormat::Dec {
+            if *format != ResultFormat::Dec && *format != ResultFormat::Eng {
                 f.write_u8(b'E').expect("");
                 f.write_u8(b'r').expect("");
                 f.write_u8(b'r').expect("");
@@ -114,7 +252,7 @@ pub fn render_result_into(
                 };
             } else {
                 let mut lens =
-                    num_to_string(f, num, &ResultFormat::Dec, decimal_count, use_grouping);
+                    num_to_string(f, num, format, decimal_count, rounding_mode, use_grouping);
                 f.write_u8(b' ').expect("");
                 f.write_u8(b'%').expect("");
                 lens.unit_part_len += 1;
@@ -134,7 +272,16 @@ pub fn render_result_into(
                         f.write_u8(b' ').expect("");
                     }
                     let cell = &mat.cells[row_i * mat.col_count + col_i];
-                    render_result_into(units, cell, format, false, f, decimal_count, use_grouping);
+                    render_result_into(
+                        units,
+                        cell,
+                        format,
+                        false,
+                        f,
+                        decimal_count,
+                        rounding_mode,
+                        use_grouping,
+                    );
                 }
             }
             f.write_u8(b']').expect("");
@@ -152,23 +299,50 @@ fn num_to_string(
     num: &Decimal,
     format: &ResultFormat,
     decimal_count: Option<usize>,
+    rounding_mode: RoundingMode,
     use_grouping: bool,
 ) -> ResultLengths {
-    let num_a = if *format != ResultFormat::Dec && num.trunc() == *num {
-        Some(num.clone())
+    if *format == ResultFormat::Eng {
+        return if let Some((mantissa, exp)) =
+            to_engineering_notation(num, decimal_count, rounding_mode)
+        {
+            let mut lens = num_to_string(
+                f,
+                &mantissa,
+                &ResultFormat::Dec,
+                None,
+                rounding_mode,
+                use_grouping,
+            );
+            if exp != 0 {
+                let exp = format!("e{}", exp);
+                for ch in exp.as_bytes() {
+                    f.write_u8(*ch).expect("");
+                }
+                lens.frac_part_len += exp.len();
+            }
+            lens
+        } else {
+            f.write_u8(b'E').expect("");
+            f.write_u8(b'r').expect("");
+            f.write_u8(b'r').expect("");
+            ResultLengths {
+                int_part_len: 3,
+                frac_part_len: 0,
+                unit_part_len: 0,
+            }
+        };
+    }
+    let num = if *format != ResultFormat::Dec && num.trunc() == *num {
+        num.clone()
     } else if let Some(decimal_count) = decimal_count {
-        let mut result = num.clone();
-        result.rescale(decimal_count as u32);
-        Some(result.normalize())
+        rounding_mode
+            .round_dp(num, decimal_count as u32)
+            .normalize()
     } else {
-        let with_scale_0 = num.trunc();
-        if *num == with_scale_0 {
-            Some(with_scale_0)
-        } else {
-            None
-        }
+        // no trailing zeros, so e.g. "5.00" is rendered as "5"
+        num.normalize()
     };
-    let num = num_a.as_ref().unwrap_or(num);
 
     return if *format == ResultFormat::Bin || *format == ResultFormat::Hex {
         if let Some(n) = num.to_i64() {
@@ -209,7 +383,7 @@ fn num_to_string(
         let string = if num.scale() == 0 {
             num.to_string()
         } else {
-            if let Some(without_repeating_fract) = remove_repeatings(num) {
+            if let Some(without_repeating_fract) = remove_repeatings(&num) {
                 without_repeating_fract.to_string()
             } else {
                 num.to_string()
@@ -253,6 +427,55 @@ fn num_to_string(
     };
 }
 
+/// Splits the number into a mantissa in [1, 1000) and an exponent which is a multiple of 3,
+/// e.g. 12300 -> (12.3, 3), 0.0012 -> (1.2, -3)
+fn to_engineering_notation(
+    num: &Decimal,
+    decimal_count: Option<usize>,
+    rounding_mode: RoundingMode,
+) -> Option<(Decimal, i64)> {
+    if num.is_zero() {
+        return Some((Decimal::zero(), 0));
+    }
+    let abs = num.abs().normalize();
+    // the exponent of the first significant digit
+    let string = abs.to_string();
+    let exp = match string.bytes().position(|it| it == b'.') {
+        Some(pos) if &string[0..pos] == "0" => {
+            let leading_zeros = string[pos + 1..]
+                .bytes()
+                .take_while(|it| *it == b'0')
+                .count();
+            -(leading_zeros as i64 + 1)
+        }
+        Some(pos) => pos as i64 - 1,
+        None => string.len() as i64 - 1,
+    };
+    let mut eng_exp = exp.div_euclid(3) * 3;
+    let mut mantissa = if eng_exp >= 0 {
+        abs.checked_div(&pow(dec(10), eng_exp)?)?
+    } else {
+        abs.checked_mul(&pow(dec(10), -eng_exp)?)?
+    };
+    if let Some(decimal_count) = decimal_count {
+        mantissa = rounding_mode.round_dp(&mantissa, decimal_count as u32);
+    }
+    // rounding can carry over, e.g. 999.99 -> 1000
+    if mantissa >= dec(1000) {
+        mantissa = mantissa.checked_div(&dec(1000))?;
+        eng_exp += 3;
+    }
+    let mantissa = mantissa.normalize();
+    Some((
+        if num.is_sign_negative() {
+            -mantissa
+        } else {
+            mantissa
+        },
+        eng_exp,
+    ))
+}
+
 fn remove_repeatings(num: &Decimal) -> Option<Decimal> {
     let string = num.to_string();
     if let Some(pos) = string.bytes().position(|it| it == b'.') {
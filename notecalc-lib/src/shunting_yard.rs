@@ -1,5 +1,5 @@
 use crate::calc::ShuntingYardResult;
-use crate::functions::FnType;
+use crate::functions::{FnRegistry, FnType};
 use crate::token_parser::{Assoc, OperatorTokenType, Token, TokenType};
 use std::ops::Neg;
 
@@ -33,6 +33,8 @@ enum ParenStackEntry {
     Fn(FnStackEntry),
     /// e.g. (12 + 3)
     Simple,
+    /// e.g. |-3|, the index of the opening bar token
+    AbsBar(usize),
 }
 
 impl ParenStackEntry {
@@ -191,7 +193,7 @@ impl ValidationState {
                 // red in case of e.g. missing/wrong parameter
                 false
             }
-            Some(ParenStackEntry::Simple) => true,
+            Some(ParenStackEntry::Simple) | Some(ParenStackEntry::AbsBar(..)) => true,
             None => true, // if there is no matrix/fn at the top of stack, it is an error
         }
     }
@@ -209,7 +211,9 @@ impl ValidationState {
             Some(ParenStackEntry::Fn(FnStackEntry { fn_arg_count, .. })) => {
                 *fn_arg_count += 1;
             }
-            Some(ParenStackEntry::Simple) | None => panic!(),
+            Some(ParenStackEntry::Simple) | Some(ParenStackEntry::AbsBar(..)) | None => {
+                panic!()
+            }
         }
     }
 
@@ -245,6 +249,7 @@ impl ShuntingYard {
     pub fn shunting_yard<'text_ptr>(
         tokens: &mut Vec<Token<'text_ptr>>,
         output_stack: &mut Vec<ShuntingYardResult>,
+        fns: &FnRegistry,
     ) {
         // TODO: into iter!!!
         // TODO:mem extract out so no alloc SmallVec?
@@ -261,7 +266,9 @@ impl ShuntingYard {
                     return;
                 }
                 TokenType::StringLiteral => {
-                    if let Some(fn_type) = FnType::value_of(input_token.ptr) {
+                    if let Some(fn_type) = FnType::value_of(input_token.ptr)
+                        .or_else(|| fns.index_of(input_token.ptr).map(FnType::Custom))
+                    {
                         // next token is parenthesis
                         if tokens
                             .get(input_index as usize + 1)
@@ -324,6 +331,30 @@ impl ShuntingYard {
                 }
                 TokenType::Operator(op) => match op {
                     OperatorTokenType::ParenOpen => {
+                        let prev_token_is_num_or_closing_paren = input_index > 0
+                            && matches!(
+                                tokens[(input_index - 1) as usize].typ,
+                                TokenType::NumberLiteral(..)
+                                    | TokenType::Operator(OperatorTokenType::ParenClose)
+                            );
+                        if !v.expect_expression && prev_token_is_num_or_closing_paren {
+                            // implicit multiplication, e.g. 2(3+4) or (1+2)(3+4)
+                            let mult = OperatorTokenType::Mult;
+                            v.had_operator = true;
+                            ShuntingYard::operator_rule(
+                                &mult,
+                                &mut operator_stack,
+                                output_stack,
+                                &mut v.last_valid_operator_index,
+                                &mut v.last_valid_output_range,
+                                input_index,
+                            );
+                            operator_stack.push(ShuntingYardOperatorResult {
+                                op_type: mult,
+                                index_into_tokens: input_index,
+                            });
+                            v.expect_expression = true;
+                        }
                         operator_stack.push(ShuntingYardOperatorResult {
                             op_type: op.clone(),
                             index_into_tokens: input_index,
@@ -333,14 +364,22 @@ impl ShuntingYard {
                     }
                     OperatorTokenType::ParenClose => {
                         let is_error = match v.parenthesis_stack.last() {
-                            None | Some(ParenStackEntry::Matrix(..)) => true,
+                            None
+                            | Some(ParenStackEntry::Matrix(..))
+                            | Some(ParenStackEntry::AbsBar(..)) => true,
                             Some(ParenStackEntry::Simple) | Some(ParenStackEntry::Fn(..)) => false,
                         };
-                        let prev_token_is_open_paren = input_index > 0
-                            && matches!(
-                                tokens[(input_index - 1) as usize].typ,
-                                TokenType::Operator(OperatorTokenType::ParenOpen)
-                            );
+                        // e.g. pi() or pi( )
+                        let prev_token_is_open_paren = matches!(
+                            tokens[0..input_index as usize].iter().rev().find(|it| {
+                                it.typ != TokenType::StringLiteral
+                                    || !it.ptr.iter().all(|ch| ch.is_ascii_whitespace())
+                            }),
+                            Some(Token {
+                                typ: TokenType::Operator(OperatorTokenType::ParenOpen),
+                                ..
+                            })
+                        );
 
                         if !prev_token_is_open_paren && (v.expect_expression || is_error) {
                             ShuntingYard::rollback(
@@ -374,7 +413,22 @@ impl ShuntingYard {
                                 fn_entry.fn_token_index as isize,
                             );
                         }
-                        if v.can_be_valid_closing_token() && !output_stack.is_empty() {
+                        // e.g. (3+4) kg, the unit belongs to the group, the range will be closed by it
+                        let unit_follows =
+                            matches!(
+                                ShuntingYard::get_next_nonstring_token(tokens, input_index as usize + 1),
+                                Some((
+                                    Token {
+                                        typ: TokenType::Operator(OperatorTokenType::ApplyUnit(..)),
+                                        ..
+                                    },
+                                    _
+                                ))
+                            );
+                        if v.can_be_valid_closing_token()
+                            && !output_stack.is_empty()
+                            && !unit_follows
+                        {
                             ShuntingYard::send_everything_to_output(
                                 &mut operator_stack,
                                 output_stack,
@@ -439,6 +493,7 @@ impl ShuntingYard {
                                 ptr: &[],
                                 typ: matrix_token_type.clone(),
                                 has_error: false,
+                                is_ambiguous: false,
                             },
                         );
                         // we inserted one element so increase it
@@ -518,6 +573,23 @@ impl ShuntingYard {
                         }
                     }
                     OperatorTokenType::Assign => {
+                        if !v.had_assign_op
+                            && !v.had_non_ws_string_literal
+                            && !v.expect_expression
+                            && v.parenthesis_stack.is_empty()
+                            && v.last_valid_output_range.is_some()
+                            && ShuntingYard::only_label_follows(tokens, input_index as usize + 1)
+                        {
+                            // "2 + 2 = four", the label after the expression names its result
+                            v.had_assign_op = true;
+                            v.assign_op_input_token_pos = Some(input_index as usize);
+                            ShuntingYard::set_tokens_to_string(
+                                tokens,
+                                input_index as usize + 1,
+                                tokens.len() - 1,
+                            );
+                            break;
+                        }
                         if v.had_assign_op || !v.had_non_ws_string_literal {
                             if let Some(assign_op_input_token_pos) = v.assign_op_input_token_pos {
                                 tokens[assign_op_input_token_pos].typ = TokenType::StringLiteral;
@@ -627,26 +699,31 @@ impl ShuntingYard {
                         }
                     }
                     OperatorTokenType::UnitConverter => {
-                        // the converter must be the last operator, only a unit can follow it
+                        // the converter must be the last operator, only a unit
+                        // (or a variable holding a unit) can follow it
                         // so clear the operator stack, push the next unit onto the output
 
                         // push the unit onto the output, and close it
-                        if let Some((
-                            Token {
-                                typ: TokenType::Unit(unit),
-                                ..
-                            },
-                            offset,
-                        )) =
+                        if let Some((Token { typ: target, .. }, offset)) =
                             ShuntingYard::get_next_nonstring_token(tokens, input_index as usize + 1)
+                                .filter(|(it, _)| {
+                                    matches!(
+                                        it.typ,
+                                        TokenType::Unit(..) | TokenType::Variable { .. }
+                                    )
+                                })
                         {
                             if ShuntingYard::get_next_nonstring_token(
                                 tokens,
                                 input_index as usize + 1 + offset + 1,
                             )
-                            .is_some()
+                            .map(|(it, _)| {
+                                it.typ != TokenType::Operator(OperatorTokenType::UnitConverter)
+                            })
+                            .unwrap_or(false)
                             {
-                                // after 'in', there must be a single unit component, nothing else
+                                // after 'in', there must be a single unit component, nothing else,
+                                // except an other conversion (e.g. "1 mi in km in m")
                                 continue;
                             }
                             v.expect_expression = false;
@@ -660,7 +737,7 @@ impl ShuntingYard {
                                     &mut v.last_valid_operator_index,
                                     &mut v.last_valid_output_range,
                                 );
-                                to_out2(output_stack, TokenType::Unit(unit.clone()), input_index);
+                                to_out2(output_stack, target.clone(), input_index);
                                 to_out2(output_stack, TokenType::Operator(op.clone()), input_index);
                                 v.close_valid_range(
                                     output_stack.len(),
@@ -672,6 +749,95 @@ impl ShuntingYard {
                             // it is not an "in" operator but a string literal
                         }
                     }
+                    OperatorTokenType::AbsBar => {
+                        let closes_bar = !v.expect_expression
+                            && matches!(
+                                v.parenthesis_stack.last(),
+                                Some(ParenStackEntry::AbsBar(..))
+                            );
+                        if !closes_bar {
+                            if !v.expect_expression {
+                                // e.g. "2 |-3|", the bar starts a new expression
+                                ShuntingYard::rollback(
+                                    &mut operator_stack,
+                                    output_stack,
+                                    input_index,
+                                    &mut v,
+                                );
+                            }
+                            // the opening bar works the same as "abs("
+                            v.parenthesis_stack
+                                .push(ParenStackEntry::AbsBar(input_index as usize));
+                            v.prev_token_type = ValidationTokenType::Nothing;
+                            v.expect_expression = true;
+                            operator_stack.push(ShuntingYardOperatorResult {
+                                op_type: OperatorTokenType::ParenOpen,
+                                index_into_tokens: input_index,
+                            });
+                            continue;
+                        }
+                        v.prev_token_type = ValidationTokenType::Expr;
+                        ShuntingYard::send_anything_until_opening_bracket(
+                            &mut operator_stack,
+                            output_stack,
+                            &OperatorTokenType::ParenOpen,
+                        );
+                        if let Some(ParenStackEntry::AbsBar(bar_token_index)) =
+                            v.parenthesis_stack.pop()
+                        {
+                            to_out2(
+                                output_stack,
+                                TokenType::Operator(OperatorTokenType::Fn {
+                                    arg_count: 1,
+                                    typ: FnType::Abs,
+                                }),
+                                bar_token_index as isize,
+                            );
+                        }
+                        // e.g. |-3| kg
+                        let unit_follows =
+                            matches!(
+                                ShuntingYard::get_next_nonstring_token(tokens, input_index as usize + 1),
+                                Some((
+                                    Token {
+                                        typ: TokenType::Operator(OperatorTokenType::ApplyUnit(..)),
+                                        ..
+                                    },
+                                    _
+                                ))
+                            );
+                        if v.can_be_valid_closing_token() && !unit_follows {
+                            ShuntingYard::send_everything_to_output(
+                                &mut operator_stack,
+                                output_stack,
+                                &mut v.last_valid_operator_index,
+                                &mut v.last_valid_output_range,
+                            );
+                            v.close_valid_range(
+                                output_stack.len(),
+                                input_index,
+                                operator_stack.len(),
+                            );
+                        }
+                    }
+                    OperatorTokenType::Sqrt | OperatorTokenType::Not => {
+                        // prefix operator, e.g. √9 or "not true"
+                        if !v.expect_expression {
+                            // e.g. "2√9", the sqrt starts a new expression
+                            ShuntingYard::rollback(
+                                &mut operator_stack,
+                                output_stack,
+                                input_index,
+                                &mut v,
+                            );
+                        }
+                        v.had_operator = true;
+                        v.prev_token_type = ValidationTokenType::Op;
+                        operator_stack.push(ShuntingYardOperatorResult {
+                            op_type: op.clone(),
+                            index_into_tokens: input_index,
+                        });
+                    }
                     OperatorTokenType::UnaryPlus | OperatorTokenType::UnaryMinus => {
                         panic!("Token parser does not generate unary operators");
                     }
@@ -703,14 +869,19 @@ impl ShuntingYard {
                     }
                 },
                 TokenType::NumberErr => {
-                    ShuntingYard::handle_num_token(
-                        TokenType::NumberErr,
-                        &mut v,
-                        tokens,
-                        output_stack,
-                        &mut operator_stack,
-                        &mut input_index,
-                    );
+                    // an unparsable number invalidates the whole line, so only the
+                    // erroneous number is kept, the evaluation of which results in an error
+                    output_stack.clear();
+                    operator_stack.clear();
+                    to_out2(output_stack, TokenType::NumberErr, input_index);
+                    v.last_valid_input_token_range =
+                        Some((input_index as usize, input_index as usize));
+                    v.last_valid_output_range = Some((0, 0));
+                    v.last_valid_operator_index = None;
+                    v.assign_op_input_token_pos = None;
+                    // everything behind it will be turned into string
+                    input_index = tokens.len() as isize - 1;
+                    break;
                 }
                 TokenType::NumberLiteral(num) => {
                     // TODO nézd meg muszáj e klnozni, ne me tudja ez a fv átvenni az ownershipet
@@ -725,7 +896,9 @@ impl ShuntingYard {
                         &mut input_index,
                     );
                 }
-                TokenType::Variable { .. } | TokenType::LineReference { .. } => {
+                TokenType::Variable { .. }
+                | TokenType::LineReference { .. }
+                | TokenType::LineRange { .. } => {
                     if !v.expect_expression {
                         ShuntingYard::rollback(
                             &mut operator_stack,
@@ -814,6 +987,9 @@ impl ShuntingYard {
         // it is simpler and won't cause any trouble
         if !output_stack.is_empty() && v.assign_op_input_token_pos.is_some() {
             if let Some(assign_op_input_token_pos) = v.assign_op_input_token_pos {
+                // in the "expr = label" form the operator is outside of the valid range
+                tokens[assign_op_input_token_pos].typ =
+                    TokenType::Operator(OperatorTokenType::Assign);
                 output_stack.push(ShuntingYardResult::new(
                     TokenType::Operator(OperatorTokenType::Assign),
                     assign_op_input_token_pos,
@@ -874,6 +1050,32 @@ impl ShuntingYard {
         }
     }
 
+    /// true if the remaining tokens are a single identifier (and whitespaces)
+    fn only_label_follows(tokens: &[Token], from: usize) -> bool {
+        let mut label_count = 0;
+        for token in tokens.iter().skip(from) {
+            match token.typ {
+                TokenType::StringLiteral if token.ptr.iter().all(|it| it.is_whitespace()) => {}
+                TokenType::StringLiteral => {
+                    let is_identifier = token.ptr[0].is_alphabetic() || token.ptr[0] == '_';
+                    if !is_identifier
+                        || !token
+                            .ptr
+                            .iter()
+                            .all(|it| it.is_alphanumeric() || *it == '_')
+                    {
+                        return false;
+                    }
+                    label_count += 1;
+                }
+                // redefinition of an existing variable
+                TokenType::Variable { .. } => label_count += 1,
+                _ => return false,
+            }
+        }
+        return label_count == 1;
+    }
+
     fn get_next_nonstring_token<'a, 'text_ptr>(
         tokens: &'a [Token<'text_ptr>],
         i: usize,
@@ -1008,9 +1210,9 @@ pub mod tests {
     use super::*;
     use crate::calc::{CalcResult, CalcResultType};
     use crate::helper::create_vars;
-    use crate::token_parser::TokenParser;
+    use crate::token_parser::{ParseOptions, TokenParser};
     use crate::units::units::{UnitOutput, Units};
-    use crate::{Variable, Variables, MAX_LINE_COUNT};
+    use crate::{Variable, Variables, VARIABLE_COUNT};
     use bumpalo::Bump;
     use rust_decimal::prelude::*;
 
@@ -1019,6 +1221,7 @@ pub mod tests {
             ptr: &[],
             typ: TokenType::NumberLiteral(n.into()),
             has_error: false,
+            is_ambiguous: false,
         }
     }
 
@@ -1027,6 +1230,7 @@ pub mod tests {
             ptr: &[],
             typ: TokenType::NumberLiteral(n.into()),
             has_error: true,
+            is_ambiguous: false,
         }
     }
 
@@ -1035,6 +1239,7 @@ pub mod tests {
             ptr: &[],
             typ: TokenType::NumberErr,
             has_error: true,
+            is_ambiguous: false,
         }
     }
 
@@ -1043,6 +1248,7 @@ pub mod tests {
             ptr: &[],
             typ: TokenType::Operator(op_repr),
             has_error: false,
+            is_ambiguous: false,
         }
     }
 
@@ -1051,6 +1257,7 @@ pub mod tests {
             ptr: &[],
             typ: TokenType::Operator(op_repr),
             has_error: true,
+            is_ambiguous: false,
         }
     }
 
@@ -1059,6 +1266,7 @@ pub mod tests {
             ptr: unsafe { std::mem::transmute(op_repr) },
             typ: TokenType::StringLiteral,
             has_error: false,
+            is_ambiguous: false,
         }
     }
 
@@ -1067,6 +1275,7 @@ pub mod tests {
             ptr: unsafe { std::mem::transmute(op_repr) },
             typ: TokenType::Header,
             has_error: false,
+            is_ambiguous: false,
         }
     }
 
@@ -1075,6 +1284,7 @@ pub mod tests {
             ptr: unsafe { std::mem::transmute(op_repr) },
             typ: TokenType::Operator(OperatorTokenType::ApplyUnit(UnitOutput::new())),
             has_error: false,
+            is_ambiguous: false,
         }
     }
 
@@ -1083,6 +1293,7 @@ pub mod tests {
             ptr: unsafe { std::mem::transmute(op_repr) },
             typ: TokenType::Unit(UnitOutput::new()),
             has_error: false,
+            is_ambiguous: false,
         }
     }
 
@@ -1091,6 +1302,7 @@ pub mod tests {
             ptr: unsafe { std::mem::transmute(op_repr) },
             typ: TokenType::Variable { var_index: 0 },
             has_error: false,
+            is_ambiguous: false,
         }
     }
 
@@ -1099,6 +1311,7 @@ pub mod tests {
             ptr: unsafe { std::mem::transmute(op_repr) },
             typ: TokenType::LineReference { var_index: 0 },
             has_error: false,
+            is_ambiguous: false,
         }
     }
 
@@ -1107,6 +1320,7 @@ pub mod tests {
             ptr: &[],
             typ: TokenType::NumberLiteral(Decimal::from_f64(n).unwrap()),
             has_error: false,
+            is_ambiguous: false,
         }
     }
 
@@ -1201,21 +1415,41 @@ pub mod tests {
         tokens: &mut Vec<Token<'text_ptr>>,
         vars: &'b Variables,
         allocator: &'text_ptr Bump,
+    ) -> Vec<ShuntingYardResult> {
+        do_shunting_yard_with_fns(text, units, tokens, vars, &FnRegistry::new(), allocator)
+    }
+
+    pub fn do_shunting_yard_with_fns<'text_ptr, 'units, 'b>(
+        text: &[char],
+        units: &'units Units,
+        tokens: &mut Vec<Token<'text_ptr>>,
+        vars: &'b Variables,
+        fns: &FnRegistry,
+        allocator: &'text_ptr Bump,
     ) -> Vec<ShuntingYardResult> {
         let mut output = vec![];
-        TokenParser::parse_line(&text, vars, tokens, &units, 10, allocator);
-        ShuntingYard::shunting_yard(tokens, &mut output);
+        TokenParser::parse_line(
+            &text,
+            vars,
+            tokens,
+            &units,
+            10,
+            allocator,
+            ParseOptions::default(),
+        );
+        ShuntingYard::shunting_yard(tokens, &mut output, fns);
         return output;
     }
 
     fn test_output_vars(var_names: &[&'static [char]], text: &str, expected_tokens: &[Token]) {
-        let var_names: Vec<Option<Variable>> = (0..MAX_LINE_COUNT + 1)
+        let var_names: Vec<Option<Variable>> = (0..VARIABLE_COUNT)
             .into_iter()
             .map(|index| {
                 if let Some(var_name) = var_names.get(index) {
                     Some(Variable {
                         name: Box::from(*var_name),
                         value: Err(()),
+                        placeholder: false,
                     })
                 } else {
                     None
@@ -1237,6 +1471,7 @@ pub mod tests {
                     ptr: &[],
                     typ: it.typ.clone(),
                     has_error: false,
+                    is_ambiguous: false,
                 })
                 .collect::<Vec<_>>()
                 .as_slice(),
@@ -1258,10 +1493,12 @@ pub mod tests {
         vars[0] = Some(Variable {
             name: Box::from(&['b', '0'][..]),
             value: Ok(CalcResult::new(CalcResultType::Number(Decimal::zero()), 0)),
+            placeholder: false,
         });
         vars[1] = Some(Variable {
             name: Box::from(&['&', '[', '1', ']'][..]),
             value: Ok(CalcResult::new(CalcResultType::Number(Decimal::zero()), 0)),
+            placeholder: false,
         });
         let _ = do_shunting_yard(&temp, &units, &mut tokens, &vars, &arena);
         compare_tokens(expected_tokens, &tokens);
@@ -1374,11 +1611,54 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn test_result_label() {
+        test_output(
+            "2 + 2 = four",
+            &[
+                num(2),
+                num(2),
+                op(OperatorTokenType::Add),
+                op(OperatorTokenType::Assign),
+            ],
+        );
+        test_tokens(
+            "2 + 2 = four",
+            &[
+                num(2),
+                str(" "),
+                op(OperatorTokenType::Add),
+                str(" "),
+                num(2),
+                str(" "),
+                op(OperatorTokenType::Assign),
+                str(" "),
+                str("four"),
+            ],
+        );
+    }
+
     #[test]
     fn test_binary_not() {
         test_output("NOT(0b11)", &[num(0b11), op(OperatorTokenType::BinNot)]);
     }
 
+    #[test]
+    fn test_sqrt_operator() {
+        test_output("√9", &[num(9), op(OperatorTokenType::Sqrt)]);
+        test_output(
+            "1 + √16 * 2",
+            &[
+                num(1),
+                num(16),
+                op(OperatorTokenType::Sqrt),
+                num(2),
+                op(OperatorTokenType::Mult),
+                op(OperatorTokenType::Add),
+            ],
+        );
+    }
+
     #[test]
     fn test_shunting_matrices() {
         test_output(
@@ -1666,6 +1946,30 @@ pub mod tests {
         test_output("+1-+2", &[num(1), num(2), op(OperatorTokenType::Sub)]);
         test_output("+1++2", &[num(1), num(2), op(OperatorTokenType::Add)]);
         test_output("2^-2", &[num(2), num(-2), op(OperatorTokenType::Pow)]);
+        test_output(
+            "[-1,-2;-3,-4]",
+            &[
+                num(-1),
+                num(-2),
+                num(-3),
+                num(-4),
+                op(OperatorTokenType::Matrix {
+                    row_count: 2,
+                    col_count: 2,
+                }),
+            ],
+        );
+        test_output(
+            "[-1, -2]",
+            &[
+                num(-1),
+                num(-2),
+                op(OperatorTokenType::Matrix {
+                    row_count: 1,
+                    col_count: 2,
+                }),
+            ],
+        );
 
         test_output(
             "-(1) - -(2)",
@@ -2249,6 +2553,56 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn test_nested_fn_arg_counts() {
+        test_output(
+            "max(min(3, 5), 2)",
+            &[
+                num(3),
+                num(5),
+                op(OperatorTokenType::Fn {
+                    arg_count: 2,
+                    typ: FnType::Min,
+                }),
+                num(2),
+                op(OperatorTokenType::Fn {
+                    arg_count: 2,
+                    typ: FnType::Max,
+                }),
+            ],
+        );
+        test_output(
+            "max(1, min(2, abs(3)), [4, 5], pi( ))",
+            &[
+                num(1),
+                num(2),
+                num(3),
+                op(OperatorTokenType::Fn {
+                    arg_count: 1,
+                    typ: FnType::Abs,
+                }),
+                op(OperatorTokenType::Fn {
+                    arg_count: 2,
+                    typ: FnType::Min,
+                }),
+                num(4),
+                num(5),
+                op(OperatorTokenType::Matrix {
+                    row_count: 1,
+                    col_count: 2,
+                }),
+                op(OperatorTokenType::Fn {
+                    arg_count: 0,
+                    typ: FnType::Pi,
+                }),
+                op(OperatorTokenType::Fn {
+                    arg_count: 4,
+                    typ: FnType::Max,
+                }),
+            ],
+        );
+    }
+
     #[test]
     fn test_fn_errors() {
         test_tokens(
@@ -2265,6 +2619,32 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn test_abs_bars() {
+        let abs = || {
+            op(OperatorTokenType::Fn {
+                arg_count: 1,
+                typ: FnType::Abs,
+            })
+        };
+        test_output("|-3|", &[num(-3), abs()]);
+        test_output(
+            "|1 - |2||",
+            &[num(1), num(2), abs(), op(OperatorTokenType::Sub), abs()],
+        );
+        test_output(
+            "2 * |1 - 4|",
+            &[
+                num(2),
+                num(1),
+                num(4),
+                op(OperatorTokenType::Sub),
+                abs(),
+                op(OperatorTokenType::Mult),
+            ],
+        );
+    }
+
     #[test]
     fn test_header() {
         test_tokens("# header", &[header("# header")]);
@@ -2340,9 +2720,10 @@ pub mod tests {
             "90-/9b^72^4",
             &[
                 num(9),
-                apply_to_prev_token_unit("b^72"),
+                num(72),
                 num(4),
                 op(OperatorTokenType::Pow),
+                op(OperatorTokenType::Pow),
             ],
         );
     }
@@ -1,8 +1,10 @@
+use crate::calc::dec;
 use crate::functions::FnType;
 use crate::units::units::{UnitOutput, Units};
-use crate::{Variables, SUM_VARIABLE_INDEX};
+use crate::{Variables, AVG_VARIABLE_INDEX, COUNT_VARIABLE_INDEX, SUM_VARIABLE_INDEX};
 use bumpalo::Bump;
 use rust_decimal::prelude::*;
+use smallvec::SmallVec;
 use std::str::FromStr;
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -10,8 +12,18 @@ pub enum TokenType {
     StringLiteral,
     Header,
     // index to the variable vec
-    Variable { var_index: usize },
-    LineReference { var_index: usize },
+    Variable {
+        var_index: usize,
+    },
+    LineReference {
+        var_index: usize,
+    },
+    /// `&[2:5]`, the results of the lines between the two referenced ones (inclusive)
+    /// as a column vector, the indices are the variable indices of the first and last lines
+    LineRange {
+        from_var_index: usize,
+        to_var_index: usize,
+    },
     NumberLiteral(Decimal),
     Operator(OperatorTokenType),
     Unit(UnitOutput),
@@ -23,9 +35,12 @@ pub struct Token<'a> {
     pub ptr: &'a [char],
     pub typ: TokenType,
     pub has_error: bool,
+    /// the name matched a variable but it is a valid unit as well
+    /// (e.g. `5 m` when there is a variable named `m`), so the editor can hint it
+    pub is_ambiguous: bool,
 }
 
-const PI: Decimal = Decimal::from_parts(1102470953, 185874565, 1703060790, false, 28);
+pub const PI: Decimal = Decimal::from_parts(1102470953, 185874565, 1703060790, false, 28);
 
 impl<'text_ptr> Token<'text_ptr> {
     pub fn is_number(&self) -> bool {
@@ -40,6 +55,10 @@ impl<'text_ptr> Token<'text_ptr> {
         self.has_error
     }
 
+    pub fn is_ambiguous(&self) -> bool {
+        self.is_ambiguous
+    }
+
     pub fn set_token_error_flag_by_index(index: usize, tokens: &mut [Token]) {
         // TODO I could not reproduce it but it happened runtime, so I use 'get_mut'
         // later when those indices will be used correctly (now they are just dummy values lot of times),
@@ -64,6 +83,7 @@ pub enum OperatorTokenType {
     BinOr,
     BinXor,
     BinNot,
+    Sqrt,
     Pow,
     ParenOpen,
     ParenClose,
@@ -74,18 +94,50 @@ pub enum OperatorTokenType {
     ShiftRight,
     Assign,
     UnitConverter,
+    PercentOf,
+    /// `%` between two integer literals, e.g. `17 % 5`, or `rem`.
+    /// A `%` after a number which is not followed by an other integer is a percentage (`20%`).
+    /// The sign of the result follows the dividend (truncated division), e.g. `-7 rem 3 = -1`.
+    Modulo,
+    /// `mod`, the sign of the result follows the divisor (floored division), e.g. `-7 mod 3 = 2`.
+    Mod,
+    /// `increased by` and `decreased by`, the same as `+` and `-`,
+    /// so `100 increased by 20%` is 120.
+    IncreasedBy,
+    DecreasedBy,
+    /// Logical `and`, `or` and `not`, unlike their bitwise uppercase counterparts they result
+    /// in 1 (true) or 0 (false), and any non-zero number is true.
+    And,
+    Or,
+    Not,
+    Greater,
+    Less,
+    GreaterOrEq,
+    LessOrEq,
+    /// `|`, the absolute value of the enclosed expression, e.g. `|-5 kg|`.
+    /// The shunting yard turns a pair of bars into an `abs` call.
+    AbsBar,
     ApplyUnit(UnitOutput),
-    Matrix { row_count: usize, col_count: usize },
-    Fn { arg_count: usize, typ: FnType },
+    Matrix {
+        row_count: usize,
+        col_count: usize,
+    },
+    Fn {
+        arg_count: usize,
+        typ: FnType,
+    },
 }
 
-#[derive(Eq, PartialEq, Copy, Clone)]
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
 pub enum Assoc {
     Left,
     Right,
 }
 
 impl OperatorTokenType {
+    /// Binding strength of the operator, a higher value binds tighter
+    /// (e.g. `Pow` > `Mult` > `Add`).
+    /// Parentheses, brackets, separators and other structural operators have 0.
     pub fn precedence(&self) -> usize {
         match self {
             OperatorTokenType::Add => 2,
@@ -99,6 +151,7 @@ impl OperatorTokenType {
             OperatorTokenType::BinOr => 0,
             OperatorTokenType::BinXor => 0,
             OperatorTokenType::BinNot => 4,
+            OperatorTokenType::Sqrt => 4,
             OperatorTokenType::Pow => 6,
             OperatorTokenType::ParenOpen => 0,
             OperatorTokenType::ParenClose => 0,
@@ -106,15 +159,28 @@ impl OperatorTokenType {
             OperatorTokenType::ShiftRight => 0,
             OperatorTokenType::Assign => 0,
             OperatorTokenType::UnitConverter => 0,
+            OperatorTokenType::PercentOf => 3,
+            OperatorTokenType::Modulo => 3,
+            OperatorTokenType::Mod => 3,
+            OperatorTokenType::IncreasedBy | OperatorTokenType::DecreasedBy => 2,
+            OperatorTokenType::And | OperatorTokenType::Or => 0,
+            OperatorTokenType::Not => 4,
+            OperatorTokenType::Greater
+            | OperatorTokenType::Less
+            | OperatorTokenType::GreaterOrEq
+            | OperatorTokenType::LessOrEq => 1,
             OperatorTokenType::Semicolon | OperatorTokenType::Comma => 0,
             OperatorTokenType::BracketOpen => 0,
             OperatorTokenType::BracketClose => 0,
+            OperatorTokenType::AbsBar => 0,
             OperatorTokenType::Matrix { .. } => 0,
             OperatorTokenType::Fn { .. } => 0,
             OperatorTokenType::ApplyUnit(_) => 5,
         }
     }
 
+    /// How operators with the same precedence are grouped,
+    /// e.g. `Pow` is right associative so `2^3^2` means `2^(3^2)`.
     pub fn assoc(&self) -> Assoc {
         match self {
             OperatorTokenType::ParenClose => Assoc::Left,
@@ -129,16 +195,28 @@ impl OperatorTokenType {
             OperatorTokenType::BinOr => Assoc::Left,
             OperatorTokenType::BinXor => Assoc::Left,
             OperatorTokenType::BinNot => Assoc::Left,
+            OperatorTokenType::Sqrt => Assoc::Right,
             OperatorTokenType::Pow => Assoc::Right,
             OperatorTokenType::ParenOpen => Assoc::Left,
             OperatorTokenType::ShiftLeft => Assoc::Left,
             OperatorTokenType::ShiftRight => Assoc::Left,
             OperatorTokenType::Assign => Assoc::Left,
             OperatorTokenType::UnitConverter => Assoc::Left,
+            OperatorTokenType::PercentOf => Assoc::Left,
+            OperatorTokenType::Modulo => Assoc::Left,
+            OperatorTokenType::Mod => Assoc::Left,
+            OperatorTokenType::IncreasedBy | OperatorTokenType::DecreasedBy => Assoc::Left,
+            OperatorTokenType::And | OperatorTokenType::Or => Assoc::Left,
+            OperatorTokenType::Not => Assoc::Right,
+            OperatorTokenType::Greater
+            | OperatorTokenType::Less
+            | OperatorTokenType::GreaterOrEq
+            | OperatorTokenType::LessOrEq => Assoc::Left,
             // Right, so 1 comma won't replace an other on the operator stack
             OperatorTokenType::Semicolon | OperatorTokenType::Comma => Assoc::Right,
             OperatorTokenType::BracketOpen => Assoc::Left,
             OperatorTokenType::BracketClose => Assoc::Left,
+            OperatorTokenType::AbsBar => Assoc::Left,
             OperatorTokenType::Matrix { .. } => Assoc::Left,
             OperatorTokenType::Fn { .. } => Assoc::Left,
             OperatorTokenType::ApplyUnit(_) => Assoc::Left,
@@ -148,11 +226,72 @@ impl OperatorTokenType {
 
 pub struct TokenParser {}
 
+/// The keyword which converts a value into another unit, e.g. "10 km in m".
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConversionKeyword {
+    In,
+    /// "in" collides with natural language ("10 apples in a box")
+    As,
+}
+
+impl Default for ConversionKeyword {
+    fn default() -> Self {
+        ConversionKeyword::In
+    }
+}
+
+impl ConversionKeyword {
+    /// the keyword followed by the mandatory whitespace
+    fn with_space(&self) -> &'static [char] {
+        match self {
+            ConversionKeyword::In => &['i', 'n', ' '],
+            ConversionKeyword::As => &['a', 's', ' '],
+        }
+    }
+}
+
+/// How the numbers are written, e.g. "3.14" or "3,14" (European style).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NumberInputMode {
+    /// "1,000.5", ',' separates the thousands (and the elements of lists)
+    DecimalPoint,
+    /// "1.000,5", '.' separates the thousands, and ',' is a list separator
+    /// only if it is not between digits, e.g. "[1,5; 2, 3]" is "[1.5; 2, 3]"
+    DecimalComma,
+}
+
+impl Default for NumberInputMode {
+    fn default() -> Self {
+        NumberInputMode::DecimalPoint
+    }
+}
+
+/// The settings which affect how a line is split into tokens.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    pub conversion_keyword: ConversionKeyword,
+    pub number_input_mode: NumberInputMode,
+}
+
+/// name, value, unit
+const CONSTANTS: [(&[char], &str, &[char]); 3] = [
+    // speed of light
+    (&['c'], "299792458", &['m', '/', 's']),
+    // standard gravity
+    (&['g'], "9.80665", &['m', '/', 's', '^', '2']),
+    // Euler's number, the same as `e()`.
+    // Only a standalone `e` is the constant: `3e4` is exponential notation, while in `3e`
+    // the `e` is not a multiplier but plain text (so it is `3`), like in `3 e`.
+    (&['e'], "2.7182818284590452353602874714", &[]),
+];
+
 #[derive(Clone, Copy)]
 enum CanBeUnit {
     Not,
     ApplyToPrevToken,
     StandInItself,
+    /// after the conversion keyword, where "number" and "scalar" mean dimensionless as well
+    ConversionTarget,
 }
 
 impl TokenParser {
@@ -163,7 +302,12 @@ impl TokenParser {
         units: &Units,
         line_index: usize,
         allocator: &'text_ptr Bump,
+        options: ParseOptions,
     ) {
+        let ParseOptions {
+            conversion_keyword,
+            number_input_mode,
+        } = options;
         let mut index = 0;
         let mut can_be_unit = CanBeUnit::Not;
         if line.starts_with(&['#']) {
@@ -171,10 +315,64 @@ impl TokenParser {
                 ptr: allocator.alloc_slice_fill_iter(line.iter().map(|it| *it)),
                 typ: TokenType::Header,
                 has_error: false,
+                is_ambiguous: false,
             });
             return;
         }
+        let var_candidates = TokenParser::sorted_var_candidates(variable_names, line_index);
+        // for each open bracket/paren, whether ',' between digits groups thousands there
+        let mut comma_groups_digits: SmallVec<[bool; 4]> = SmallVec::new();
+        // either the number or the unit token of a constant is empty, see `try_extract_constant`
+        fn constant_len(num_token: &Token, unit_token: &Option<Token>) -> usize {
+            num_token.ptr.len() + unit_token.as_ref().map(|it| it.ptr.len()).unwrap_or(0)
+        }
         while index < line.len() {
+            if matches!(can_be_unit, CanBeUnit::Not) {
+                if let Some((num_token, unit_token)) = TokenParser::try_extract_constant(
+                    &line[index..],
+                    dst,
+                    units,
+                    conversion_keyword,
+                    allocator,
+                )
+                .filter(|(num_token, unit_token)| {
+                    let name = &line[index..index + constant_len(num_token, unit_token)];
+                    // variables can shadow the constants
+                    !var_candidates.iter().any(|(_, var_index)| {
+                        variable_names[*var_index]
+                            .as_ref()
+                            .map(|it| *it.name == *name)
+                            .unwrap_or(false)
+                    })
+                }) {
+                    index += constant_len(&num_token, &unit_token);
+                    dst.push(num_token);
+                    if let Some(unit_token) = unit_token {
+                        dst.push(unit_token);
+                    }
+                    continue;
+                }
+            }
+            if let Some((num_token, unit_token)) =
+                TokenParser::try_extract_dms_literal(&line[index..], units, allocator)
+            {
+                index += num_token.ptr.len();
+                dst.push(num_token);
+                dst.push(unit_token);
+                can_be_unit = CanBeUnit::Not;
+                continue;
+            }
+            if let Some(token) = TokenParser::try_extract_line_range(
+                &line[index..],
+                variable_names,
+                line_index,
+                allocator,
+            ) {
+                index += token.ptr.len();
+                dst.push(token);
+                can_be_unit = CanBeUnit::Not;
+                continue;
+            }
             let parse_result = TokenParser::try_extract_comment(&line[index..], allocator)
                 .or_else(|| {
                     let prev_was_lineref = dst
@@ -184,7 +382,9 @@ impl TokenParser {
                     TokenParser::try_extract_variable_name(
                         &line[index..],
                         variable_names,
-                        line_index,
+                        TokenParser::var_candidates_starting_with(&var_candidates, line[index]),
+                        units,
+                        conversion_keyword,
                         allocator,
                         prev_was_lineref,
                     )
@@ -192,20 +392,25 @@ impl TokenParser {
                 .or_else(|| {
                     TokenParser::try_extract_unit(&line[index..], units, can_be_unit, allocator)
                         .or_else(|| {
-                            TokenParser::try_extract_operator(&line[index..], allocator).or_else(
-                                || {
-                                    TokenParser::try_extract_number_literal(
+                            TokenParser::try_extract_operator(
+                                &line[index..],
+                                conversion_keyword,
+                                allocator,
+                            )
+                            .or_else(|| {
+                                TokenParser::try_extract_number_literal(
+                                    &line[index..],
+                                    comma_groups_digits.last().copied().unwrap_or(true),
+                                    number_input_mode,
+                                    allocator,
+                                )
+                                .or_else(|| {
+                                    TokenParser::try_extract_string_literal(
                                         &line[index..],
                                         allocator,
                                     )
-                                    .or_else(|| {
-                                        TokenParser::try_extract_string_literal(
-                                            &line[index..],
-                                            allocator,
-                                        )
-                                    })
-                                },
-                            )
+                                })
+                            })
                         })
                 });
             if let Some(token) = parse_result {
@@ -222,24 +427,60 @@ impl TokenParser {
                         }
                     }
                     TokenType::NumberLiteral(..) | TokenType::NumberErr => {
-                        can_be_unit = CanBeUnit::ApplyToPrevToken;
+                        can_be_unit = if TokenParser::looks_like_hex_digits(
+                            &token.ptr,
+                            &line[index + token.ptr.len()..],
+                        ) {
+                            CanBeUnit::Not
+                        } else {
+                            CanBeUnit::ApplyToPrevToken
+                        };
                     }
                     TokenType::Unit(..) => {
                         can_be_unit = CanBeUnit::Not;
                     }
                     TokenType::Operator(typ) => {
+                        match typ {
+                            OperatorTokenType::ParenOpen | OperatorTokenType::BracketOpen => {
+                                let is_fn_call = index > 0 && line[index - 1].is_alphanumeric();
+                                let is_list = *typ == OperatorTokenType::BracketOpen || is_fn_call;
+                                comma_groups_digits.push(
+                                    !is_list || TokenParser::has_spaced_comma(&line[index + 1..]),
+                                );
+                            }
+                            _ => {}
+                        }
                         match typ {
                             OperatorTokenType::ParenClose => {
+                                comma_groups_digits.pop();
                                 // keep can_be_unit as it was
                             }
+                            OperatorTokenType::BracketClose => {
+                                comma_groups_digits.pop();
+                                // e.g. [1, 2] kg, but "in" is a conversion here, not inch
+                                can_be_unit = TokenParser::unit_can_follow(
+                                    &line[index + token.ptr.len()..],
+                                    conversion_keyword,
+                                );
+                            }
+                            OperatorTokenType::Perc => {
+                                // e.g. "30% kg" is meaningless, the unit is parsed so
+                                // that the evaluation rejects it instead of silently ignoring it
+                                can_be_unit = TokenParser::unit_can_follow(
+                                    &line[index + token.ptr.len()..],
+                                    conversion_keyword,
+                                );
+                            }
                             OperatorTokenType::UnitConverter => {
-                                can_be_unit = CanBeUnit::StandInItself
+                                can_be_unit = CanBeUnit::ConversionTarget
                             }
                             OperatorTokenType::Div => can_be_unit = CanBeUnit::StandInItself,
                             _ => can_be_unit = CanBeUnit::Not,
                         }
                     }
-                    TokenType::Variable { .. } | TokenType::LineReference { .. } => {
+                    TokenType::Variable { .. }
+                    | TokenType::LineReference { .. }
+                    | TokenType::LineRange { .. } => {
                         can_be_unit = CanBeUnit::Not;
                     }
                 }
@@ -249,10 +490,203 @@ impl TokenParser {
                 break;
             }
         }
+        TokenParser::convert_perc_between_integers_to_modulo(dst);
+        TokenParser::convert_logical_words_in_prose_to_text(dst);
+    }
+
+    /// A unit can be applied to the previous token unless a conversion follows,
+    /// e.g. in "[1, 2] in m", "in" is not inch.
+    fn unit_can_follow(rest: &[char], conversion_keyword: ConversionKeyword) -> CanBeUnit {
+        let rest = &rest[rest
+            .iter()
+            .take_while(|it| it.is_ascii_whitespace())
+            .count()..];
+        if rest.starts_with(conversion_keyword.with_space()) {
+            CanBeUnit::Not
+        } else {
+            CanBeUnit::ApplyToPrevToken
+        }
+    }
+
+    /// Letters glued to an integer are not parsed as a unit when they are
+    /// more likely digits the user meant to be part of the number:
+    /// - anything right after a hex or binary literal, e.g. "0x1fg" (a unit
+    ///   must be separated by a space there, see "0xFF B" vs "0xFFB")
+    /// - a single hex letter followed by '^' or a digit, e.g. "9b^72" or "4f2"
+    fn looks_like_hex_digits(number: &[char], rest: &[char]) -> bool {
+        let number = if number.first().map(|it| is_minus_sign(*it)).unwrap_or(false) {
+            &number[1..]
+        } else {
+            number
+        };
+        let next_is_letter = rest.first().map(|it| it.is_alphabetic()).unwrap_or(false);
+        if has_radix_prefix(number, 'x') || has_radix_prefix(number, 'b') {
+            next_is_letter
+        } else if !number.is_empty() && number.iter().all(|it| it.is_ascii_digit()) {
+            rest.first()
+                .map(|it| it.is_ascii_hexdigit())
+                .unwrap_or(false)
+                && next_is_letter
+                && matches!(rest.get(1), Some(ch) if *ch == '^' || ch.is_ascii_digit())
+        } else {
+            false
+        }
+    }
+
+    /// `17 % 5` means modulo, while `20%` or `200 + 20%` are percentages.
+    fn convert_perc_between_integers_to_modulo(tokens: &mut [Token]) {
+        fn is_integer_literal(token: Option<&Token>) -> bool {
+            match token.map(|it| &it.typ) {
+                Some(TokenType::NumberLiteral(num)) => num.trunc() == *num,
+                _ => false,
+            }
+        }
+        fn is_whitespace(token: &Token) -> bool {
+            token.typ == TokenType::StringLiteral && token.ptr.iter().all(|it| it.is_whitespace())
+        }
+        for i in 0..tokens.len() {
+            if tokens[i].typ != TokenType::Operator(OperatorTokenType::Perc) {
+                continue;
+            }
+            let prev = tokens[0..i].iter().rev().find(|it| !is_whitespace(it));
+            let next = tokens[i + 1..].iter().find(|it| !is_whitespace(it));
+            if is_integer_literal(prev) && is_integer_literal(next) {
+                tokens[i].typ = TokenType::Operator(OperatorTokenType::Modulo);
+            }
+        }
+    }
+
+    /// `and`, `or`, `not`, `true` and `false` are common words, so they are operators and literals
+    /// only between operands and operators, e.g. "1 < 2 or not true", but not in
+    /// "this is not true" or "5 kg or so".
+    fn convert_logical_words_in_prose_to_text(tokens: &mut [Token]) {
+        fn is_whitespace(token: &Token) -> bool {
+            token.typ == TokenType::StringLiteral && token.ptr.iter().all(|it| it.is_whitespace())
+        }
+        fn is_bool_literal(token: &Token) -> bool {
+            matches!(token.typ, TokenType::NumberLiteral(..))
+                && (token.ptr == &['t', 'r', 'u', 'e'][..]
+                    || token.ptr == &['f', 'a', 'l', 's', 'e'][..])
+        }
+        fn ends_operand(token: Option<&Token>) -> bool {
+            match token.map(|it| &it.typ) {
+                Some(TokenType::NumberLiteral(..))
+                | Some(TokenType::NumberErr)
+                | Some(TokenType::Unit(..))
+                | Some(TokenType::Variable { .. })
+                | Some(TokenType::LineReference { .. })
+                | Some(TokenType::LineRange { .. })
+                | Some(TokenType::Operator(OperatorTokenType::ParenClose))
+                | Some(TokenType::Operator(OperatorTokenType::BracketClose))
+                | Some(TokenType::Operator(OperatorTokenType::AbsBar))
+                | Some(TokenType::Operator(OperatorTokenType::Perc)) => true,
+                _ => false,
+            }
+        }
+        fn starts_operand(tokens: &[Token], i: Option<usize>) -> bool {
+            let i = if let Some(i) = i {
+                i
+            } else {
+                return false;
+            };
+            match &tokens[i].typ {
+                TokenType::NumberLiteral(..)
+                | TokenType::NumberErr
+                | TokenType::Variable { .. }
+                | TokenType::LineReference { .. }
+                | TokenType::LineRange { .. }
+                | TokenType::Operator(OperatorTokenType::ParenOpen)
+                | TokenType::Operator(OperatorTokenType::BracketOpen)
+                | TokenType::Operator(OperatorTokenType::AbsBar)
+                | TokenType::Operator(OperatorTokenType::Sub)
+                | TokenType::Operator(OperatorTokenType::Sqrt)
+                | TokenType::Operator(OperatorTokenType::Not) => true,
+                // a function call, e.g. "abs(-1)"
+                TokenType::StringLiteral => matches!(
+                    tokens.get(i + 1).map(|it| &it.typ),
+                    Some(TokenType::Operator(OperatorTokenType::ParenOpen))
+                ),
+                _ => false,
+            }
+        }
+        // an operator turned into text can invalidate its neighbours (e.g. "true" in
+        // "is not true"), so it is repeated until nothing changes
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for i in 0..tokens.len() {
+                let is_logical_word = matches!(
+                    tokens[i].typ,
+                    TokenType::Operator(OperatorTokenType::And)
+                        | TokenType::Operator(OperatorTokenType::Or)
+                        | TokenType::Operator(OperatorTokenType::Not)
+                ) || is_bool_literal(&tokens[i]);
+                if !is_logical_word {
+                    continue;
+                }
+                let prev = (0..i).rev().find(|it| !is_whitespace(&tokens[*it]));
+                let next = (i + 1..tokens.len()).find(|it| !is_whitespace(&tokens[*it]));
+                let prev_token = prev.map(|it| &tokens[it]);
+                let next_token = next.map(|it| &tokens[it]);
+                let is_operand = match &tokens[i].typ {
+                    TokenType::Operator(OperatorTokenType::And)
+                    | TokenType::Operator(OperatorTokenType::Or) => {
+                        ends_operand(prev_token) && starts_operand(tokens, next)
+                    }
+                    TokenType::Operator(OperatorTokenType::Not) => {
+                        let prev_is_operator = prev_token
+                            .map(|it| {
+                                matches!(it.typ, TokenType::Operator(..)) && !ends_operand(Some(it))
+                            })
+                            .unwrap_or(true);
+                        prev_is_operator && starts_operand(tokens, next)
+                    }
+                    _ => {
+                        // true or false
+                        let is_operator_or_nothing = |token: Option<&Token>| {
+                            token
+                                .map(|it| matches!(it.typ, TokenType::Operator(..)))
+                                .unwrap_or(true)
+                        };
+                        is_operator_or_nothing(prev_token) && is_operator_or_nothing(next_token)
+                    }
+                };
+                if !is_operand {
+                    tokens[i].typ = TokenType::StringLiteral;
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    /// Whether there is a ',' followed by whitespace in the list starting at `str`,
+    /// nested lists are skipped.
+    /// In that case ',' is an argument separator only when followed by whitespace,
+    /// e.g. `[1,000, 2,000]` has two elements.
+    fn has_spaced_comma(str: &[char]) -> bool {
+        let mut depth = 0;
+        for (i, ch) in str.iter().enumerate() {
+            match ch {
+                '(' | '[' => depth += 1,
+                ')' | ']' if depth == 0 => return false,
+                ')' | ']' => depth -= 1,
+                ',' if depth == 0
+                    && str.get(i + 1).map(|it| it.is_whitespace()).unwrap_or(false) =>
+                {
+                    return true;
+                }
+                _ => {}
+            }
+        }
+        return false;
     }
 
+    /// If `comma_groups_digits` is true, ',' can be used as a thousands separator (`1,000`),
+    /// otherwise it separates arguments or matrix elements.
     pub fn try_extract_number_literal<'text_ptr>(
         str: &[char],
+        comma_groups_digits: bool,
+        number_input_mode: NumberInputMode,
         allocator: &'text_ptr Bump,
     ) -> Option<Token<'text_ptr>> {
         let mut number_str = [b'0'; 256];
@@ -260,7 +694,7 @@ impl TokenParser {
         let mut i = 0;
         // unary minus is parsed as part of the number only if
         // it is right before the number
-        if str[0] == '-'
+        if is_minus_sign(str[0])
             && str
                 .get(1)
                 .map(|it| !it.is_ascii_whitespace())
@@ -278,10 +712,31 @@ impl TokenParser {
                 // ptr: &str[0..i],
                 ptr: allocator.alloc_slice_fill_iter(str.iter().map(|it| *it).take(1)),
                 has_error: false,
+                is_ambiguous: false,
             });
         }
 
-        if str[i..].starts_with(&['0', 'b']) {
+        // true and false are 1 and 0, just like the results of comparisons
+        for (name, value) in &[
+            (&['t', 'r', 'u', 'e'][..], 1),
+            (&['f', 'a', 'l', 's', 'e'][..], 0),
+        ] {
+            let standalone = str.starts_with(name)
+                && str
+                    .get(name.len())
+                    .map(|it| !it.is_alphanumeric() && *it != '_')
+                    .unwrap_or(true);
+            if standalone {
+                return Some(Token {
+                    typ: TokenType::NumberLiteral((*value).into()),
+                    ptr: allocator.alloc_slice_fill_iter(name.iter().map(|it| *it)),
+                    has_error: false,
+                    is_ambiguous: false,
+                });
+            }
+        }
+
+        if has_radix_prefix(&str[i..], 'b') {
             i += 2;
             let mut end_index_before_last_whitespace = i;
             while i < str.len() {
@@ -291,6 +746,11 @@ impl TokenParser {
                     number_str_index += 1;
                 } else if str[i].is_ascii_whitespace() {
                     // allowed
+                } else if str[i] == '_'
+                    && matches!(str[i - 1], '0' | '1')
+                    && matches!(str.get(i + 1), Some('0') | Some('1'))
+                {
+                    // allowed only between digits
                 } else {
                     break;
                 }
@@ -309,11 +769,12 @@ impl TokenParser {
                     // ptr: &str[0..i],
                     ptr: allocator.alloc_slice_fill_iter(str.iter().map(|it| *it).take(i)),
                     has_error: false,
+                    is_ambiguous: false,
                 })
             } else {
                 None
             }
-        } else if str[i..].starts_with(&['0', 'x']) {
+        } else if has_radix_prefix(&str[i..], 'x') {
             i += 2;
             let mut end_index_before_last_whitespace = i;
             while i < str.len() {
@@ -346,13 +807,14 @@ impl TokenParser {
                     // ptr: &str[0..i],
                     ptr: allocator.alloc_slice_fill_iter(str.iter().map(|it| *it).take(i)),
                     has_error: false,
+                    is_ambiguous: false,
                 })
             } else {
                 None
             }
         } else if str
             .get(0)
-            .map(|it| it.is_ascii_digit() || *it == '.' || *it == '-')
+            .map(|it| it.is_ascii_digit() || *it == '.' || is_minus_sign(*it))
             .unwrap_or(false)
         {
             let mut decimal_point_count = 0;
@@ -362,14 +824,49 @@ impl TokenParser {
             let mut e_neg = false;
             let mut e_already_added = false;
             let mut multiplier = None;
+            let decimal_comma = number_input_mode == NumberInputMode::DecimalComma;
+            // the separator is between digits and followed by exactly 3 of them
+            let separates_thousands = |i: usize| {
+                str[i - 1].is_ascii_digit()
+                    && str.len() >= i + 4
+                    && str[i + 1..i + 4].iter().all(|it| it.is_ascii_digit())
+                    && str
+                        .get(i + 4)
+                        .map(|it| !it.is_ascii_digit())
+                        .unwrap_or(true)
+            };
 
             while i < str.len() {
-                if str[i] == '.' && decimal_point_count < 1 && e_count < 1 {
+                let can_be_thousands_separator =
+                    digit_count > 0 && decimal_point_count == 0 && e_count == 0;
+                if decimal_comma
+                    && str[i] == '.'
+                    && can_be_thousands_separator
+                    && separates_thousands(i)
+                {
+                    // thousands separator, e.g. 1.000,5
+                } else if decimal_comma
+                    && str[i] == ','
+                    && decimal_point_count < 1
+                    && e_count < 1
+                    && digit_count > 0
+                    && str
+                        .get(i + 1)
+                        .map(|it| it.is_ascii_digit())
+                        .unwrap_or(false)
+                {
+                    // e.g. 3,14
+                    decimal_point_count += 1;
+                    end_index_before_last_whitespace = i + 1;
+                    number_str[number_str_index] = b'.';
+                    number_str_index += 1;
+                } else if !decimal_comma && str[i] == '.' && decimal_point_count < 1 && e_count < 1
+                {
                     decimal_point_count += 1;
                     end_index_before_last_whitespace = i + 1;
                     number_str[number_str_index] = str[i] as u8;
                     number_str_index += 1;
-                } else if str[i] == '-' && e_count == 1 {
+                } else if is_minus_sign(str[i]) && e_count == 1 {
                     if e_neg || e_already_added {
                         break;
                     }
@@ -377,20 +874,15 @@ impl TokenParser {
                 } else if str[i] == 'e' && e_count < 1 && !str[i - 1].is_ascii_whitespace() {
                     // cannot have whitespace before 'e'
                     e_count += 1;
-                } else if str[i] == 'k'
-                    && e_count < 1
-                    && !str[i - 1].is_ascii_whitespace()
-                    && str.get(i + 1).map(|it| !it.is_alphabetic()).unwrap_or(true)
-                {
-                    multiplier = Some(1_000);
-                    end_index_before_last_whitespace = i + 1;
-                    break;
-                } else if str[i] == 'M'
+                } else if (str[i] == 'k' || str[i] == 'M')
                     && e_count < 1
                     && !str[i - 1].is_ascii_whitespace()
                     && str.get(i + 1).map(|it| !it.is_alphabetic()).unwrap_or(true)
                 {
-                    multiplier = Some(1_000_000);
+                    // the magnitude suffix closes the number and it cannot be combined
+                    // with an exponent: "1e3k" is "1e3" followed by "k",
+                    // and "2ke3" is "2k" followed by "e3"
+                    multiplier = Some(if str[i] == 'k' { 1_000 } else { 1_000_000 });
                     end_index_before_last_whitespace = i + 1;
                     break;
                 } else if str[i].is_ascii_digit() {
@@ -413,12 +905,27 @@ impl TokenParser {
                     }
                 } else if str[i].is_ascii_whitespace() {
                     // allowed
+                } else if !decimal_comma
+                    && str[i] == ','
+                    && comma_groups_digits
+                    && can_be_thousands_separator
+                    && separates_thousands(i)
+                {
+                    // thousands separator, e.g. 1,000
                 } else {
                     break;
                 }
                 i += 1;
             }
             i = end_index_before_last_whitespace;
+            if multiplier.is_none() && digit_count > 0 {
+                if let Some((word_multiplier, len)) =
+                    TokenParser::try_extract_magnitude_word(&str[i..])
+                {
+                    multiplier = Some(word_multiplier);
+                    i += len;
+                }
+            }
             if digit_count > 0 {
                 let num = if e_already_added {
                     Decimal::from_scientific(&unsafe {
@@ -437,6 +944,7 @@ impl TokenParser {
                                 ptr: allocator
                                     .alloc_slice_fill_iter(str.iter().map(|it| *it).take(i)),
                                 has_error: false,
+                                is_ambiguous: false,
                             })
                         } else {
                             Some(Token {
@@ -444,6 +952,7 @@ impl TokenParser {
                                 ptr: allocator
                                     .alloc_slice_fill_iter(str.iter().map(|it| *it).take(i)),
                                 has_error: true,
+                                is_ambiguous: false,
                             })
                         }
                     } else {
@@ -451,6 +960,7 @@ impl TokenParser {
                             typ: TokenType::NumberLiteral(num),
                             ptr: allocator.alloc_slice_fill_iter(str.iter().map(|it| *it).take(i)),
                             has_error: false,
+                            is_ambiguous: false,
                         })
                     }
                 } else {
@@ -459,6 +969,7 @@ impl TokenParser {
                         // ptr: &str[0..i],
                         ptr: allocator.alloc_slice_fill_iter(str.iter().map(|it| *it).take(i)),
                         has_error: true,
+                        is_ambiguous: false,
                     })
                 }
             } else {
@@ -469,6 +980,35 @@ impl TokenParser {
         }
     }
 
+    /// e.g. the " million" in "3 million"
+    fn try_extract_magnitude_word(str: &[char]) -> Option<(i64, usize)> {
+        let mut i = 0;
+        while i < str.len() && str[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i == 0 {
+            return None;
+        }
+        for (word, multiplier) in &[
+            ("thousand", 1_000),
+            ("million", 1_000_000),
+            ("billion", 1_000_000_000),
+            ("trillion", 1_000_000_000_000),
+        ] {
+            let len = word.len();
+            let matches = str.len() >= i + len
+                && str[i..i + len].iter().copied().eq(word.chars())
+                && str
+                    .get(i + len)
+                    .map(|it| !it.is_alphanumeric())
+                    .unwrap_or(true);
+            if matches {
+                return Some((*multiplier, i + len));
+            }
+        }
+        return None;
+    }
+
     fn try_extract_unit<'text_ptr>(
         str: &[char],
         unit: &Units,
@@ -478,6 +1018,36 @@ impl TokenParser {
         if matches!(can_be_unit, CanBeUnit::Not) || str[0].is_ascii_whitespace() {
             return None;
         }
+        if str[0] == '%'
+            && !matches!(
+                can_be_unit,
+                CanBeUnit::StandInItself | CanBeUnit::ConversionTarget
+            )
+        {
+            // it is the percentage operator, except e.g. in "5000 ppm in %"
+            return None;
+        }
+        if matches!(can_be_unit, CanBeUnit::ConversionTarget) {
+            // e.g. "(5 kg / g) in number", it strips the unit of a dimensionless value
+            for word in &[
+                &['n', 'u', 'm', 'b', 'e', 'r'][..],
+                &['s', 'c', 'a', 'l', 'a', 'r'],
+            ] {
+                if str.starts_with(word)
+                    && str
+                        .get(word.len())
+                        .map(|it| !it.is_alphanumeric())
+                        .unwrap_or(true)
+                {
+                    return Some(Token {
+                        typ: TokenType::Unit(UnitOutput::new()),
+                        ptr: allocator.alloc_slice_fill_iter(word.iter().map(|it| *it)),
+                        has_error: false,
+                        is_ambiguous: false,
+                    });
+                }
+            }
+        }
         let (unit, parsed_len) = unit.parse(str);
         return if parsed_len == 0 {
             None
@@ -494,16 +1064,195 @@ impl TokenParser {
                     typ: TokenType::Operator(OperatorTokenType::ApplyUnit(unit)),
                     ptr,
                     has_error: false,
+                    is_ambiguous: false,
                 }),
-                CanBeUnit::StandInItself => Some(Token {
+                CanBeUnit::StandInItself | CanBeUnit::ConversionTarget => Some(Token {
                     typ: TokenType::Unit(unit),
                     ptr,
                     has_error: false,
+                    is_ambiguous: false,
                 }),
             }
         };
     }
 
+    /// e.g. `c` (speed of light) as `299792458 m/s`.
+    /// To avoid false matches in plain text (or in assignments), a constant must be
+    /// a standalone word at the start of an expression or after an operator,
+    /// followed by an other operator or nothing.
+    /// A constant which is also the name of a unit (`g`) is that unit after a
+    /// multiplication, e.g. `5 * g` is 5 grams.
+    /// The unit of a constant is not in the text, its token is an empty slice
+    /// at the end of the constant.
+    fn try_extract_constant<'text_ptr>(
+        str: &[char],
+        prev_tokens: &[Token],
+        units: &Units,
+        conversion_keyword: ConversionKeyword,
+        allocator: &'text_ptr Bump,
+    ) -> Option<(Token<'text_ptr>, Option<Token<'text_ptr>>)> {
+        let prev_token = prev_tokens.iter().rev().find(|it| {
+            it.typ != TokenType::StringLiteral || !it.ptr.iter().all(|ch| ch.is_whitespace())
+        });
+        let after_operator = match prev_token.map(|it| &it.typ) {
+            None => true,
+            Some(TokenType::Operator(op)) => !matches!(
+                op,
+                OperatorTokenType::ParenClose
+                    | OperatorTokenType::BracketClose
+                    | OperatorTokenType::Perc
+                    | OperatorTokenType::ApplyUnit(..)
+            ),
+            Some(_) => false,
+        };
+        if !after_operator {
+            return None;
+        }
+        let after_mult = matches!(
+            prev_token.map(|it| &it.typ),
+            Some(TokenType::Operator(OperatorTokenType::Mult))
+        );
+        for (name, value, unit_str) in CONSTANTS.iter() {
+            let standalone = str.starts_with(name)
+                && str
+                    .get(name.len())
+                    .map(|it| !it.is_alphanumeric() && *it != '_' && *it != '(')
+                    .unwrap_or(true);
+            if !standalone {
+                continue;
+            }
+            let rest = {
+                let ws_count = str[name.len()..]
+                    .iter()
+                    .take_while(|it| it.is_whitespace())
+                    .count();
+                &str[name.len() + ws_count..]
+            };
+            let followed_by_operator = rest.is_empty()
+                || "+-*/^)],;%<>·×÷".contains(rest[0])
+                || rest.starts_with(conversion_keyword.with_space());
+            if !followed_by_operator {
+                // e.g. "c = 12" or "c is"
+                return None;
+            }
+            let ptr: &'text_ptr [char] = allocator.alloc_slice_fill_iter(name.iter().map(|it| *it));
+            if after_mult {
+                let (unit, parsed_len) = units.parse(name);
+                if parsed_len == name.len() {
+                    // "1 g", the number is not in the text, the unit is the name itself
+                    let (num_ptr, unit_ptr) = ptr.split_at(0);
+                    let num_token = Token {
+                        typ: TokenType::NumberLiteral(Decimal::one()),
+                        ptr: num_ptr,
+                        has_error: false,
+                        is_ambiguous: false,
+                    };
+                    let unit_token = Token {
+                        typ: TokenType::Operator(OperatorTokenType::ApplyUnit(unit)),
+                        ptr: unit_ptr,
+                        has_error: false,
+                        is_ambiguous: false,
+                    };
+                    return Some((num_token, Some(unit_token)));
+                }
+            }
+            let (ptr, unit_ptr) = ptr.split_at(ptr.len());
+            let num_token = Token {
+                typ: TokenType::NumberLiteral(Decimal::from_str(value).ok()?),
+                ptr,
+                has_error: false,
+                is_ambiguous: false,
+            };
+            if unit_str.is_empty() {
+                return Some((num_token, None));
+            }
+            let (unit, parsed_len) = units.parse(unit_str);
+            if parsed_len != unit_str.len() {
+                return None;
+            }
+            let unit_token = Token {
+                typ: TokenType::Operator(OperatorTokenType::ApplyUnit(unit)),
+                ptr: unit_ptr,
+                has_error: false,
+                is_ambiguous: false,
+            };
+            return Some((num_token, Some(unit_token)));
+        }
+        return None;
+    }
+
+    /// Degrees, minutes and seconds of arc, e.g. `40° 26' 46"`, the result is the
+    /// angle in degrees.
+    /// `'` and `"` are minutes and seconds only after a `°`, otherwise they could be
+    /// feet and inches.
+    fn try_extract_dms_literal<'text_ptr>(
+        str: &[char],
+        units: &Units,
+        allocator: &'text_ptr Bump,
+    ) -> Option<(Token<'text_ptr>, Token<'text_ptr>)> {
+        // an unsigned number, e.g. "26" or "46.5"
+        fn number(str: &[char]) -> Option<(Decimal, usize)> {
+            let len = str
+                .iter()
+                .take_while(|it| it.is_ascii_digit() || **it == '.')
+                .count();
+            if len == 0 || !str[0].is_ascii_digit() {
+                return None;
+            }
+            let text: String = str[0..len].iter().collect();
+            Decimal::from_str(&text).ok().map(|it| (it, len))
+        }
+        fn skip_whitespaces(str: &[char], i: usize) -> usize {
+            i + str[i..]
+                .iter()
+                .take_while(|it| it.is_ascii_whitespace())
+                .count()
+        }
+
+        let (degrees, mut i) = number(str)?;
+        if str.get(i) != Some(&'°') {
+            return None;
+        }
+        i += 1;
+        let mut total = degrees;
+        let mut has_minutes_or_seconds = false;
+        for (mark, divisor) in &[('\'', 60), ('"', 3600)] {
+            let start = skip_whitespaces(str, i);
+            if let Some((value, len)) = number(&str[start..]) {
+                if str.get(start + len) == Some(mark) && value < dec(60) {
+                    total = total.checked_add(&value.checked_div(&dec(*divisor))?)?;
+                    i = start + len + 1;
+                    has_minutes_or_seconds = true;
+                }
+            }
+        }
+        if !has_minutes_or_seconds {
+            return None;
+        }
+        let (unit, parsed_len) = units.parse(&['°']);
+        if parsed_len != 1 {
+            return None;
+        }
+        let ptr: &'text_ptr [char] =
+            allocator.alloc_slice_fill_iter(str.iter().map(|it| *it).take(i));
+        // the '°' is part of the number token, the unit token is an empty slice at the
+        // end of the literal so that it is still positioned in the text
+        let (ptr, unit_ptr) = ptr.split_at(ptr.len());
+        let num_token = Token {
+            typ: TokenType::NumberLiteral(total),
+            ptr,
+            has_error: false,
+            is_ambiguous: false,
+        };
+        let unit_token = Token {
+            typ: TokenType::Operator(OperatorTokenType::ApplyUnit(unit)),
+            ptr: unit_ptr,
+            has_error: false,
+            is_ambiguous: false,
+        };
+        return Some((num_token, unit_token));
+    }
+
     fn try_extract_comment<'text_ptr>(
         line: &[char],
         allocator: &'text_ptr Bump,
@@ -513,39 +1262,115 @@ impl TokenParser {
                 typ: TokenType::StringLiteral,
                 ptr: allocator.alloc_slice_fill_iter(line.iter().map(|it| *it)),
                 has_error: false,
+                is_ambiguous: false,
             })
         } else {
             None
         };
     }
 
+    /// `&[n:m]`, where `n` and `m` are line references above the current line.
+    /// If the bounds are not valid (they are not defined or out of order), it is a `NumberErr`.
+    fn try_extract_line_range<'text_ptr>(
+        str: &[char],
+        vars: &Variables,
+        line_index: usize,
+        allocator: &'text_ptr Bump,
+    ) -> Option<Token<'text_ptr>> {
+        if !str.starts_with(&['&', '[']) {
+            return None;
+        }
+        let from_len = str[2..].iter().take_while(|it| it.is_ascii_digit()).count();
+        let colon_index = 2 + from_len;
+        if from_len == 0 || str.get(colon_index) != Some(&':') {
+            return None;
+        }
+        let to_len = str[colon_index + 1..]
+            .iter()
+            .take_while(|it| it.is_ascii_digit())
+            .count();
+        let close_index = colon_index + 1 + to_len;
+        if to_len == 0 || str.get(close_index) != Some(&']') {
+            return None;
+        }
+        let find_line_ref = |line_id: &[char]| {
+            let mut name = vec!['&', '['];
+            name.extend_from_slice(line_id);
+            name.push(']');
+            vars[0..line_index]
+                .iter()
+                .position(|it| it.as_ref().map(|it| *it.name == *name).unwrap_or(false))
+        };
+        let from = find_line_ref(&str[2..colon_index]);
+        let to = find_line_ref(&str[colon_index + 1..close_index]);
+        let typ = match (from, to) {
+            (Some(from), Some(to)) if from <= to => TokenType::LineRange {
+                from_var_index: from,
+                to_var_index: to,
+            },
+            _ => TokenType::NumberErr,
+        };
+        return Some(Token {
+            typ,
+            ptr: allocator.alloc_slice_fill_iter(str.iter().map(|it| *it).take(close_index + 1)),
+            has_error: false,
+            is_ambiguous: false,
+        });
+    }
+
+    /// The variables defined above the line, sorted by the first char of their names,
+    /// so at a given position only those are compared which can start there.
+    /// The later definitions come first in each group.
+    fn sorted_var_candidates(variable_names: &Variables, line_index: usize) -> Vec<(char, usize)> {
+        let mut var_candidates: Vec<(char, usize)> = variable_names[0..line_index]
+            .iter()
+            .enumerate()
+            .filter_map(|(var_index, var)| {
+                var.as_ref()
+                    .and_then(|it| it.name.first())
+                    .map(|first_ch| (*first_ch, var_index))
+            })
+            .collect();
+        var_candidates.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+        return var_candidates;
+    }
+
+    fn var_candidates_starting_with(
+        var_candidates: &[(char, usize)],
+        first_ch: char,
+    ) -> &[(char, usize)] {
+        let from = var_candidates
+            .binary_search_by(|(ch, _)| {
+                if *ch < first_ch {
+                    std::cmp::Ordering::Less
+                } else {
+                    std::cmp::Ordering::Greater
+                }
+            })
+            .unwrap_or_else(|it| it);
+        let count = var_candidates[from..]
+            .iter()
+            .take_while(|(ch, _)| *ch == first_ch)
+            .count();
+        return &var_candidates[from..from + count];
+    }
+
     fn try_extract_variable_name<'text_ptr>(
         line: &[char],
         vars: &Variables,
-        row_index: usize,
+        candidates: &[(char, usize)],
+        units: &Units,
+        conversion_keyword: ConversionKeyword,
         allocator: &'text_ptr Bump,
         prev_was_lineref: bool,
     ) -> Option<Token<'text_ptr>> {
-        if line.starts_with(&['s', 'u', 'm']) && line.get(3).map(|it| *it == ' ').unwrap_or(true) {
-            return Some(Token {
-                typ: TokenType::Variable {
-                    var_index: SUM_VARIABLE_INDEX,
-                },
-                ptr: allocator.alloc_slice_fill_iter(line.iter().map(|it| *it).take(3)),
-                has_error: false,
-            });
-        }
         let mut longest_match_index = 0;
         let mut longest_match = 0;
-        'asd: for (var_index, var) in vars[0..row_index].iter().enumerate().rev() {
-            if var.is_none() {
-                continue;
-            }
-            let var = var.as_ref().unwrap();
-            for (i, ch) in var.name.iter().enumerate() {
-                if i >= line.len() || line[i] != *ch {
-                    continue 'asd;
-                }
+        'asd: for (_, var_index) in candidates {
+            let var_index = *var_index;
+            let var = vars[var_index].as_ref().expect("candidates are defined");
+            if !line.starts_with(&var.name) {
+                continue 'asd;
             }
             // if the next char is '(', it can't be a var name
             if line
@@ -583,14 +1408,50 @@ impl TokenParser {
                     var_index: longest_match_index,
                 }
             };
+            let is_ambiguous =
+                !is_line_ref && units.parse(&line[0..longest_match]).1 == longest_match;
             return Some(Token {
                 typ,
                 ptr: allocator.alloc_slice_fill_iter(line.iter().map(|it| *it).take(longest_match)),
                 has_error: false,
+                is_ambiguous,
             });
-        } else {
-            return None;
-        };
+        }
+        // the running aggregates of the current section, unless a variable is defined with
+        // their names. Only if they are followed by an operator or nothing,
+        // so "count (1, 2)" or "avg speed" are not aggregates
+        for (name, var_index) in &[
+            (&['s', 'u', 'm'][..], SUM_VARIABLE_INDEX),
+            (&['a', 'v', 'g'][..], AVG_VARIABLE_INDEX),
+            (&['c', 'o', 'u', 'n', 't'][..], COUNT_VARIABLE_INDEX),
+        ] {
+            if !line.starts_with(name) {
+                continue;
+            }
+            let rest = &line[name.len()..];
+            let rest = &rest[rest.iter().take_while(|it| **it == ' ').count()..];
+            let followed_by_operator =
+                || match TokenParser::try_extract_operator(rest, conversion_keyword, allocator) {
+                    Some(Token {
+                        typ: TokenType::Operator(OperatorTokenType::ParenOpen),
+                        ..
+                    }) => false,
+                    Some(_) => true,
+                    None => false,
+                };
+            if rest.is_empty() || followed_by_operator() {
+                return Some(Token {
+                    typ: TokenType::Variable {
+                        var_index: *var_index,
+                    },
+                    ptr: allocator
+                        .alloc_slice_fill_iter(line.iter().map(|it| *it).take(name.len())),
+                    has_error: false,
+                    is_ambiguous: false,
+                });
+            }
+        }
+        return None;
     }
 
     fn try_extract_string_literal<'text_ptr>(
@@ -599,7 +1460,10 @@ impl TokenParser {
     ) -> Option<Token<'text_ptr>> {
         let mut i = 0;
         for ch in str {
-            if "=%/+-*^()[]".chars().any(|it| it == *ch) || ch.is_ascii_whitespace() {
+            if "=≔%/+-*^()[]".chars().any(|it| it == *ch)
+                || ch.is_ascii_whitespace()
+                || (*ch == ':' && str.get(i + 1) == Some(&'='))
+            {
                 break;
             }
             // it means somwewhere we passed an invalid slice
@@ -613,6 +1477,7 @@ impl TokenParser {
                 ptr: allocator.alloc_slice_fill_iter(str.iter().map(|it| *it).take(i)),
                 // ptr: &str[0..i],
                 has_error: false,
+                is_ambiguous: false,
             });
         } else {
             for ch in &str[0..] {
@@ -628,6 +1493,7 @@ impl TokenParser {
                     // ptr: &str[0..i],
                     ptr: allocator.alloc_slice_fill_iter(str.iter().map(|it| *it).take(i)),
                     has_error: false,
+                    is_ambiguous: false,
                 })
             } else {
                 None
@@ -637,6 +1503,7 @@ impl TokenParser {
 
     fn try_extract_operator<'text_ptr>(
         str: &[char],
+        conversion_keyword: ConversionKeyword,
         allocator: &'text_ptr Bump,
     ) -> Option<Token<'text_ptr>> {
         fn op<'text_ptr>(
@@ -650,14 +1517,16 @@ impl TokenParser {
                 // ptr: &str[0..len],
                 ptr: allocator.alloc_slice_fill_iter(str.iter().map(|it| *it).take(len)),
                 has_error: false,
+                is_ambiguous: false,
             });
         }
         match str[0] {
-            '=' => op(OperatorTokenType::Assign, str, 1, allocator),
+            '=' | '≔' => op(OperatorTokenType::Assign, str, 1, allocator),
+            ':' if str.get(1) == Some(&'=') => op(OperatorTokenType::Assign, str, 2, allocator),
             '+' => op(OperatorTokenType::Add, str, 1, allocator),
-            '-' => op(OperatorTokenType::Sub, str, 1, allocator),
-            '*' => op(OperatorTokenType::Mult, str, 1, allocator),
-            '/' => op(OperatorTokenType::Div, str, 1, allocator),
+            '-' | '−' | '–' => op(OperatorTokenType::Sub, str, 1, allocator),
+            '*' | '·' | '×' => op(OperatorTokenType::Mult, str, 1, allocator),
+            '/' | '÷' => op(OperatorTokenType::Div, str, 1, allocator),
             '%' => op(OperatorTokenType::Perc, str, 1, allocator),
             '^' => op(OperatorTokenType::Pow, str, 1, allocator),
             '(' => op(OperatorTokenType::ParenOpen, str, 1, allocator),
@@ -666,9 +1535,14 @@ impl TokenParser {
             ']' => op(OperatorTokenType::BracketClose, str, 1, allocator),
             ',' => op(OperatorTokenType::Comma, str, 1, allocator),
             ';' => op(OperatorTokenType::Semicolon, str, 1, allocator),
+            '√' => op(OperatorTokenType::Sqrt, str, 1, allocator),
+            '|' => op(OperatorTokenType::AbsBar, str, 1, allocator),
             _ => {
-                if str.starts_with(&['i', 'n', ' ']) {
-                    op(OperatorTokenType::UnitConverter, str, 2, allocator)
+                if str.starts_with(conversion_keyword.with_space()) {
+                    let len = conversion_keyword.with_space().len() - 1;
+                    op(OperatorTokenType::UnitConverter, str, len, allocator)
+                } else if str.starts_with(&['o', 'f', ' ']) {
+                    op(OperatorTokenType::PercentOf, str, 2, allocator)
                 } else if str.starts_with(&['A', 'N', 'D'])
                     && str.get(3).map(|it| !it.is_alphabetic()).unwrap_or(true)
                 {
@@ -685,10 +1559,48 @@ impl TokenParser {
                     && str.get(3).map(|it| !it.is_alphabetic()).unwrap_or(true)
                 {
                     op(OperatorTokenType::BinXor, str, 3, allocator)
+                } else if str.starts_with(&['a', 'n', 'd'])
+                    && str.get(3).map(|it| !it.is_alphanumeric()).unwrap_or(true)
+                {
+                    op(OperatorTokenType::And, str, 3, allocator)
+                } else if str.starts_with(&['o', 'r'])
+                    && str.get(2).map(|it| !it.is_alphanumeric()).unwrap_or(true)
+                {
+                    op(OperatorTokenType::Or, str, 2, allocator)
+                } else if str.starts_with(&['n', 'o', 't'])
+                    && str.get(3).map(|it| !it.is_alphanumeric()).unwrap_or(true)
+                {
+                    op(OperatorTokenType::Not, str, 3, allocator)
+                } else if str.starts_with(&['m', 'o', 'd'])
+                    && str.get(3).map(|it| !it.is_alphanumeric()).unwrap_or(true)
+                {
+                    op(OperatorTokenType::Mod, str, 3, allocator)
+                } else if str.starts_with(&['r', 'e', 'm'])
+                    && str.get(3).map(|it| !it.is_alphanumeric()).unwrap_or(true)
+                {
+                    op(OperatorTokenType::Modulo, str, 3, allocator)
+                } else if let Some(len) = starts_with_words(
+                    str,
+                    &[&['i', 'n', 'c', 'r', 'e', 'a', 's', 'e', 'd'], &['b', 'y']],
+                ) {
+                    op(OperatorTokenType::IncreasedBy, str, len, allocator)
+                } else if let Some(len) = starts_with_words(
+                    str,
+                    &[&['d', 'e', 'c', 'r', 'e', 'a', 's', 'e', 'd'], &['b', 'y']],
+                ) {
+                    op(OperatorTokenType::DecreasedBy, str, len, allocator)
                 } else if str.starts_with(&['<', '<']) {
                     op(OperatorTokenType::ShiftLeft, str, 2, allocator)
                 } else if str.starts_with(&['>', '>']) {
                     op(OperatorTokenType::ShiftRight, str, 2, allocator)
+                } else if str.starts_with(&['>', '=']) {
+                    op(OperatorTokenType::GreaterOrEq, str, 2, allocator)
+                } else if str.starts_with(&['<', '=']) {
+                    op(OperatorTokenType::LessOrEq, str, 2, allocator)
+                } else if str[0] == '>' {
+                    op(OperatorTokenType::Greater, str, 1, allocator)
+                } else if str[0] == '<' {
+                    op(OperatorTokenType::Less, str, 1, allocator)
                 } else {
                     None
                 }
@@ -697,6 +1609,56 @@ impl TokenParser {
     }
 }
 
+/// Rebuilds the source of the tokens, it is the inverse of `TokenParser::parse_line`
+/// for the consumed part of the line.
+pub fn tokens_to_string(tokens: &[Token]) -> String {
+    tokens.iter().flat_map(|it| it.ptr.iter()).collect()
+}
+
+/// Whether the line is only text (plain words, a header or a comment),
+/// so it can not have a result.
+pub fn line_is_text_only(tokens: &[Token]) -> bool {
+    tokens
+        .iter()
+        .all(|it| matches!(it.typ, TokenType::StringLiteral | TokenType::Header))
+}
+
+/// Matches a keyword consisting of multiple words (e.g. `increased by`) at the start of `str`,
+/// the words can be separated by any number of whitespaces.
+/// Returns the length of the matched text.
+fn starts_with_words(str: &[char], words: &[&[char]]) -> Option<usize> {
+    let mut len = 0;
+    for (i, word) in words.iter().enumerate() {
+        if i > 0 {
+            let ws_count = str[len..]
+                .iter()
+                .take_while(|it| it.is_whitespace())
+                .count();
+            if ws_count == 0 {
+                return None;
+            }
+            len += ws_count;
+        }
+        if !str[len..].starts_with(word) {
+            return None;
+        }
+        len += word.len();
+    }
+    let is_whole_word = str.get(len).map(|it| !it.is_alphanumeric()).unwrap_or(true);
+    return if is_whole_word { Some(len) } else { None };
+}
+
+/// the ASCII hyphen-minus, the minus sign (U+2212) or the en dash (U+2013),
+/// the latter two often appear in copy-pasted expressions
+fn is_minus_sign(ch: char) -> bool {
+    matches!(ch, '-' | '−' | '–')
+}
+
+/// "0x" or "0b" in either case, `radix_ch` is the lowercase letter
+pub(crate) fn has_radix_prefix(str: &[char], radix_ch: char) -> bool {
+    str.get(0) == Some(&'0') && str.get(1).map(|it| it.to_ascii_lowercase()) == Some(radix_ch)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -704,7 +1666,7 @@ mod tests {
     use crate::helper::create_vars;
     use crate::shunting_yard::tests::*;
     use crate::units::units::Units;
-    use crate::{Variable, MAX_LINE_COUNT};
+    use crate::{Variable, VARIABLE_COUNT};
 
     #[test]
     fn test_number_parsing() {
@@ -713,12 +1675,21 @@ mod tests {
             let temp = str.chars().collect::<Vec<_>>();
             let units = Units::new();
             let arena = Bump::new();
-            TokenParser::parse_line(&temp, &create_vars(), &mut vec, &units, 0, &arena);
+            TokenParser::parse_line(
+                &temp,
+                &create_vars(),
+                &mut vec,
+                &units,
+                0,
+                &arena,
+                ParseOptions::default(),
+            );
             match vec.get(0) {
                 Some(Token {
                     ptr: _,
                     typ: TokenType::NumberLiteral(num),
                     has_error: _,
+                    is_ambiguous: _,
                 }) => {
                     assert_eq!(*num, expected_value.into());
                 }
@@ -732,12 +1703,21 @@ mod tests {
             let temp = str.chars().collect::<Vec<_>>();
             let units = Units::new();
             let arena = Bump::new();
-            TokenParser::parse_line(&temp, &create_vars(), &mut vec, &units, 0, &arena);
+            TokenParser::parse_line(
+                &temp,
+                &create_vars(),
+                &mut vec,
+                &units,
+                0,
+                &arena,
+                ParseOptions::default(),
+            );
             match vec.get(0) {
                 Some(Token {
                     ptr: _,
                     typ: TokenType::NumberLiteral(num),
                     has_error: _,
+                    is_ambiguous: _,
                 }) => {
                     assert_eq!(Decimal::from_str(expected_value).expect("must"), *num);
                 }
@@ -750,9 +1730,11 @@ mod tests {
         test_parse("0b0101", 5);
         test_parse("0b0101 1010", 90);
         test_parse("0b0101 101     1", 91);
+        test_parse("0B11", 3);
 
         test_parse("0x1", 1);
         test_parse("0xAB_Cd_e____f", 11_259_375);
+        test_parse("0X1F", 31);
 
         test_parse("1", 1);
         test_parse("123456", 123456);
@@ -767,85 +1749,666 @@ mod tests {
         test_parse_f("123.456.3", "123.456");
     }
 
-    fn test_vars(var_names: &[&'static [char]], text: &str, expected_tokens: &[Token]) {
-        let var_names: Vec<Option<Variable>> = (0..MAX_LINE_COUNT + 1)
-            .into_iter()
-            .map(|index| {
-                if let Some(var_name) = var_names.get(index) {
-                    Some(Variable {
-                        name: Box::from(*var_name),
-                        value: Ok(CalcResult::new(CalcResultType::Number(Decimal::zero()), 0)),
-                    })
-                } else {
-                    None
-                }
-            })
-            .collect();
-        println!("{}", text);
-        let mut vec = vec![];
-        let temp = text.chars().collect::<Vec<_>>();
+    #[test]
+    fn test_comparison_operators() {
+        test("1>2", &[num(1), op(OperatorTokenType::Greater), num(2)]);
+        test("1<2", &[num(1), op(OperatorTokenType::Less), num(2)]);
+        test(
+            "1>=2",
+            &[num(1), op(OperatorTokenType::GreaterOrEq), num(2)],
+        );
+        test("1<=2", &[num(1), op(OperatorTokenType::LessOrEq), num(2)]);
+        test("1>>2", &[num(1), op(OperatorTokenType::ShiftRight), num(2)]);
+    }
+
+    #[test]
+    fn test_modulo() {
+        test(
+            "17 % 5",
+            &[
+                num(17),
+                str(" "),
+                op(OperatorTokenType::Modulo),
+                str(" "),
+                num(5),
+            ],
+        );
+        test("17%5", &[num(17), op(OperatorTokenType::Modulo), num(5)]);
+        test(
+            "17 mod 5",
+            &[
+                num(17),
+                str(" "),
+                op(OperatorTokenType::Mod),
+                str(" "),
+                num(5),
+            ],
+        );
+        test(
+            "17 rem 5",
+            &[
+                num(17),
+                str(" "),
+                op(OperatorTokenType::Modulo),
+                str(" "),
+                num(5),
+            ],
+        );
+        test("20%", &[num(20), op(OperatorTokenType::Perc)]);
+        test(
+            "200 + 20%",
+            &[
+                num(200),
+                str(" "),
+                op(OperatorTokenType::Add),
+                str(" "),
+                num(20),
+                op(OperatorTokenType::Perc),
+            ],
+        );
+        test(
+            "1.5 % 5",
+            &[
+                numf(1.5),
+                str(" "),
+                op(OperatorTokenType::Perc),
+                str(" "),
+                num(5),
+            ],
+        );
+        // the unit is parsed so the evaluation can reject it
+        test(
+            "30% kg",
+            &[
+                num(30),
+                op(OperatorTokenType::Perc),
+                str(" "),
+                apply_to_prev_token_unit("kg"),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_variable_which_is_also_a_unit_is_ambiguous() {
+        fn parse<'a>(var_name: &[char], text: &str, arena: &'a Bump) -> Vec<Token<'a>> {
+            let mut vars = create_vars();
+            vars[0] = Some(Variable {
+                name: Box::from(var_name),
+                value: Ok(CalcResult::new(CalcResultType::Number(Decimal::zero()), 0)),
+                placeholder: false,
+            });
+            let temp = text.chars().collect::<Vec<_>>();
+            let units = Units::new();
+            let mut tokens = vec![];
+            TokenParser::parse_line(
+                &temp,
+                &vars,
+                &mut tokens,
+                &units,
+                10,
+                arena,
+                ParseOptions::default(),
+            );
+            tokens
+        }
+        let arena = Bump::new();
+
+        let tokens = parse(&['m'], "5 m", &arena);
+        assert!(matches!(
+            tokens[2].typ,
+            TokenType::Variable { var_index: 0 }
+        ));
+        assert!(tokens[2].is_ambiguous());
+        assert!(!tokens[0].is_ambiguous());
+
+        let tokens = parse(&['a', 'p', 'p', 'l', 'e'], "5 * apple", &arena);
+        assert!(matches!(
+            tokens[4].typ,
+            TokenType::Variable { var_index: 0 }
+        ));
+        assert!(!tokens[4].is_ambiguous());
+    }
+
+    #[test]
+    fn test_long_line_with_many_variables() {
+        let names: Vec<Vec<char>> = (0..100)
+            .map(|i| format!("var{}", i).chars().collect())
+            .collect();
+        let vars: Vec<Option<Variable>> = (0..VARIABLE_COUNT)
+            .map(|i| {
+                names.get(i).map(|name| Variable {
+                    name: Box::from(&name[..]),
+                    value: Ok(CalcResult::new(CalcResultType::Number(Decimal::zero()), 0)),
+                    placeholder: false,
+                })
+            })
+            .collect();
+        let text = "var42 + var7 * ".repeat(700);
+        let temp = text.chars().collect::<Vec<_>>();
+        let units = Units::new();
+        let arena = Bump::new();
+        let mut tokens = vec![];
+
+        TokenParser::parse_line(
+            &temp,
+            &vars,
+            &mut tokens,
+            &units,
+            100,
+            &arena,
+            ParseOptions::default(),
+        );
+        // only the positions starting with 'v' are compared with the names
+        let var_candidates = TokenParser::sorted_var_candidates(&vars, 100);
+        let compared_names: usize = temp
+            .iter()
+            .map(|ch| TokenParser::var_candidates_starting_with(&var_candidates, *ch).len())
+            .sum();
+        assert_eq!(compared_names, 1400 * 100);
+
+        let var_count = tokens
+            .iter()
+            .filter(|it| matches!(it.typ, TokenType::Variable { .. }))
+            .count();
+        assert_eq!(var_count, 1400);
+        assert!(matches!(
+            tokens[0].typ,
+            TokenType::Variable { var_index: 42 }
+        ));
+        assert!(matches!(
+            tokens[4].typ,
+            TokenType::Variable { var_index: 7 }
+        ));
+    }
+
+    #[test]
+    fn test_sqrt_operator() {
+        test("√9", &[op(OperatorTokenType::Sqrt), num(9)]);
+        test(
+            "√(4+5)",
+            &[
+                op(OperatorTokenType::Sqrt),
+                op(OperatorTokenType::ParenOpen),
+                num(4),
+                op(OperatorTokenType::Add),
+                num(5),
+                op(OperatorTokenType::ParenClose),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_exponent_before_unit() {
+        test("1e3m", &[num(1000), apply_to_prev_token_unit("m")]);
+        test(
+            "1e3 m",
+            &[num(1000), str(" "), apply_to_prev_token_unit("m")],
+        );
+        test(
+            "2.5e-2 kg",
+            &[numf(0.025), str(" "), apply_to_prev_token_unit("kg")],
+        );
+    }
+
+    #[test]
+    fn test_precedence_and_assoc() {
+        assert!(OperatorTokenType::Pow.precedence() > OperatorTokenType::Mult.precedence());
+        assert!(OperatorTokenType::Mult.precedence() > OperatorTokenType::Add.precedence());
+        assert_eq!(
+            OperatorTokenType::Mult.precedence(),
+            OperatorTokenType::Div.precedence()
+        );
+        assert_eq!(OperatorTokenType::Pow.assoc(), Assoc::Right);
+        assert_eq!(OperatorTokenType::Sub.assoc(), Assoc::Left);
+    }
+
+    #[test]
+    fn test_magnitude_words() {
+        test("3 thousand", &[num(3_000)]);
+        test("2.5 million", &[num(2_500_000)]);
+        test("1 billion", &[num(1_000_000_000)]);
+        test("2 trillion", &[num(2_000_000_000_000)]);
+        test(
+            "the million dollar question",
+            &[
+                str("the"),
+                str(" "),
+                str("million"),
+                str(" "),
+                str("dollar"),
+                str(" "),
+                str("question"),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_comma_as_thousands_separator() {
+        test(
+            "1,000 + 2,000",
+            &[
+                num(1_000),
+                str(" "),
+                op(OperatorTokenType::Add),
+                str(" "),
+                num(2_000),
+            ],
+        );
+        test("1,000,000", &[num(1_000_000)]);
+        test(
+            "[1,000, 2,000]",
+            &[
+                op(OperatorTokenType::BracketOpen),
+                num(1_000),
+                op(OperatorTokenType::Comma),
+                str(" "),
+                num(2_000),
+                op(OperatorTokenType::BracketClose),
+            ],
+        );
+        // without a spaced separator, every ',' separates elements
+        test(
+            "[100,200]",
+            &[
+                op(OperatorTokenType::BracketOpen),
+                num(100),
+                op(OperatorTokenType::Comma),
+                num(200),
+                op(OperatorTokenType::BracketClose),
+            ],
+        );
+    }
+
+    fn test_vars(var_names: &[&'static [char]], text: &str, expected_tokens: &[Token]) {
+        test_vars_with_keyword(var_names, text, expected_tokens, ConversionKeyword::In);
+    }
+
+    fn test_vars_with_keyword(
+        var_names: &[&'static [char]],
+        text: &str,
+        expected_tokens: &[Token],
+        conversion_keyword: ConversionKeyword,
+    ) {
+        test_vars_with_options(
+            var_names,
+            text,
+            expected_tokens,
+            ParseOptions {
+                conversion_keyword,
+                ..Default::default()
+            },
+        );
+    }
+
+    fn test_decimal_comma(text: &str, expected_tokens: &[Token]) {
+        test_vars_with_options(
+            &[],
+            text,
+            expected_tokens,
+            ParseOptions {
+                number_input_mode: NumberInputMode::DecimalComma,
+                ..Default::default()
+            },
+        );
+    }
+
+    fn test_vars_with_options(
+        var_names: &[&'static [char]],
+        text: &str,
+        expected_tokens: &[Token],
+        options: ParseOptions,
+    ) {
+        let var_names: Vec<Option<Variable>> = (0..VARIABLE_COUNT)
+            .into_iter()
+            .map(|index| {
+                if let Some(var_name) = var_names.get(index) {
+                    Some(Variable {
+                        name: Box::from(*var_name),
+                        value: Ok(CalcResult::new(CalcResultType::Number(Decimal::zero()), 0)),
+                        placeholder: false,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect();
+        println!("{}", text);
+        let mut vec = vec![];
+        let temp = text.chars().collect::<Vec<_>>();
+        let units = Units::new();
+        let arena = Bump::new();
+        // line index is 10 so the search for the variable does not stop at 0
+        TokenParser::parse_line(&temp, &var_names, &mut vec, &units, 10, &arena, options);
+        assert_eq!(
+            expected_tokens.len(),
+            vec.len(),
+            "actual tokens:\n {:?}",
+            vec.iter()
+                .map(|it| format!("{:?}\n", it))
+                .collect::<Vec<_>>()
+                .join(" -----> ")
+        );
+        for (actual_token, expected_token) in vec.iter().zip(expected_tokens.iter()) {
+            match (&expected_token.typ, &actual_token.typ) {
+                (TokenType::NumberLiteral(expected_num), TokenType::NumberLiteral(actual_num)) => {
+                    assert_eq!(expected_num, actual_num)
+                }
+                (TokenType::Unit(_), TokenType::Unit(_))
+                | (
+                    TokenType::Operator(OperatorTokenType::ApplyUnit(_)),
+                    TokenType::Operator(OperatorTokenType::ApplyUnit(_)),
+                ) => {
+                    //     expected_op is an &str
+                    let str_slice = unsafe { std::mem::transmute::<_, &str>(expected_token.ptr) };
+                    let expected_chars = str_slice.chars().collect::<Vec<char>>();
+                    assert_eq!(actual_token.ptr, expected_chars.as_slice())
+                }
+                (TokenType::NumberErr, _) => {
+                    assert_eq!(actual_token.typ, expected_token.typ);
+                }
+                (TokenType::Operator(etyp), TokenType::Operator(atyp)) => assert_eq!(etyp, atyp),
+                (TokenType::StringLiteral, TokenType::StringLiteral)
+                | (TokenType::Header, TokenType::Header) => {
+                    // expected_op is an &str
+                    let str_slice = unsafe { std::mem::transmute::<_, &str>(expected_token.ptr) };
+                    let expected_chars = str_slice.chars().collect::<Vec<char>>();
+                    assert_eq!(actual_token.ptr, expected_chars.as_slice())
+                }
+                (TokenType::Variable { .. }, TokenType::Variable { .. })
+                | (TokenType::LineReference { .. }, TokenType::LineReference { .. }) => {
+                    // expected_op is an &str
+                    let str_slice = unsafe { std::mem::transmute::<_, &str>(expected_token.ptr) };
+                    let expected_chars = str_slice.chars().collect::<Vec<char>>();
+                    assert_eq!(actual_token.ptr, expected_chars.as_slice())
+                }
+                _ => panic!(
+                    "'{}', {:?} != {:?}, actual tokens:\n {:?}",
+                    text,
+                    expected_token,
+                    actual_token,
+                    vec.iter()
+                        .map(|it| format!("{:?}\n", it))
+                        .collect::<Vec<_>>()
+                        .join(" -----> ")
+                ),
+            }
+        }
+    }
+
+    fn test(text: &str, expected_tokens: &[Token]) {
+        test_vars(&[], text, expected_tokens);
+    }
+
+    #[test]
+    fn test_conversion_to_number() {
+        test(
+            "5 in number",
+            &[
+                num(5),
+                str(" "),
+                op(OperatorTokenType::UnitConverter),
+                str(" "),
+                unit("number"),
+            ],
+        );
+        test_vars_with_keyword(
+            &[],
+            "5 as scalar",
+            &[
+                num(5),
+                str(" "),
+                op(OperatorTokenType::UnitConverter),
+                str(" "),
+                unit("scalar"),
+            ],
+            ConversionKeyword::As,
+        );
+        // only as a conversion target
+        test("5 number", &[num(5), str(" "), str("number")]);
+    }
+
+    #[test]
+    fn test_as_conversion_keyword() {
+        test_vars_with_keyword(
+            &[],
+            "10 km as m",
+            &[
+                num(10),
+                str(" "),
+                unit("km"),
+                str(" "),
+                op(OperatorTokenType::UnitConverter),
+                str(" "),
+                unit("m"),
+            ],
+            ConversionKeyword::As,
+        );
+        // "in" is not a conversion then
+        test_vars_with_keyword(
+            &[],
+            "10 km in m",
+            &[
+                num(10),
+                str(" "),
+                unit("km"),
+                str(" "),
+                str("in"),
+                str(" "),
+                str("m"),
+            ],
+            ConversionKeyword::As,
+        );
+        // and "as" is not one by default
+        test(
+            "10 km as m",
+            &[
+                num(10),
+                str(" "),
+                unit("km"),
+                str(" "),
+                str("as"),
+                str(" "),
+                str("m"),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_unicode_multiplication_and_division_signs() {
+        test(
+            "2 · 3",
+            &[
+                num(2),
+                str(" "),
+                op(OperatorTokenType::Mult),
+                str(" "),
+                num(3),
+            ],
+        );
+        test("2×3", &[num(2), op(OperatorTokenType::Mult), num(3)]);
+        test(
+            "6 ÷ 2",
+            &[
+                num(6),
+                str(" "),
+                op(OperatorTokenType::Div),
+                str(" "),
+                num(2),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_line_is_text_only() {
+        let units = Units::new();
+        let arena = Bump::new();
+        let is_text_only = |text: &str| {
+            let temp = text.chars().collect::<Vec<_>>();
+            let mut tokens = vec![];
+            TokenParser::parse_line(
+                &temp,
+                &create_vars(),
+                &mut tokens,
+                &units,
+                10,
+                &arena,
+                ParseOptions::default(),
+            );
+            line_is_text_only(&tokens)
+        };
+        assert!(is_text_only("just a note"));
+        assert!(is_text_only("# heading"));
+        assert!(is_text_only("// a comment"));
+        assert!(is_text_only(""));
+        assert!(!is_text_only("2 + 2"));
+        assert!(!is_text_only("price: 12 km"));
+    }
+
+    #[test]
+    fn test_tokens_to_string_round_trip() {
+        let mut vars = create_vars();
+        vars[0] = Some(Variable {
+            name: Box::from(&['x'][..]),
+            value: Ok(CalcResult::new(CalcResultType::Number(Decimal::zero()), 0)),
+            placeholder: false,
+        });
+        let units = Units::new();
+        for text in &[
+            "12 + 3",
+            "  2 * (3 + 4)  ",
+            "45min in m",
+            "10(km/h)^2 * 45min in m",
+            "[1, 2; 3, 4] * 2",
+            "[1,2] kg + 1 kg",
+            "x = sin(30 degree) + x",
+            "0xFF AND 0b1010_1100",
+            "c * 2 s in km",
+            "2 * g + 3 g",
+            "40° 26' 46\" in degree",
+            "5 − 3 × 2",
+            "some text 12 km",
+            "# header",
+            "12 + 3 // comment",
+        ] {
+            let temp = text.chars().collect::<Vec<_>>();
+            let arena = Bump::new();
+            let mut tokens = vec![];
+            TokenParser::parse_line(
+                &temp,
+                &vars,
+                &mut tokens,
+                &units,
+                10,
+                &arena,
+                ParseOptions::default(),
+            );
+            assert_eq!(*text, tokens_to_string(&tokens));
+        }
+    }
+
+    #[test]
+    fn test_the_implicit_tokens_of_constants_are_inside_their_text() {
         let units = Units::new();
         let arena = Bump::new();
-        // line index is 10 so the search for the variable does not stop at 0
-        TokenParser::parse_line(&temp, &var_names, &mut vec, &units, 10, &arena);
+        let parse = |text: &str| {
+            let temp = text.chars().collect::<Vec<_>>();
+            let mut tokens = vec![];
+            TokenParser::parse_line(
+                &temp,
+                &create_vars(),
+                &mut tokens,
+                &units,
+                10,
+                &arena,
+                ParseOptions::default(),
+            );
+            tokens
+        };
+        // the unit of "c" is an empty slice right after the "c"
+        let tokens = parse("c in km/s");
+        assert!(matches!(
+            tokens[1].typ,
+            TokenType::Operator(OperatorTokenType::ApplyUnit(..))
+        ));
+        assert!(tokens[1].ptr.is_empty());
+        assert_eq!(tokens[1].ptr.as_ptr(), tokens[0].ptr[1..].as_ptr());
+
+        let tokens = parse("40° 26' 46\"");
+        assert!(matches!(
+            tokens[1].typ,
+            TokenType::Operator(OperatorTokenType::ApplyUnit(..))
+        ));
         assert_eq!(
-            expected_tokens.len(),
-            vec.len(),
-            "actual tokens:\n {:?}",
-            vec.iter()
-                .map(|it| format!("{:?}\n", it))
-                .collect::<Vec<_>>()
-                .join(" -----> ")
+            tokens[1].ptr.as_ptr(),
+            tokens[0].ptr[tokens[0].ptr.len()..].as_ptr()
         );
-        for (actual_token, expected_token) in vec.iter().zip(expected_tokens.iter()) {
-            match (&expected_token.typ, &actual_token.typ) {
-                (TokenType::NumberLiteral(expected_num), TokenType::NumberLiteral(actual_num)) => {
-                    assert_eq!(expected_num, actual_num)
-                }
-                (TokenType::Unit(_), TokenType::Unit(_))
-                | (
-                    TokenType::Operator(OperatorTokenType::ApplyUnit(_)),
-                    TokenType::Operator(OperatorTokenType::ApplyUnit(_)),
-                ) => {
-                    //     expected_op is an &str
-                    let str_slice = unsafe { std::mem::transmute::<_, &str>(expected_token.ptr) };
-                    let expected_chars = str_slice.chars().collect::<Vec<char>>();
-                    assert_eq!(actual_token.ptr, expected_chars.as_slice())
-                }
-                (TokenType::NumberErr, _) => {
-                    assert_eq!(actual_token.typ, expected_token.typ);
-                }
-                (TokenType::Operator(etyp), TokenType::Operator(atyp)) => assert_eq!(etyp, atyp),
-                (TokenType::StringLiteral, TokenType::StringLiteral)
-                | (TokenType::Header, TokenType::Header) => {
-                    // expected_op is an &str
-                    let str_slice = unsafe { std::mem::transmute::<_, &str>(expected_token.ptr) };
-                    let expected_chars = str_slice.chars().collect::<Vec<char>>();
-                    assert_eq!(actual_token.ptr, expected_chars.as_slice())
-                }
-                (TokenType::Variable { .. }, TokenType::Variable { .. })
-                | (TokenType::LineReference { .. }, TokenType::LineReference { .. }) => {
-                    // expected_op is an &str
-                    let str_slice = unsafe { std::mem::transmute::<_, &str>(expected_token.ptr) };
-                    let expected_chars = str_slice.chars().collect::<Vec<char>>();
-                    assert_eq!(actual_token.ptr, expected_chars.as_slice())
-                }
-                _ => panic!(
-                    "'{}', {:?} != {:?}, actual tokens:\n {:?}",
-                    text,
-                    expected_token,
-                    actual_token,
-                    vec.iter()
-                        .map(|it| format!("{:?}\n", it))
-                        .collect::<Vec<_>>()
-                        .join(" -----> ")
-                ),
-            }
-        }
+
+        // gram after a multiplication, the number is an empty slice before the "g"
+        let tokens = parse("5 * g");
+        assert_eq!(tokens[4].typ, TokenType::NumberLiteral(Decimal::one()));
+        assert!(tokens[4].ptr.is_empty());
+        assert_eq!(tokens[5].ptr, &['g'][..]);
+        assert_eq!(tokens[4].ptr.as_ptr(), tokens[5].ptr.as_ptr());
     }
 
-    fn test(text: &str, expected_tokens: &[Token]) {
-        test_vars(&[], text, expected_tokens);
+    #[test]
+    fn test_alternative_assignment_operators() {
+        test(
+            "x := 5",
+            &[
+                str("x"),
+                str(" "),
+                op(OperatorTokenType::Assign),
+                str(" "),
+                num(5),
+            ],
+        );
+        test("x:=5", &[str("x"), op(OperatorTokenType::Assign), num(5)]);
+        test("x≔5", &[str("x"), op(OperatorTokenType::Assign), num(5)]);
+        // a lone colon is not an operator
+        test("3:4", &[num(3), str(":4")]);
+    }
+
+    #[test]
+    fn test_unit_after_matrix() {
+        test(
+            "[1] kg",
+            &[
+                op(OperatorTokenType::BracketOpen),
+                num(1),
+                op(OperatorTokenType::BracketClose),
+                str(" "),
+                apply_to_prev_token_unit("kg"),
+            ],
+        );
+        test(
+            "[1] in m",
+            &[
+                op(OperatorTokenType::BracketOpen),
+                num(1),
+                op(OperatorTokenType::BracketClose),
+                str(" "),
+                op(OperatorTokenType::UnitConverter),
+                str(" "),
+                unit("m"),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_unicode_minus_signs() {
+        test(
+            "5 − 3",
+            &[
+                num(5),
+                str(" "),
+                op(OperatorTokenType::Sub),
+                str(" "),
+                num(3),
+            ],
+        );
+        test("5–3", &[num(5), op(OperatorTokenType::Sub), num(3)]);
+        test("−7", &[op(OperatorTokenType::Sub), num(7)]);
     }
 
     #[test]
@@ -1438,6 +3001,211 @@ mod tests {
         test("2kalap", &[num(2), str("kalap")]);
     }
 
+    #[test]
+    fn test_bool_literals_and_logical_operators() {
+        test(
+            "true and false",
+            &[
+                num(1),
+                str(" "),
+                op(OperatorTokenType::And),
+                str(" "),
+                num(0),
+            ],
+        );
+        test(
+            "not true or false",
+            &[
+                op(OperatorTokenType::Not),
+                str(" "),
+                num(1),
+                str(" "),
+                op(OperatorTokenType::Or),
+                str(" "),
+                num(0),
+            ],
+        );
+        test("trueish", &[str("trueish")]);
+        test("android", &[str("android")]);
+        // in prose they are just words
+        test(
+            "is not true",
+            &[str("is"), str(" "), str("not"), str(" "), str("true")],
+        );
+        test(
+            "5 kg or so",
+            &[
+                num(5),
+                str(" "),
+                unit("kg"),
+                str(" "),
+                str("or"),
+                str(" "),
+                str("so"),
+            ],
+        );
+        test("true story", &[str("true"), str(" "), str("story")]);
+    }
+
+    #[test]
+    fn test_section_aggregates_are_followed_by_an_operator_or_nothing() {
+        test("sum", &[var("sum")]);
+        test("count", &[var("count")]);
+        test(
+            "avg + 1",
+            &[
+                var("avg"),
+                str(" "),
+                op(OperatorTokenType::Add),
+                str(" "),
+                num(1),
+            ],
+        );
+        test(
+            "count in m",
+            &[
+                var("count"),
+                str(" "),
+                op(OperatorTokenType::UnitConverter),
+                str(" "),
+                unit("m"),
+            ],
+        );
+        test(
+            "count (1, 2)",
+            &[
+                str("count"),
+                str(" "),
+                op(OperatorTokenType::ParenOpen),
+                num(1),
+                op(OperatorTokenType::Comma),
+                str(" "),
+                num(2),
+                op(OperatorTokenType::ParenClose),
+            ],
+        );
+        test("avg speed", &[str("avg"), str(" "), str("speed")]);
+        test("summary", &[str("summary")]);
+    }
+
+    #[test]
+    fn test_line_range() {
+        let vars: Vec<Option<Variable>> = (0..VARIABLE_COUNT)
+            .map(|index| {
+                Some(Variable {
+                    name: format!("&[{}]", index + 1)
+                        .chars()
+                        .collect::<Vec<_>>()
+                        .into(),
+                    value: Ok(CalcResult::new(CalcResultType::Number(Decimal::zero()), 0)),
+                    placeholder: false,
+                })
+            })
+            .collect();
+        let parse = |text: &str| {
+            let chars = text.chars().collect::<Vec<_>>();
+            let units = Units::new();
+            let arena = Bump::new();
+            let mut tokens = vec![];
+            TokenParser::parse_line(
+                &chars,
+                &vars,
+                &mut tokens,
+                &units,
+                10,
+                &arena,
+                ParseOptions::default(),
+            );
+            let types: Vec<TokenType> = tokens.iter().map(|it| it.typ.clone()).collect();
+            types
+        };
+        assert_eq!(
+            parse("&[2:5]"),
+            &[TokenType::LineRange {
+                from_var_index: 1,
+                to_var_index: 4
+            }]
+        );
+        // out of order
+        assert_eq!(parse("&[5:2]"), &[TokenType::NumberErr]);
+        // below the current line
+        assert_eq!(parse("&[2:11]"), &[TokenType::NumberErr]);
+    }
+
+    #[test]
+    fn test_decimal_comma_input() {
+        test_decimal_comma("3,14", &[numf(3.14)]);
+        test_decimal_comma("1.000.000,5", &[numf(1_000_000.5)]);
+        test_decimal_comma("1 000,5", &[numf(1_000.5)]);
+        test_decimal_comma(
+            "[1,5; 2,5]",
+            &[
+                op(OperatorTokenType::BracketOpen),
+                numf(1.5),
+                op(OperatorTokenType::Semicolon),
+                str(" "),
+                numf(2.5),
+                op(OperatorTokenType::BracketClose),
+            ],
+        );
+        // the comma is a list separator if it is not between digits
+        test_decimal_comma(
+            "[1, 2]",
+            &[
+                op(OperatorTokenType::BracketOpen),
+                num(1),
+                op(OperatorTokenType::Comma),
+                str(" "),
+                num(2),
+                op(OperatorTokenType::BracketClose),
+            ],
+        );
+        // the default is the decimal point
+        test("3,14", &[num(3), op(OperatorTokenType::Comma), num(14)]);
+    }
+
+    #[test]
+    fn test_increased_and_decreased_by() {
+        test(
+            "100 increased by 20%",
+            &[
+                num(100),
+                str(" "),
+                op(OperatorTokenType::IncreasedBy),
+                str(" "),
+                num(20),
+                op(OperatorTokenType::Perc),
+            ],
+        );
+        test(
+            "100 decreased  by 15%",
+            &[
+                num(100),
+                str(" "),
+                op(OperatorTokenType::DecreasedBy),
+                str(" "),
+                num(15),
+                op(OperatorTokenType::Perc),
+            ],
+        );
+        test("increased", &[str("increased")]);
+        test(
+            "increased byproduct",
+            &[str("increased"), str(" "), str("byproduct")],
+        );
+    }
+
+    #[test]
+    fn test_exponent_and_magnitude_suffix_together() {
+        test("1.5M", &[num(1_500_000)]);
+        test("1.5k", &[num(1_500)]);
+        // the number ends after the exponent
+        test("1e3k", &[num(1_000), str("k")]);
+        test("3e-3M", &[numf(0.003), str("M")]);
+        // the number ends after the suffix
+        test("2ke3", &[num(2_000), str("e3")]);
+    }
+
     #[test]
     fn test_that_strings_are_parsed_fully_so_b0_is_not_equal_to_b_and_0() {
         test_vars(
@@ -1689,7 +3457,9 @@ mod tests {
                 str("J7qt799"),
                 op(OperatorTokenType::Div),
                 num(9),
-                apply_to_prev_token_unit("b^72"),
+                str("b"),
+                op(OperatorTokenType::Pow),
+                num(72),
                 str("u5KYD76O26w6"),
                 op(OperatorTokenType::Pow),
                 num(4),
@@ -1698,6 +3468,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_letters_glued_to_integers_that_look_like_hex_digits() {
+        test(
+            "9b^72",
+            &[num(9), str("b"), op(OperatorTokenType::Pow), num(72)],
+        );
+        test("4f2", &[num(4), str("f2")]);
+        test("0x1fg", &[num(0x1), str("fg")]);
+        test("0b101b", &[num(0b101), str("b")]);
+        // units are still allowed with a space or when they don't look like digits
+        test(
+            "0x1f g",
+            &[num(0x1f), str(" "), apply_to_prev_token_unit("g")],
+        );
+        test("9b", &[num(9), apply_to_prev_token_unit("b")]);
+        test("9B/s", &[num(9), apply_to_prev_token_unit("B / s")]);
+        test("9m^2", &[num(9), apply_to_prev_token_unit("m^2")]);
+        test("2.5b^2", &[numf(2.5), apply_to_prev_token_unit("b^2")]);
+    }
+
     #[test]
     fn test_huge_unit_number_no_panic() {
         test(
@@ -1770,6 +3560,23 @@ mod tests {
 
     #[test]
     fn test_undorscore_is_allowed_in_hex() {
+        test("0b1010_1100", &[num(0b1010_1100)]);
+        test("0b1_0_1", &[num(0b101)]);
+        test("0b1010_", &[num(0b1010), str("_")]);
+        test("0b_1010", &[str("0b_1010")]);
+        test("0b1010__1100", &[num(0b1010), str("__1100")]);
+        test(
+            "0b1010_1100 * 2",
+            &[
+                num(0b1010_1100),
+                str(" "),
+                op(OperatorTokenType::Mult),
+                str(" "),
+                num(2),
+            ],
+        );
+        test("0b1010 1100", &[num(0b1010_1100)]);
+
         test("0xAA_B", &[num(0xAAB)]);
         test("0xAA_BB", &[num(0xAABB)]);
         test("0xA_A_B", &[num(0xAAB)]);
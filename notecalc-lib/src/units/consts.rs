@@ -52,6 +52,9 @@ pub const ALL_UNIT_COUNT: usize = 25;
 pub const EMPTY_UNIT_DIMENSIONS: [UnitDimensionExponent; BASE_UNIT_DIMENSION_COUNT] =
     [0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
 
+pub const ANGLE_UNIT_DIMENSIONS: [UnitDimensionExponent; BASE_UNIT_DIMENSION_COUNT] =
+    [0, 0, 0, 0, 0, 0, 0, 1, 0, 0];
+
 pub(crate) const BASE_UNIT_DIMENSIONS: [[UnitDimensionExponent; BASE_UNIT_DIMENSION_COUNT];
     ALL_UNIT_COUNT] = [
     [1, 0, 0, 0, 0, 0, 0, 0, 0, 0], // Mass
@@ -98,6 +101,8 @@ fn create_prefixes() -> UnitPrefixes {
             RefCell::new(Prefix::from_scientific(&['d'], "1e-1", false)),
             RefCell::new(Prefix::from_scientific(&['c'], "1e-2", false)),
             RefCell::new(Prefix::from_scientific(&['m'], "1e-3", true)),
+            // before 'u' so that it is the one displayed when a prefix is chosen automatically
+            RefCell::new(Prefix::from_scientific(&['µ'], "1e-6", true)),
             RefCell::new(Prefix::from_scientific(&['u'], "1e-6", true)),
             RefCell::new(Prefix::from_scientific(&['n'], "1e-9", true)),
             RefCell::new(Prefix::from_scientific(&['p'], "1e-12", true)),
@@ -183,6 +188,7 @@ fn create_prefixes() -> UnitPrefixes {
             RefCell::new(Prefix::from_scientific(&['d'], "1e-2", false)),
             RefCell::new(Prefix::from_scientific(&['c'], "1e-4", false)),
             RefCell::new(Prefix::from_scientific(&['m'], "1e-6", true)),
+            RefCell::new(Prefix::from_scientific(&['µ'], "1e-12", true)),
             RefCell::new(Prefix::from_scientific(&['u'], "1e-12", true)),
             RefCell::new(Prefix::from_scientific(&['n'], "1e-18", true)),
             RefCell::new(Prefix::from_scientific(&['p'], "1e-24", true)),
@@ -207,6 +213,7 @@ fn create_prefixes() -> UnitPrefixes {
             RefCell::new(Prefix::from_scientific(&['d'], "1e-3", false)),
             RefCell::new(Prefix::from_scientific(&['c'], "1e-6", false)),
             RefCell::new(Prefix::from_scientific(&['m'], "1e-9", true)),
+            RefCell::new(Prefix::from_scientific(&['µ'], "1e-18", true)),
             RefCell::new(Prefix::from_scientific(&['u'], "1e-18", true)),
             RefCell::new(Prefix::from_scientific(&['n'], "1e-27", true)),
             // TODO
@@ -891,6 +898,16 @@ pub fn init_units() -> (HashMap<&'static str, RefCell<Unit>>, UnitPrefixes) {
             offset: Decimal::from_i64(0).unwrap(),
         },
     ); // 3.785412 L
+    map.insert(
+        "impgal",
+        Unit {
+            name: &['i', 'm', 'p', 'g', 'a', 'l'],
+            base: BASE_UNIT_DIMENSIONS[UnitType::Volume as usize],
+            prefix_groups: (None, None),
+            value: Decimal::from_str("0.00454609").unwrap(),
+            offset: Decimal::from_i64(0).unwrap(),
+        },
+    ); // 4.54609 L, imperial gallon
     map.insert(
         "bbl",
         Unit {
@@ -1293,6 +1310,27 @@ pub fn init_units() -> (HashMap<&'static str, RefCell<Unit>>, UnitPrefixes) {
             offset: Decimal::from_i64(0).unwrap(),
         },
     );
+    map.insert(
+        "°",
+        Unit {
+            name: &['°'],
+            base: BASE_UNIT_DIMENSIONS[UnitType::Angle as usize],
+            prefix_groups: (None, None),
+            value: &pi / &Decimal::from_isize(180).unwrap(),
+            offset: Decimal::from_i64(0).unwrap(),
+        },
+    );
+    // turn = 2 * pi rad
+    map.insert(
+        "turn",
+        Unit {
+            name: &['t', 'u', 'r', 'n'],
+            base: BASE_UNIT_DIMENSIONS[UnitType::Angle as usize],
+            prefix_groups: (None, None),
+            value: &pi * &Decimal::from_isize(2).unwrap(),
+            offset: Decimal::from_i64(0).unwrap(),
+        },
+    );
     // grad = rad / (2*pi) * 400  = rad / 0.015707963267948966192313216916399
     map.insert(
         "grad",
@@ -1314,6 +1352,49 @@ pub fn init_units() -> (HashMap<&'static str, RefCell<Unit>>, UnitPrefixes) {
             offset: Decimal::from_i64(0).unwrap(),
         },
     );
+    // Ratio
+    // they are dimensionless, so e.g. "5000 ppm" is just a number (0.005)
+    // but they can be the target of a conversion: "5000 ppm in %"
+    map.insert(
+        "%",
+        Unit {
+            name: &['%'],
+            base: EMPTY_UNIT_DIMENSIONS,
+            prefix_groups: (None, None),
+            value: Decimal::from_str("0.01").unwrap(),
+            offset: Decimal::from_i64(0).unwrap(),
+        },
+    );
+    map.insert(
+        "‰",
+        Unit {
+            name: &['‰'],
+            base: EMPTY_UNIT_DIMENSIONS,
+            prefix_groups: (None, None),
+            value: Decimal::from_str("0.001").unwrap(),
+            offset: Decimal::from_i64(0).unwrap(),
+        },
+    );
+    map.insert(
+        "ppm",
+        Unit {
+            name: &['p', 'p', 'm'],
+            base: EMPTY_UNIT_DIMENSIONS,
+            prefix_groups: (None, None),
+            value: Decimal::from_str("0.000001").unwrap(),
+            offset: Decimal::from_i64(0).unwrap(),
+        },
+    );
+    map.insert(
+        "ppb",
+        Unit {
+            name: &['p', 'p', 'b'],
+            base: EMPTY_UNIT_DIMENSIONS,
+            prefix_groups: (None, None),
+            value: Decimal::from_str("0.000000001").unwrap(),
+            offset: Decimal::from_i64(0).unwrap(),
+        },
+    );
     // cycle = rad / (2*pi) = rad / 6.2831853071795864769252867665793
     map.insert(
         "cycle",
@@ -1402,6 +1483,26 @@ pub fn init_units() -> (HashMap<&'static str, RefCell<Unit>>, UnitPrefixes) {
             offset: Decimal::from_str("459.67").unwrap(),
         },
     );
+    map.insert(
+        "°C",
+        Unit {
+            name: &['°', 'C'],
+            base: BASE_UNIT_DIMENSIONS[UnitType::Temperature as usize],
+            prefix_groups: (None, None),
+            value: Decimal::from_i64(1).unwrap(),
+            offset: Decimal::from_str("273.15").unwrap(),
+        },
+    );
+    map.insert(
+        "°F",
+        Unit {
+            name: &['°', 'F'],
+            base: BASE_UNIT_DIMENSIONS[UnitType::Temperature as usize],
+            prefix_groups: (None, None),
+            value: Decimal::one() / Decimal::from_str("1.8").unwrap(),
+            offset: Decimal::from_str("459.67").unwrap(),
+        },
+    );
     map.insert(
         "degR",
         Unit {
@@ -1,9 +1,10 @@
-use crate::calc::pow;
+use crate::calc::{dec, pow, sqrt};
 use crate::units::consts::{
     get_base_unit_for, init_aliases, init_units, UnitDimensionExponent, BASE_UNIT_DIMENSIONS,
     BASE_UNIT_DIMENSION_COUNT,
 };
 use crate::units::{Prefix, Unit, UnitPrefixes};
+use rust_decimal::prelude::{FromPrimitive, One, ToPrimitive, Zero};
 use rust_decimal::Decimal;
 use smallvec::alloc::fmt::{Debug, Display, Formatter};
 use smallvec::SmallVec;
@@ -18,6 +19,16 @@ fn next(str: &[char]) -> &[char] {
 }
 
 fn parse_unit(str: &[char]) -> Option<&[char]> {
+    if matches!(str.first(), Some('%') | Some('‰')) {
+        // units consisting of a single symbol
+        return Some(&str[0..1]);
+    }
+    if str.first() == Some(&'°') {
+        // "°C" and "°F" are temperatures, a lone '°' is an angle
+        let is_temperature = matches!(str.get(1), Some('C') | Some('F'))
+            && str.get(2).map(|it| !it.is_alphanumeric()).unwrap_or(true);
+        return Some(&str[0..if is_temperature { 2 } else { 1 }]);
+    }
     let mut i = 0;
     for ch in str {
         if !ch.is_alphanumeric() && *ch != '$' {
@@ -46,7 +57,55 @@ pub struct Units {
     pub no_prefix: RefCell<Prefix>,
 }
 
+/// A builtin unit, e.g. for listing the unit suggestions of an autocompletion.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnitInfo {
+    pub name: &'static str,
+    /// Other names of the unit, e.g. "meters" for "meter"
+    pub aliases: Vec<&'static str>,
+    /// The exponents of the base dimensions (mass, length, time etc.),
+    /// e.g. velocity is [0, 1, -1, 0...]
+    pub dimensions: [UnitDimensionExponent; BASE_UNIT_DIMENSION_COUNT],
+    /// The unit in which the quantities of the same dimensions are stored, e.g. "m" for "mile"
+    pub base_unit: Option<String>,
+}
+
 impl Units {
+    /// All the builtin units ordered by their names, prefixed units (e.g. "km") are not listed.
+    pub fn all_units(&self) -> Vec<UnitInfo> {
+        let mut infos: Vec<UnitInfo> = self
+            .units
+            .iter()
+            .map(|(&name, unit)| {
+                let dimensions = unit.borrow().base;
+                let mut aliases: Vec<&'static str> = self
+                    .aliases
+                    .iter()
+                    .filter(|(_alias, target)| **target == name)
+                    .map(|(alias, _target)| *alias)
+                    .collect();
+                aliases.sort();
+                UnitInfo {
+                    name,
+                    aliases,
+                    dimensions,
+                    base_unit: get_base_unit_for(self, &dimensions).map(|it| {
+                        let name: String = it
+                            .prefix
+                            .borrow()
+                            .name
+                            .iter()
+                            .chain(it.unit.borrow().name.iter())
+                            .collect();
+                        name
+                    }),
+                }
+            })
+            .collect();
+        infos.sort_by_key(|it| it.name);
+        return infos;
+    }
+
     pub fn new() -> Units {
         let (units, prefixes) = init_units();
         Units {
@@ -131,10 +190,11 @@ impl Units {
             };
             // Is there a "^ number"?
             c = skip_whitespaces(c);
+            let mut root = 1;
             if parse_char(&mut c, '^') {
                 c = skip_whitespaces(c);
-                let p = parse_number(&mut c);
-                if let Some(p) = p {
+                let p = parse_exponent(&mut c);
+                if let Some((p, p_root)) = p {
                     power = if let Some(mul) =
                         i8::try_from(p).ok().and_then(|b| power.checked_mul(b))
                     {
@@ -142,15 +202,20 @@ impl Units {
                     } else {
                         return (output, 0);
                     };
+                    root = if let Ok(p_root) = i8::try_from(p_root) {
+                        p_root
+                    } else {
+                        return (output, 0);
+                    };
                 } else {
                     // No valid number found for the power!
-                    if !output.add_unit(UnitInstance::new(res.0, res.1, power)) {
+                    if !output.add_unit_with_root(UnitInstance::new(res.0, res.1, power), 1) {
                         return (output, 0);
                     }
                     break 'main_loop;
                 }
             }
-            if !output.add_unit(UnitInstance::new(res.0, res.1, power)) {
+            if !output.add_unit_with_root(UnitInstance::new(res.0, res.1, power), root) {
                 return (output, 0);
             }
             // Add the unit to the list
@@ -201,6 +266,7 @@ impl Units {
         if last_valid_cursor_pos == 0 {
             output.units.clear();
         }
+        output.reduce();
         return (output, last_valid_cursor_pos);
     }
 
@@ -255,12 +321,25 @@ impl Units {
             }
             return None;
         }
+        // the map has no order, so if more prefix-unit combinations match
+        // (e.g. "kPa" could be "k" + "Pa" or "kP" + "a"), the longest unit name wins
+        let mut best_match: Option<(usize, (RefCell<Unit>, RefCell<Prefix>))> = None;
         for (unit_name, unit) in &self.units {
-            let result = check(self, str, unit, unit_name);
-            if result.is_some() {
-                return result;
+            let unit_name_len = unit_name.chars().count();
+            if best_match
+                .as_ref()
+                .map(|(len, _)| *len >= unit_name_len)
+                .unwrap_or(false)
+            {
+                continue;
+            }
+            if let Some(result) = check(self, str, unit, unit_name) {
+                best_match = Some((unit_name_len, result));
             }
         }
+        if let Some((_, result)) = best_match {
+            return Some(result);
+        }
         for (alias, unit_name) in &self.aliases {
             let unit = self.units.get(unit_name).expect(unit_name);
             let result = check(self, str, unit, alias);
@@ -272,6 +351,10 @@ impl Units {
     }
 
     pub fn simplify(&self, unit: &UnitOutput) -> Option<UnitOutput> {
+        if unit.root != 1 {
+            // the base units have integer exponents
+            return None;
+        }
         if let Some(base_unit) = get_base_unit_for(self, &unit.dimensions) {
             let dimensions = base_unit.unit.borrow().base;
             Some(UnitOutput {
@@ -281,12 +364,53 @@ impl Units {
                     power: 1,
                 }],
                 dimensions,
+                root: 1,
             })
         } else {
             None
         }
     }
 
+    /// The same unit with the SI prefix which keeps the displayed value of `num` (stored in
+    /// base unit) in [1, 1000), e.g. 1500 m is 1.5 km and 0.0005 s is 500 µs.
+    /// `None` for compound units and for the units which can't be prefixed.
+    pub fn auto_prefix(&self, num: &Decimal, unit: &UnitOutput) -> Option<UnitOutput> {
+        if unit.units.len() != 1 || unit.units[0].power != 1 || unit.root != 1 {
+            return None;
+        }
+        let prefixes = match &unit.units[0].unit.borrow().prefix_groups {
+            (Some(prefixes), _) => RefCell::clone(prefixes),
+            _ => return None,
+        };
+        let with_prefix = |prefix: &RefCell<Prefix>| UnitOutput {
+            units: vec![UnitInstance {
+                unit: RefCell::clone(&unit.units[0].unit),
+                prefix: RefCell::clone(prefix),
+                power: 1,
+            }],
+            ..unit.clone()
+        };
+        let value = with_prefix(&self.no_prefix)
+            .from_base_to_this_unit(num)?
+            .abs();
+        if value.is_zero() {
+            return None;
+        }
+        let thousand = dec(1000);
+        let prefixes = prefixes.borrow();
+        let best = prefixes
+            .iter()
+            .filter(|it| it.borrow().scientific)
+            .chain(std::iter::once(&self.no_prefix))
+            .find(|it| {
+                value
+                    .checked_div(&it.borrow().value)
+                    .map(|it| it >= Decimal::one() && it < thousand)
+                    .unwrap_or(false)
+            })?;
+        Some(with_prefix(best))
+    }
+
     fn find_prefix_for(unit: &Unit, prefix_name: &[char]) -> Option<RefCell<Prefix>> {
         match &unit.prefix_groups {
             (Some(p1), Some(p2)) => p1
@@ -330,6 +454,38 @@ fn parse_number(text: &mut &[char]) -> Option<isize> {
     };
 }
 
+/// The exponent as a reduced fraction, e.g. "2" is (2, 1), "-0.5" is (-1, 2)
+fn parse_exponent(text: &mut &[char]) -> Option<(isize, isize)> {
+    let negative = text.first() == Some(&'-');
+    let mut numerator = parse_number(text)?.abs();
+    let mut denominator: isize = 1;
+    if text.len() > 1 && text[0] == '.' && text[1].is_ascii_digit() {
+        *text = &text[1..];
+        while !text.is_empty() && text[0].is_ascii_digit() {
+            let digit = text[0].to_digit(10)? as isize;
+            numerator = numerator.checked_mul(10)?.checked_add(digit)?;
+            denominator = denominator.checked_mul(10)?;
+            *text = &text[1..];
+        }
+    }
+    let divisor = gcd(numerator, denominator);
+    let numerator = numerator / divisor;
+    Some((
+        if negative { -numerator } else { numerator },
+        denominator / divisor,
+    ))
+}
+
+fn gcd(a: isize, b: isize) -> isize {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        let t = a % b;
+        a = b;
+        b = t;
+    }
+    a
+}
+
 fn parse_char(c: &mut &[char], ch: char) -> bool {
     return if c.is_empty() {
         false
@@ -351,6 +507,9 @@ pub struct UnitOutput {
     // TOOD: replace it with a fixed array Some None?
     pub units: Vec<UnitInstance>,
     pub dimensions: [i8; BASE_UNIT_DIMENSION_COUNT],
+    /// The powers in `units` and `dimensions` are divided by it, so fractional
+    /// exponents can be represented, e.g. "s^0.5" is "s^1" with a root of 2
+    pub root: UnitDimensionExponent,
 }
 
 impl Debug for UnitOutput {
@@ -366,15 +525,25 @@ impl Display for UnitOutput {
         let mut str_num: SmallVec<[char; 32]> = SmallVec::with_capacity(32);
         let mut str_den: SmallVec<[char; 32]> = SmallVec::with_capacity(32);
 
-        for unit in self.units.iter() {
+        // canonical order, so e.g. "m*kg" and "kg*m" are rendered the same way:
+        // positive exponents first, then by exponent, alphabetical within
+        let mut sorted_units: SmallVec<[&UnitInstance; 8]> = self.units.iter().collect();
+        sorted_units.sort_by_key(|unit| {
+            let mut name: SmallVec<[char; 16]> = SmallVec::new();
+            name.extend_from_slice(unit.prefix.borrow().name);
+            name.extend_from_slice(unit.unit.borrow().name);
+            (unit.power < 0, unit.power, name)
+        });
+
+        for unit in sorted_units.iter() {
             if unit.power > 0 {
                 nnum += 1;
                 str_num.push(' ');
                 str_num.extend_from_slice(unit.prefix.borrow().name);
                 str_num.extend_from_slice(unit.unit.borrow().name);
-                if (unit.power as f64 - 1.0).abs() > 1e-15 {
+                if unit.power != self.root {
                     str_num.push('^');
-                    str_num.extend(unit.power.to_string().chars());
+                    str_num.extend(self.exponent_to_string(unit.power).chars());
                 }
             } else {
                 nden += 1;
@@ -382,22 +551,22 @@ impl Display for UnitOutput {
         }
 
         if nden > 0 {
-            for unit in self.units.iter() {
+            for unit in sorted_units.iter() {
                 if unit.power < 0 {
                     if nnum > 0 {
                         str_den.push(' ');
                         str_den.extend_from_slice(unit.prefix.borrow().name);
                         str_den.extend_from_slice(unit.unit.borrow().name);
-                        if (unit.power as f64 + 1.0).abs() > 1e-15 {
+                        if -unit.power != self.root {
                             str_den.push('^');
-                            str_den.extend((-unit.power).to_string().chars());
+                            str_den.extend(self.exponent_to_string(-unit.power).chars());
                         }
                     } else {
                         str_den.push(' ');
                         str_den.extend_from_slice(unit.prefix.borrow().name);
                         str_den.extend_from_slice(unit.unit.borrow().name);
                         str_den.push('^');
-                        str_den.extend(unit.power.to_string().chars());
+                        str_den.extend(self.exponent_to_string(unit.power).chars());
                     }
                 }
             }
@@ -440,9 +609,125 @@ impl UnitOutput {
         UnitOutput {
             units: vec![],
             dimensions: [0; BASE_UNIT_DIMENSION_COUNT],
+            root: 1,
+        }
+    }
+
+    /// Adds a unit whose power has to be divided by `root`
+    #[must_use]
+    pub fn add_unit_with_root(
+        &mut self,
+        mut unit: UnitInstance,
+        root: UnitDimensionExponent,
+    ) -> bool {
+        if !self.expand_root(root) {
+            return false;
+        }
+        if let Some(power) = unit.power.checked_mul(self.root / root) {
+            unit.power = power;
+            self.add_unit(unit)
+        } else {
+            false
         }
     }
 
+    /// Changes the root to the least common multiple of the current one and `root`,
+    /// the powers are scaled accordingly
+    #[must_use]
+    fn expand_root(&mut self, root: UnitDimensionExponent) -> bool {
+        let divisor = gcd(self.root as isize, root as isize) as UnitDimensionExponent;
+        let factor = root / divisor;
+        if factor == 1 {
+            return true;
+        }
+        if let Some(new_root) = self.root.checked_mul(factor) {
+            self.root = new_root;
+        } else {
+            return false;
+        }
+        for dim in &mut self.dimensions {
+            if let Some(new_dim) = dim.checked_mul(factor) {
+                *dim = new_dim;
+            } else {
+                return false;
+            }
+        }
+        for unit in &mut self.units {
+            if let Some(new_power) = unit.power.checked_mul(factor) {
+                unit.power = new_power;
+            } else {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    /// Brings the fractional exponents to their simplest form, e.g. "s^0.5 * s^0.5" to "s"
+    fn reduce(&mut self) {
+        if self.root == 1 {
+            return;
+        }
+        // merge the same units
+        let mut merged: Vec<UnitInstance> = Vec::with_capacity(self.units.len());
+        for unit in self.units.drain(..) {
+            if let Some(existing) = merged.iter_mut().find(|it| {
+                it.unit.borrow().name == unit.unit.borrow().name
+                    && it.prefix.borrow().name == unit.prefix.borrow().name
+            }) {
+                existing.power += unit.power;
+            } else {
+                merged.push(unit);
+            }
+        }
+        merged.retain(|it| it.power != 0);
+        self.units = merged;
+
+        let mut divisor = self.root as isize;
+        for dim in &self.dimensions {
+            divisor = gcd(divisor, *dim as isize);
+        }
+        for unit in &self.units {
+            divisor = gcd(divisor, unit.power as isize);
+        }
+        let divisor = divisor as UnitDimensionExponent;
+        if divisor > 1 {
+            self.root /= divisor;
+            for dim in &mut self.dimensions {
+                *dim /= divisor;
+            }
+            for unit in &mut self.units {
+                unit.power /= divisor;
+            }
+        }
+    }
+
+    fn exponent_to_string(&self, power: UnitDimensionExponent) -> String {
+        if self.root == 1 {
+            power.to_string()
+        } else {
+            // the root is always a divisor of a power of 10, so it is a finite decimal
+            (dec(power as i64) / dec(self.root as i64))
+                .normalize()
+                .to_string()
+        }
+    }
+
+    /// The n-th root of the unit, e.g. the square root of "m^2" is "m".
+    /// Returns None if the exponents could not be rendered as finite decimals, e.g. "m^(1/3)"
+    pub fn root(&self, n: UnitDimensionExponent) -> Option<UnitOutput> {
+        let mut result = self.clone();
+        result.root = result.root.checked_mul(n)?;
+        result.reduce();
+        let mut rest = result.root;
+        while rest % 2 == 0 {
+            rest /= 2;
+        }
+        while rest % 5 == 0 {
+            rest /= 5;
+        }
+        return if rest == 1 { Some(result) } else { None };
+    }
+
     #[must_use]
     pub fn add_unit(&mut self, unit: UnitInstance) -> bool {
         for i in 0..BASE_UNIT_DIMENSION_COUNT {
@@ -502,42 +787,49 @@ impl UnitOutput {
     }
 }
 
+/// The simplest form of `unit`, e.g. `m/m` becomes dimensionless and `kg*m/s^2` becomes `N`.
+/// If there is nothing to simplify, the unit is returned as it is.
+pub fn simplify(unit: &UnitOutput, units: &Units) -> UnitOutput {
+    unit.simplify(units).unwrap_or_else(|| unit.clone())
+}
+
 impl std::ops::Mul for &UnitOutput {
-    type Output = UnitOutput;
+    type Output = Option<UnitOutput>;
 
     fn mul(self, other: Self) -> Self::Output {
         let mut result = self.clone();
+        let mut other = other.clone();
+        // the exponents must have a common root, it can overflow only with absurd exponents
+        if !(result.expand_root(other.root) && other.expand_root(result.root)) {
+            return None;
+        }
 
-        for (i, (this_dim, other_dim)) in self
-            .dimensions
-            .iter()
-            .zip(other.dimensions.iter())
-            .enumerate()
-        {
-            result.dimensions[i] = this_dim + other_dim;
+        for (i, other_dim) in other.dimensions.iter().enumerate() {
+            result.dimensions[i] = result.dimensions[i].checked_add(*other_dim)?;
         }
 
         for other_unit in &other.units {
             result.units.push(other_unit.clone());
         }
+        result.reduce();
 
-        return result;
+        return Some(result);
     }
 }
 
 impl std::ops::Div for &UnitOutput {
-    type Output = UnitOutput;
+    type Output = Option<UnitOutput>;
 
     fn div(self, other: Self) -> Self::Output {
         let mut result = self.clone();
+        let mut other = other.clone();
+        // the exponents must have a common root, it can overflow only with absurd exponents
+        if !(result.expand_root(other.root) && other.expand_root(result.root)) {
+            return None;
+        }
 
-        for (i, (this_dim, other_dim)) in self
-            .dimensions
-            .iter()
-            .zip(other.dimensions.iter())
-            .enumerate()
-        {
-            result.dimensions[i] = this_dim - other_dim;
+        for (i, other_dim) in other.dimensions.iter().enumerate() {
+            result.dimensions[i] = result.dimensions[i].checked_sub(*other_dim)?;
         }
 
         for other_unit in &other.units {
@@ -545,16 +837,17 @@ impl std::ops::Div for &UnitOutput {
             clone.power = -clone.power;
             result.units.push(clone);
         }
+        result.reduce();
 
-        return result;
+        return Some(result);
     }
 }
 
 impl PartialEq for UnitOutput {
     fn eq(&self, other: &Self) -> bool {
-        // All dimensions must be the same
+        // All dimensions must be the same, the roots can be different
         for (a, b) in self.dimensions.iter().zip(other.dimensions.iter()) {
-            if a != b {
+            if *a as i32 * other.root as i32 != *b as i32 * self.root as i32 {
                 return false;
             }
         }
@@ -567,11 +860,7 @@ impl UnitOutput {
         if self.is_derived() {
             let mut result = value.clone();
             for unit in &self.units {
-                let base_value = &unit.unit.borrow().value;
-                let prefix_val = &unit.prefix.borrow().value;
-                let power = unit.power;
-
-                result = result.checked_mul(&pow(base_value * prefix_val, power as i64)?)?;
+                result = result.checked_mul(&self.unit_factor(unit)?)?;
             }
             return Some(result);
         } else {
@@ -579,8 +868,8 @@ impl UnitOutput {
             let offset = &self.units[0].unit.borrow().offset;
             let prefix_val = &self.units[0].prefix.borrow().value;
 
-            let a = value + offset;
-            let b = base_value * prefix_val;
+            let a = value.checked_add(offset)?;
+            let b = base_value.checked_mul(prefix_val)?;
             return a.checked_mul(&b);
         }
     }
@@ -589,11 +878,7 @@ impl UnitOutput {
         return if self.is_derived() {
             let mut result = value.clone();
             for unit in &self.units {
-                let base_value = &unit.unit.borrow().value;
-                let prefix_val = &unit.prefix.borrow().value;
-                let power = unit.power;
-                let pow = pow(base_value.checked_mul(prefix_val)?, power as i64)?;
-                result = result.checked_div(&pow)?;
+                result = result.checked_div(&self.unit_factor(unit)?)?;
             }
             Some(result)
         } else {
@@ -619,6 +904,21 @@ impl UnitOutput {
         };
     }
 
+    /// The value of the unit in its base unit, e.g. 1000 for km^1 or ~0.0316 for ms^0.5
+    fn unit_factor(&self, unit: &UnitInstance) -> Option<Decimal> {
+        let base_value = &unit.unit.borrow().value;
+        let prefix_val = &unit.prefix.borrow().value;
+        let powered = pow(base_value.checked_mul(prefix_val)?, unit.power as i64)?;
+        match self.root {
+            1 => Some(powered),
+            2 => sqrt(&powered),
+            root => powered
+                .to_f64()
+                .map(|it| it.powf(1.0 / root as f64))
+                .and_then(Decimal::from_f64),
+        }
+    }
+
     pub fn pow(&self, p: i64) -> Option<UnitOutput> {
         let mut result = self.clone();
         let p = i8::try_from(p).ok()?;
@@ -628,12 +928,13 @@ impl UnitOutput {
         for unit in &mut result.units {
             unit.power = unit.power.checked_mul(p)?;
         }
+        result.reduce();
 
         return Some(result);
     }
 
     pub fn is_derived(&self) -> bool {
-        self.units.len() > 1 || (self.units.len() == 1 && self.units[0].power > 1)
+        self.units.len() > 1 || (self.units.len() == 1 && self.units[0].power > 1) || self.root != 1
     }
 }
 
@@ -680,6 +981,146 @@ mod tests {
         units.parse(&str.chars().collect::<Vec<char>>()).0
     }
 
+    #[test]
+    fn test_prefixed_derived_units() {
+        let units = Units::new();
+        for (text, prefix, unit) in &[
+            ("kN", "k", "N"),
+            ("MHz", "M", "Hz"),
+            ("mJ", "m", "J"),
+            ("GPa", "G", "Pa"),
+            ("kPa", "k", "Pa"),
+            ("MW", "M", "W"),
+        ] {
+            let parsed = parse(text, &units);
+            assert_eq!(1, parsed.units.len(), "{}", text);
+            assert_eq!(
+                *prefix,
+                parsed.units[0]
+                    .prefix
+                    .borrow()
+                    .name
+                    .iter()
+                    .collect::<String>(),
+                "{}",
+                text
+            );
+            assert_eq!(
+                *unit,
+                parsed.units[0]
+                    .unit
+                    .borrow()
+                    .name
+                    .iter()
+                    .collect::<String>(),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn test_all_units() {
+        let units = Units::new();
+        let all_units = units.all_units();
+        let find = |name: &str| all_units.iter().find(|it| it.name == name).unwrap();
+
+        let meter = find("m");
+        assert_eq!(meter.dimensions, [0, 1, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(meter.base_unit.as_deref(), Some("m"));
+
+        // "kg" is the prefixed "g"
+        let gram = find("g");
+        assert_eq!(gram.dimensions, [1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(gram.base_unit.as_deref(), Some("kg"));
+
+        let second = find("s");
+        assert_eq!(second.dimensions, [0, 0, 1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(second.base_unit.as_deref(), Some("s"));
+
+        assert!(find("meter").aliases.contains(&"meters"));
+        assert_eq!(find("mile").base_unit.as_deref(), Some("m"));
+        assert!(all_units.iter().all(|it| it.name != "km"));
+    }
+
+    #[test]
+    fn test_fractional_exponents() {
+        let units = Units::new();
+
+        let unit = parse("s^0.5", &units);
+        assert_eq!(1, unit.units.len());
+        assert_eq!(1, unit.units[0].power);
+        assert_eq!(2, unit.root);
+        assert_eq!("s^0.5", unit.to_string());
+
+        assert_eq!("m^1.5", parse("m^1.5", &units).to_string());
+        assert_eq!("m^-0.25", parse("m^-0.25", &units).to_string());
+        // it is reduced to the simplest form
+        assert_eq!("s", parse("s^1.0", &units).to_string());
+
+        let product = (&parse("s^0.5", &units) * &parse("s^0.5", &units)).unwrap();
+        assert_eq!(parse("s", &units), product);
+        assert_eq!("s", product.to_string());
+        assert_eq!(1, product.root);
+        // the common root of the exponents would overflow
+        let mut a = parse("s", &units);
+        a.root = 11;
+        let mut b = parse("m", &units);
+        b.root = 13;
+        assert!((&a * &b).is_none());
+        assert!((&a / &b).is_none());
+        // the common root fits but the sum of the scaled dimensions would overflow
+        let mut a = parse("s", &units);
+        a.root = 2;
+        a.dimensions = [100; BASE_UNIT_DIMENSION_COUNT];
+        let mut b = parse("s", &units);
+        b.dimensions = [30; BASE_UNIT_DIMENSION_COUNT];
+        assert!((&a * &b).is_none());
+        b.dimensions = [-30; BASE_UNIT_DIMENSION_COUNT];
+        assert!((&a / &b).is_none());
+
+        assert_eq!(parse("m", &units), parse("m^2", &units).root(2).unwrap());
+        assert!(parse("m^0.5", &units) != parse("m", &units));
+    }
+
+    #[test]
+    fn test_simplify() {
+        let units = Units::new();
+
+        let cancelled = simplify(&parse("m/m", &units), &units);
+        assert!(cancelled.is_unitless());
+        assert!(cancelled.units.is_empty());
+
+        assert_eq!(
+            simplify(&parse("kg*m/s^2", &units), &units).to_string(),
+            "N"
+        );
+        assert_eq!(
+            simplify(&parse("(kg m^2)/s^2", &units), &units).to_string(),
+            "J"
+        );
+        // already simple
+        assert_eq!(simplify(&parse("km", &units), &units).to_string(), "km");
+        assert_eq!(
+            simplify(&parse("km/h", &units), &units).to_string(),
+            "km / h"
+        );
+    }
+
+    #[test]
+    fn test_display_order_is_canonical() {
+        let units = Units::new();
+        assert_eq!(
+            parse("m*kg", &units).to_string(),
+            parse("kg*m", &units).to_string()
+        );
+        assert_eq!(parse("s*m/K/mol", &units).to_string(), "(m s) / (K mol)");
+        assert_eq!(
+            parse("mol^-1*m^2/s^2*kg", &units).to_string(),
+            "(kg m^2) / (s^2 mol)"
+        );
+    }
+
     #[test]
     fn should_create_unit_correctly() {
         let units = Units::new();